@@ -7,7 +7,11 @@ use cypher_indexer::{
     },
     storage::{ipfs::IpfsStorage, Storage},
 };
-use solana_sdk::{instruction::CompiledInstruction, pubkey::Pubkey, transaction::Transaction};
+use solana_sdk::{
+    instruction::{AccountMeta, CompiledInstruction},
+    pubkey::Pubkey,
+    transaction::Transaction,
+};
 use solana_transaction_status::{EncodedConfirmedTransaction, UiConfirmedBlock};
 use std::collections::HashMap;
 use tokio::runtime::Runtime;
@@ -135,11 +139,17 @@ fn benchmark_instruction_parser(c: &mut Criterion) {
         accounts: vec![0, 1, 2],
         data: vec![0u8; 32],
     };
+    let mock_accounts: Vec<AccountMeta> = (0..3)
+        .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+        .collect();
 
     c.bench_function("InstructionParser::parse_instruction", |b| {
         b.iter(|| {
-            instruction_parser
-                .parse_instruction(black_box(&mock_program_id), black_box(&mock_instruction))
+            instruction_parser.parse_instruction(
+                black_box(&mock_program_id),
+                black_box(&mock_instruction),
+                black_box(&mock_accounts),
+            )
         })
     });
 }
@@ -1,8 +1,28 @@
-use async_graphql::{Context, Object, Schema, EmptyMutation, EmptySubscription, SimpleObject, ID};
-use crate::storage::Storage;
+use async_graphql::{Context, Enum, Object, Schema, EmptyMutation, EmptySubscription, SimpleObject, ID};
+use crate::ingestion::RPC_POLLER_CHECKPOINT;
+use crate::processing::call_tree::InstructionNode;
+use crate::storage::{Commitment, Storage};
 
 struct Query;
 
+/// Minimum commitment of returned data.
+#[derive(Enum, Copy, Clone, Eq, PartialEq)]
+enum CommitmentLevel {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl From<Option<CommitmentLevel>> for Commitment {
+    fn from(level: Option<CommitmentLevel>) -> Self {
+        match level {
+            None | Some(CommitmentLevel::Processed) => Commitment::Processed,
+            Some(CommitmentLevel::Confirmed) => Commitment::Confirmed,
+            Some(CommitmentLevel::Finalized) => Commitment::Finalized,
+        }
+    }
+}
+
 #[derive(SimpleObject)]
 struct Account {
     pubkey: ID,
@@ -21,21 +41,59 @@ struct Transaction {
     logs: Vec<String>,
 }
 
+/// An instruction and the instructions it invoked through CPI.
+#[derive(SimpleObject)]
+struct Instruction {
+    program_id: ID,
+    instruction_type: String,
+    data: String,
+    outer_index: i32,
+    inner_index: Option<i32>,
+    stack_height: i32,
+    children: Vec<Instruction>,
+}
+
+impl From<InstructionNode> for Instruction {
+    fn from(node: InstructionNode) -> Self {
+        Self {
+            program_id: node.program_id.into(),
+            instruction_type: node.instruction_type,
+            data: node.data,
+            outer_index: node.outer_index.into(),
+            inner_index: node.inner_index.map(Into::into),
+            stack_height: node.stack_height as i32,
+            children: node.children.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
 #[Object]
 impl Query {
-    async fn get_account(&self, ctx: &Context<'_>, pubkey: ID) -> async_graphql::Result<Option<Account>> {
+    async fn get_account(&self, ctx: &Context<'_>, pubkey: ID, commitment: Option<CommitmentLevel>) -> async_graphql::Result<Option<Account>> {
+        let storage = ctx.data::<Storage>()?;
+        storage.get_account(pubkey.as_str(), commitment.into()).await.map_err(|e| e.into())
+    }
+
+    async fn get_transaction(&self, ctx: &Context<'_>, signature: ID, commitment: Option<CommitmentLevel>) -> async_graphql::Result<Option<Transaction>> {
+        let storage = ctx.data::<Storage>()?;
+        storage.get_transaction(signature.as_str(), commitment.into()).await.map_err(|e| e.into())
+    }
+
+    async fn get_instruction_tree(&self, ctx: &Context<'_>, signature: ID, commitment: Option<CommitmentLevel>) -> async_graphql::Result<Vec<Instruction>> {
         let storage = ctx.data::<Storage>()?;
-        storage.get_account(pubkey.as_str()).await.map_err(|e| e.into())
+        let tree = storage.get_instruction_tree(signature.as_str(), commitment.into()).await?;
+        Ok(tree.into_iter().map(Into::into).collect())
     }
 
-    async fn get_transaction(&self, ctx: &Context<'_>, signature: ID) -> async_graphql::Result<Option<Transaction>> {
+    async fn get_checkpoint(&self, ctx: &Context<'_>, name: Option<String>) -> async_graphql::Result<Option<u64>> {
         let storage = ctx.data::<Storage>()?;
-        storage.get_transaction(signature.as_str()).await.map_err(|e| e.into())
+        let name = name.unwrap_or_else(|| RPC_POLLER_CHECKPOINT.to_string());
+        storage.get_checkpoint(&name).await.map_err(|e| e.into())
     }
 
-    async fn get_transactions_by_account(&self, ctx: &Context<'_>, pubkey: ID, limit: i32) -> async_graphql::Result<Vec<Transaction>> {
+    async fn get_transactions_by_account(&self, ctx: &Context<'_>, pubkey: ID, limit: i32, commitment: Option<CommitmentLevel>) -> async_graphql::Result<Vec<Transaction>> {
         let storage = ctx.data::<Storage>()?;
-        storage.get_transactions_by_account(pubkey.as_str(), limit).await.map_err(|e| e.into())
+        storage.get_transactions_by_account(pubkey.as_str(), limit, commitment.into()).await.map_err(|e| e.into())
     }
 }
 
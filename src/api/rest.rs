@@ -1,5 +1,6 @@
 use warp::{Filter, Rejection, Reply};
-use crate::storage::Storage;
+use crate::ingestion::RPC_POLLER_CHECKPOINT;
+use crate::storage::{Commitment, Storage};
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize)]
@@ -11,11 +12,25 @@ struct ApiResponse<T> {
 #[derive(Deserialize)]
 struct AccountQuery {
     pubkey: String,
+    /// Minimum commitment of the returned row; any row by default.
+    commitment: Option<Commitment>,
 }
 
 #[derive(Deserialize)]
 struct TransactionQuery {
     signature: String,
+    commitment: Option<Commitment>,
+}
+
+#[derive(Deserialize)]
+struct CheckpointQuery {
+    name: Option<String>,
+}
+
+#[derive(Serialize)]
+struct Checkpoint {
+    name: String,
+    slot: Option<u64>,
 }
 
 pub fn routes(
@@ -35,11 +50,25 @@ pub fn routes(
         .and(storage.clone())
         .and_then(get_transaction);
 
-    account.or(transaction)
+    let instructions = warp::path("instructions")
+        .and(warp::get())
+        .and(warp::query::<TransactionQuery>())
+        .and(storage.clone())
+        .and_then(get_instruction_tree);
+
+    let checkpoint = warp::path("checkpoint")
+        .and(warp::get())
+        .and(warp::query::<CheckpointQuery>())
+        .and(storage.clone())
+        .and_then(get_checkpoint);
+
+    account.or(transaction).or(instructions).or(checkpoint)
 }
 
 async fn get_account(query: AccountQuery, storage: Storage) -> Result<impl Reply, Rejection> {
-    let account = storage.get_account(&query.pubkey).await.map_err(|e| warp::reject::custom(e))?;
+    let account = storage
+        .get_account(&query.pubkey, query.commitment.unwrap_or(Commitment::Processed))
+        .await.map_err(|e| warp::reject::custom(e))?;
     Ok(warp::reply::json(&ApiResponse {
         status: "success".to_string(),
         data: account,
@@ -47,9 +76,30 @@ async fn get_account(query: AccountQuery, storage: Storage) -> Result<impl Reply
 }
 
 async fn get_transaction(query: TransactionQuery, storage: Storage) -> Result<impl Reply, Rejection> {
-    let transaction = storage.get_transaction(&query.signature).await.map_err(|e| warp::reject::custom(e))?;
+    let transaction = storage
+        .get_transaction(&query.signature, query.commitment.unwrap_or(Commitment::Processed))
+        .await.map_err(|e| warp::reject::custom(e))?;
     Ok(warp::reply::json(&ApiResponse {
         status: "success".to_string(),
         data: transaction,
     }))
-}
\ No newline at end of file
+}
+
+async fn get_instruction_tree(query: TransactionQuery, storage: Storage) -> Result<impl Reply, Rejection> {
+    let tree = storage
+        .get_instruction_tree(&query.signature, query.commitment.unwrap_or(Commitment::Processed))
+        .await.map_err(|e| warp::reject::custom(e))?;
+    Ok(warp::reply::json(&ApiResponse {
+        status: "success".to_string(),
+        data: tree,
+    }))
+}
+
+async fn get_checkpoint(query: CheckpointQuery, storage: Storage) -> Result<impl Reply, Rejection> {
+    let name = query.name.unwrap_or_else(|| RPC_POLLER_CHECKPOINT.to_string());
+    let slot = storage.get_checkpoint(&name).await.map_err(|e| warp::reject::custom(e))?;
+    Ok(warp::reply::json(&ApiResponse {
+        status: "success".to_string(),
+        data: Checkpoint { name, slot },
+    }))
+}
@@ -1,7 +1,8 @@
 use serde::Deserialize;
 use std::fs;
+use std::path::Path;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Config {
     pub solana_rpc_url: String,
     pub clickhouse_url: String,
@@ -10,7 +11,32 @@ pub struct Config {
     pub wasm_module_path: String,
     pub geyser_plugin_config: GeyserPluginConfig,
     pub rpc_poll_interval: u64,
+    #[serde(default)]
+    pub rpc_poller: RpcPollerConfig,
     pub websocket_url: String,
+    /// RPC/WebSocket providers to spread load across. When empty,
+    /// `solana_rpc_url` and `websocket_url` are used as the only endpoint.
+    #[serde(default)]
+    pub rpc_endpoints: Vec<RpcEndpointConfig>,
+    #[serde(default = "default_health_check_interval_secs")]
+    pub rpc_health_check_interval_secs: u64,
+    #[serde(default)]
+    pub websocket: WebsocketConfig,
+    #[serde(default)]
+    pub account_subscriptions: AccountSubscriptionConfig,
+    #[serde(default)]
+    pub dedup: DedupConfig,
+    /// Yellowstone gRPC stream to ingest from, if any.
+    #[serde(default)]
+    pub grpc: Option<GrpcSourceConfig>,
+    /// File every processed event is appended to, for later replay.
+    #[serde(default)]
+    pub recording: Option<RecordingConfig>,
+    /// Ledger-archive Bigtable instance read by `backfill --source bigtable`.
+    #[serde(default)]
+    pub ledger_archive: LedgerArchiveConfig,
+    #[serde(default)]
+    pub lookup_tables: LookupTableConfig,
     pub wasm_modules: Option<_>,
     pub wasm_memory_limit: Option<i32>,
     pub wasm_execution_timeout: Option<i32>,
@@ -18,21 +44,375 @@ pub struct Config {
     pub bigtable_app_profile_id: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct RpcPollerConfig {
+    /// Blocks fetched in parallel; results are still processed in slot order.
+    pub max_concurrent_requests: usize,
+    pub requests_per_second: u32,
+    pub burst: u32,
+    pub max_retries: u32,
+    pub initial_backoff_ms: u64,
+    /// Only fetch slots the WebSocket listener reports as missed instead of
+    /// following the tip.
+    pub gap_fill_only: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RpcEndpointConfig {
+    pub rpc_url: String,
+    pub websocket_url: Option<String>,
+    /// Per-endpoint rate limit; defaults to `rpc_poller.requests_per_second`.
+    pub requests_per_second: Option<u32>,
+    pub burst: Option<u32>,
+    /// Relative share of traffic, scaled down by observed latency.
+    pub weight: Option<f64>,
+}
+
+impl Default for RpcPollerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: 8,
+            requests_per_second: 20,
+            burst: 20,
+            max_retries: 5,
+            initial_backoff_ms: 250,
+            gap_fill_only: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WebsocketConfig {
+    /// Programs to follow, one `logsSubscribe` each. Empty subscribes to all
+    /// transactions.
+    pub program_ids: Vec<String>,
+    /// Signatures are fetched once this many are pending or the interval
+    /// elapses, whichever comes first.
+    pub transaction_batch_size: usize,
+    pub transaction_batch_interval_ms: u64,
+    /// Run the push-based block source on `blockSubscribe` and slot updates.
+    pub subscribe_blocks: bool,
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    /// Reconnect when no notification arrives for this long.
+    pub idle_timeout_secs: u64,
+}
+
+/// Accounts followed over `accountSubscribe` and programs over
+/// `programSubscribe`, for indexing account state without a validator.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AccountSubscriptionConfig {
+    pub accounts: Vec<String>,
+    pub programs: Vec<ProgramSubscriptionConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProgramSubscriptionConfig {
+    pub program_id: String,
+    #[serde(default)]
+    pub filters: Vec<AccountFilter>,
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        Self {
+            program_ids: Vec::new(),
+            transaction_batch_size: 50,
+            transaction_batch_interval_ms: 200,
+            subscribe_blocks: false,
+            initial_backoff_ms: 500,
+            max_backoff_ms: 30_000,
+            idle_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct GeyserPluginConfig {
     pub libpath: String,
     pub accounts_selector: AccountsSelector,
+    #[serde(default)]
+    pub transaction_selector: Option<TransactionSelector>,
+    #[serde(default)]
+    pub output: Option<GeyserOutputConfig>,
+    #[serde(default)]
+    pub event_queue: EventQueueConfig,
+    /// Number of `is_startup` account updates written per bulk insert.
+    #[serde(default = "default_startup_batch_size")]
+    pub startup_batch_size: usize,
+}
+
+/// How long transactions and account updates are remembered to drop copies
+/// delivered by more than one ingestion source.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DedupConfig {
+    pub enabled: bool,
+    pub window_slots: u64,
+    pub ttl_secs: u64,
+}
+
+impl Default for DedupConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            window_slots: 300,
+            ttl_secs: 300,
+        }
+    }
+}
+
+/// A Yellowstone (Geyser gRPC) endpoint. The selectors are the same as the
+/// Geyser plugin's and are applied by the server.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GrpcSourceConfig {
+    pub endpoint: String,
+    pub x_token: Option<String>,
+    #[serde(default)]
+    pub commitment: GrpcCommitment,
+    #[serde(default)]
+    pub accounts_selector: AccountsSelector,
+    #[serde(default)]
+    pub transaction_selector: Option<TransactionSelector>,
+    #[serde(default = "default_true")]
+    pub slots: bool,
+    /// Full blocks, including their transactions.
+    #[serde(default)]
+    pub blocks: bool,
+    #[serde(default = "default_true")]
+    pub blocks_meta: bool,
+    #[serde(default)]
+    pub entries: bool,
+    #[serde(default = "default_grpc_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_grpc_max_backoff_ms")]
+    pub max_backoff_ms: u64,
+    /// How often to ping the server. A ping still unanswered when the next
+    /// is due drops the connection.
+    #[serde(default = "default_grpc_ping_interval_secs")]
+    pub ping_interval_secs: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GrpcCommitment {
+    Processed,
+    #[default]
+    Confirmed,
+    Finalized,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LedgerArchiveConfig {
+    pub instance_name: String,
+    pub app_profile_id: String,
+    /// Service account key; `GOOGLE_APPLICATION_CREDENTIALS` when unset.
+    pub credential_path: Option<String>,
+    pub timeout_secs: Option<u64>,
+    /// Blocks read per request.
+    pub batch_size: usize,
+}
+
+impl Default for LedgerArchiveConfig {
+    fn default() -> Self {
+        Self {
+            instance_name: "solana-ledger".to_string(),
+            app_profile_id: "default".to_string(),
+            credential_path: None,
+            timeout_secs: Some(30),
+            batch_size: 100,
+        }
+    }
+}
+
+/// Address lookup tables fetched over RPC for v0 transactions whose meta
+/// lacks `loadedAddresses`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LookupTableConfig {
+    pub fetch_missing: bool,
+    pub max_cached_tables: usize,
+}
+
+impl Default for LookupTableConfig {
+    fn default() -> Self {
+        Self {
+            fetch_missing: true,
+            max_cached_tables: 10_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecordingConfig {
+    pub path: String,
+    /// Inferred from the file extension when unset.
+    #[serde(default)]
+    pub format: Option<RecordingFormat>,
+}
+
+/// `Jsonl` writes one JSON event per line; `Bincode` writes bincode frames
+/// prefixed with their `u32` little-endian length.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordingFormat {
+    Jsonl,
+    Bincode,
+}
+
+impl RecordingFormat {
+    /// `.bin` and `.bincode` files are bincode; anything else is JSON lines.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("bin") | Some("bincode") => RecordingFormat::Bincode,
+            _ => RecordingFormat::Jsonl,
+        }
+    }
+}
+
+impl std::str::FromStr for RecordingFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(RecordingFormat::Jsonl),
+            "bincode" => Ok(RecordingFormat::Bincode),
+            _ => Err(format!("unknown recording format: {}", s)),
+        }
+    }
+}
+
+/// Accounts are selected when their pubkey is in `accounts` or their owner is
+/// in `owners`, and every entry of `filters` matches. Use `"*"` in `accounts`
+/// to select all accounts.
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct AccountsSelector {
+    #[serde(default)]
+    pub accounts: Vec<String>,
+    #[serde(default)]
     pub owners: Vec<String>,
+    #[serde(default)]
+    pub filters: Vec<AccountFilter>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccountFilter {
+    DataSize(u64),
+    /// `bytes` is base58 encoded, matching the RPC `memcmp` filter.
+    Memcmp { offset: usize, bytes: String },
+}
+
+/// Transactions are selected when they mention one of `mentions` or invoke one
+/// of `program_ids`. Use `"*"` in `mentions` to select all transactions.
+#[derive(Debug, Clone, Deserialize)]
+pub struct TransactionSelector {
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    #[serde(default)]
+    pub program_ids: Vec<String>,
+    #[serde(default)]
+    pub include_votes: bool,
+    #[serde(default = "default_true")]
+    pub include_failed: bool,
+}
+
+/// Where a validator-loaded plugin sends its events. The plugin builds its own
+/// `Processor` from the indexer config at `indexer_config_path`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct GeyserOutputConfig {
+    pub indexer_config_path: String,
+    #[serde(default)]
+    pub worker_threads: Option<usize>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct EventQueueConfig {
+    #[serde(default = "default_event_queue_capacity")]
+    pub capacity: usize,
+    #[serde(default)]
+    pub overflow: OverflowPolicy,
+}
+
+impl Default for EventQueueConfig {
+    fn default() -> Self {
+        Self {
+            capacity: default_event_queue_capacity(),
+            overflow: OverflowPolicy::default(),
+        }
+    }
+}
+
+/// What the Geyser plugin does when its event queue is full. None of the
+/// policies return an error to the validator.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// Wait up to `timeout_ms` for space, then drop the event.
+    Block { timeout_ms: u64 },
+    DropOldest,
+    /// Append overflowing events to `path` and replay them once the in-memory
+    /// queue drains.
+    SpillToDisk { path: String },
+    /// Evict the oldest non-startup account update, falling back to the
+    /// oldest event when there is none.
+    DropAccountUpdatesFirst,
+}
+
+impl Default for OverflowPolicy {
+    fn default() -> Self {
+        OverflowPolicy::Block { timeout_ms: 100 }
+    }
+}
+
+fn default_health_check_interval_secs() -> u64 {
+    10
+}
+
+fn default_grpc_initial_backoff_ms() -> u64 {
+    500
+}
+
+fn default_grpc_max_backoff_ms() -> u64 {
+    30_000
+}
+
+fn default_grpc_ping_interval_secs() -> u64 {
+    10
+}
+
+fn default_event_queue_capacity() -> usize {
+    1000
+}
+
+fn default_startup_batch_size() -> usize {
+    10_000
+}
+
+fn default_true() -> bool {
+    true
 }
 
 impl Config {
     pub fn load() -> Result<Self, Box<dyn std::error::Error>> {
-        let config_str = fs::read_to_string("config.json")?;
+        Self::load_from("config.json")
+    }
+
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let config_str = fs::read_to_string(path)?;
         let config: Config = serde_json::from_str(&config_str)?;
         Ok(config)
     }
 }
+
+impl GeyserPluginConfig {
+    pub fn load_from<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn std::error::Error>> {
+        let config_str = fs::read_to_string(path)?;
+        let config: GeyserPluginConfig = serde_json::from_str(&config_str)?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,250 @@
+use super::endpoint_pool::EndpointPool;
+use super::subscription::{reconnect, SubscriptionError};
+use crate::config::{AccountFilter, Config, WebsocketConfig};
+use crate::processing::Processor;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use solana_account_decoder::{UiAccount, UiAccountEncoding};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_client::rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType};
+use solana_sdk::account::Account;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+/// Feeds live account state from `accountSubscribe` and `programSubscribe`
+/// into the processor.
+pub struct AccountSubscriber {
+    endpoints: Arc<EndpointPool>,
+    config: WebsocketConfig,
+    accounts: Vec<Pubkey>,
+    programs: Vec<(Pubkey, Vec<RpcFilterType>)>,
+}
+
+impl AccountSubscriber {
+    pub fn new(config: &Config, endpoints: Arc<EndpointPool>) -> Result<Self, SubscriptionError> {
+        let subscriptions = &config.account_subscriptions;
+        let accounts = subscriptions
+            .accounts
+            .iter()
+            .map(|pubkey| parse_pubkey(pubkey))
+            .collect::<Result<Vec<_>, _>>()?;
+        let programs = subscriptions
+            .programs
+            .iter()
+            .map(|program| {
+                let filters = program.filters.iter().map(rpc_filter).collect();
+                Ok((parse_pubkey(&program.program_id)?, filters))
+            })
+            .collect::<Result<Vec<_>, SubscriptionError>>()?;
+
+        Ok(Self {
+            endpoints,
+            config: config.websocket.clone(),
+            accounts,
+            programs,
+        })
+    }
+
+    fn account_config() -> RpcAccountInfoConfig {
+        RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(CommitmentConfig::confirmed()),
+            ..Default::default()
+        }
+    }
+
+    /// Subscribes to every configured account and program on one connection
+    /// and processes updates until it drops or goes idle.
+    async fn run_session(&self, url: &str, processor: &Processor) -> Result<(), SubscriptionError> {
+        let client = PubsubClient::new(url).await?;
+        let (mut slots, _unsubscribe) = client.slot_subscribe().await?;
+
+        let mut subscriptions: Vec<BoxStream<'_, (Pubkey, UiAccount, u64)>> = Vec::new();
+        for pubkey in &self.accounts {
+            let pubkey = *pubkey;
+            let (updates, _unsubscribe) = client
+                .account_subscribe(&pubkey, Some(Self::account_config()))
+                .await?;
+            subscriptions.push(
+                updates
+                    .map(move |response| (pubkey, response.value, response.context.slot))
+                    .boxed(),
+            );
+        }
+        for (program_id, filters) in &self.programs {
+            let config = RpcProgramAccountsConfig {
+                filters: (!filters.is_empty()).then(|| filters.clone()),
+                account_config: Self::account_config(),
+                with_context: Some(true),
+                ..Default::default()
+            };
+            let (updates, _unsubscribe) =
+                client.program_subscribe(program_id, Some(config)).await?;
+            subscriptions.push(
+                updates
+                    .filter_map(|response| async move {
+                        match Pubkey::from_str(&response.value.pubkey) {
+                            Ok(pubkey) => {
+                                Some((pubkey, response.value.account, response.context.slot))
+                            }
+                            Err(_) => {
+                                tracing::warn!(
+                                    "Invalid pubkey in program notification: {}",
+                                    response.value.pubkey
+                                );
+                                None
+                            }
+                        }
+                    })
+                    .boxed(),
+            );
+        }
+        tracing::info!(
+            "Subscribed to {} accounts and {} programs on {}",
+            self.accounts.len(),
+            self.programs.len(),
+            url
+        );
+
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        let mut updates = stream::select_all(subscriptions);
+        let result = loop {
+            match next_update(&mut updates, &mut slots, idle_timeout).await {
+                Ok((pubkey, account, slot)) => {
+                    if let Err(e) = self.handle_update(processor, pubkey, account, slot).await {
+                        tracing::error!("Failed to process account {}: {:?}", pubkey, e);
+                    }
+                }
+                Err(e) => break Err(e),
+            }
+        };
+
+        drop(updates);
+        drop(slots);
+        let _ = client.shutdown().await;
+        result
+    }
+
+    async fn handle_update(
+        &self,
+        processor: &Processor,
+        pubkey: Pubkey,
+        account: UiAccount,
+        slot: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let account: Account = account
+            .decode()
+            .ok_or_else(|| format!("undecodable account data for {}", pubkey))?;
+        metrics::counter!("cypher_pubsub_account_updates_total").increment(1);
+        processor
+            .process_account_update(pubkey, account.data, account.owner, slot, None)
+            .await
+    }
+}
+
+/// Waits for the next account update. Accounts can stay quiet far longer
+/// than `idle_timeout`, so the slot notifications on the same connection are
+/// what show it is still alive.
+async fn next_update<U, H>(
+    updates: &mut U,
+    heartbeats: &mut H,
+    idle_timeout: Duration,
+) -> Result<U::Item, SubscriptionError>
+where
+    U: Stream + Unpin,
+    H: Stream + Unpin,
+{
+    loop {
+        tokio::select! {
+            update = updates.next() => return update.ok_or(SubscriptionError::StreamEnded),
+            heartbeat = heartbeats.next() => {
+                if heartbeat.is_none() {
+                    return Err(SubscriptionError::StreamEnded);
+                }
+            }
+            _ = tokio::time::sleep(idle_timeout) => {
+                return Err(SubscriptionError::Idle(idle_timeout));
+            }
+        }
+    }
+}
+
+fn parse_pubkey(pubkey: &str) -> Result<Pubkey, SubscriptionError> {
+    Pubkey::from_str(pubkey).map_err(|_| SubscriptionError::InvalidPubkey(pubkey.to_string()))
+}
+
+fn rpc_filter(filter: &AccountFilter) -> RpcFilterType {
+    match filter {
+        AccountFilter::DataSize(size) => RpcFilterType::DataSize(*size),
+        AccountFilter::Memcmp { offset, bytes } => RpcFilterType::Memcmp(Memcmp::new(
+            *offset,
+            MemcmpEncodedBytes::Base58(bytes.clone()),
+        )),
+    }
+}
+
+#[async_trait]
+impl super::IngestionSource for AccountSubscriber {
+    async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
+        if self.accounts.is_empty() && self.programs.is_empty() {
+            tracing::info!("No account subscriptions configured");
+            return Ok(());
+        }
+
+        let processor = &processor;
+        reconnect(
+            &self.endpoints,
+            &self.config,
+            "Account subscriptions",
+            |url| async move { self.run_session(&url, processor).await },
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::time::Instant;
+
+    #[tokio::test]
+    async fn test_idle_connection_times_out() {
+        let idle_timeout = Duration::from_millis(100);
+        let mut updates = stream::iter(vec![1]).chain(stream::pending());
+        // Slots keep arriving for a while, then the connection stalls.
+        let mut heartbeats = stream::iter(0..10)
+            .then(|_| tokio::time::sleep(Duration::from_millis(20)))
+            .chain(stream::pending());
+
+        let update = next_update(&mut updates, &mut heartbeats, idle_timeout).await;
+        assert_eq!(update.unwrap(), 1);
+
+        let started = Instant::now();
+        let result = next_update(&mut updates, &mut heartbeats, idle_timeout).await;
+        assert!(matches!(result, Err(SubscriptionError::Idle(_))));
+        assert!(started.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn test_rpc_filter() {
+        assert_eq!(
+            rpc_filter(&AccountFilter::DataSize(165)),
+            RpcFilterType::DataSize(165)
+        );
+        assert_eq!(
+            rpc_filter(&AccountFilter::Memcmp {
+                offset: 32,
+                bytes: "3Mc6vR".to_string(),
+            }),
+            RpcFilterType::Memcmp(Memcmp::new(
+                32,
+                MemcmpEncodedBytes::Base58("3Mc6vR".to_string())
+            ))
+        );
+    }
+}
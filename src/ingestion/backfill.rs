@@ -0,0 +1,357 @@
+use super::endpoint_pool::EndpointPool;
+use super::rpc_errors::{classify, Retry, RpcErrorClass};
+use crate::config::Config;
+use crate::processing::Processor;
+use crate::storage::{Storage, StorageError};
+use futures::stream::{self, StreamExt};
+use solana_client::client_error::ClientError;
+use solana_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_client::rpc_config::{RpcBlockConfig, RpcTransactionConfig};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+use std::ops::RangeInclusive;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// `getBlocks` rejects ranges wider than this.
+const MAX_GET_BLOCKS_RANGE: u64 = 500_000;
+const SIGNATURES_PAGE_LIMIT: usize = 1000;
+
+#[derive(Debug, Error)]
+pub enum BackfillError {
+    #[error("RPC error: {0}")]
+    Rpc(#[from] ClientError),
+    #[error("Processing error: {0}")]
+    Processing(String),
+    #[error("Storage error: {0}")]
+    Storage(#[from] StorageError),
+    #[error("Invalid range: {0}")]
+    InvalidRange(String),
+}
+
+#[derive(Debug, Clone)]
+pub enum BackfillRange {
+    /// Every confirmed block in `start..=end`.
+    Slots { start: u64, end: u64 },
+    /// Every transaction mentioning `program_id`, walking back from `before`
+    /// (or the tip) until `until` (or the program's first transaction).
+    Program {
+        program_id: Pubkey,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    },
+}
+
+/// Where historical blocks are read from. `Bigtable` only supports slot
+/// ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackfillSource {
+    Rpc,
+    Bigtable,
+}
+
+impl std::str::FromStr for BackfillSource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rpc" => Ok(BackfillSource::Rpc),
+            "bigtable" => Ok(BackfillSource::Bigtable),
+            _ => Err(format!("unknown backfill source: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BackfillReport {
+    pub blocks: u64,
+    pub transactions: u64,
+    pub skipped_slots: u64,
+}
+
+/// Backfills over RPC. Failed requests are retried with the `rpc_poller`
+/// `max_retries` and `initial_backoff_ms` settings.
+pub struct Backfill {
+    endpoints: Arc<EndpointPool>,
+    storage: Storage,
+    concurrency: usize,
+    retry: Retry,
+}
+
+impl Backfill {
+    pub fn new(
+        config: &Config,
+        endpoints: Arc<EndpointPool>,
+        storage: Storage,
+        concurrency: usize,
+    ) -> Self {
+        Self {
+            endpoints,
+            storage,
+            concurrency: concurrency.max(1),
+            retry: Retry::new(&config.rpc_poller),
+        }
+    }
+
+    pub async fn run(
+        &self,
+        processor: &Processor,
+        range: BackfillRange,
+    ) -> Result<BackfillReport, BackfillError> {
+        match range {
+            BackfillRange::Slots { start, end } => self.backfill_slots(processor, start, end).await,
+            BackfillRange::Program {
+                program_id,
+                before,
+                until,
+            } => {
+                self.backfill_program(processor, &program_id, before, until)
+                    .await
+            }
+        }
+    }
+
+    async fn backfill_slots(
+        &self,
+        processor: &Processor,
+        start: u64,
+        end: u64,
+    ) -> Result<BackfillReport, BackfillError> {
+        if start > end {
+            return Err(BackfillError::InvalidRange(format!(
+                "start slot {} is after end slot {}",
+                start, end
+            )));
+        }
+
+        let mut report = BackfillReport::default();
+        for chunk in slot_chunks(start, end) {
+            let (first, last) = (*chunk.start(), *chunk.end());
+            let slots = self
+                .retry
+                .run("getBlocks", || {
+                    self.endpoints
+                        .call(|client| async move { client.get_blocks(first, Some(last)).await })
+                })
+                .await?;
+            for slot in missing_slots(chunk.clone(), &slots) {
+                self.storage.record_skipped_slot(slot).await?;
+                report.skipped_slots += 1;
+            }
+
+            // Fetch concurrently but hand blocks to the processor in slot order.
+            let mut blocks = stream::iter(slots)
+                .map(|slot| {
+                    let endpoints = self.endpoints.clone();
+                    let retry = self.retry;
+                    spawn_fetch(async move { (slot, fetch_block(&endpoints, slot, retry).await) })
+                })
+                .buffered(self.concurrency);
+
+            while let Some((slot, block)) = blocks.next().await {
+                match block? {
+                    Some(block) => {
+                        processor
+                            .process_block(block, slot)
+                            .await
+                            .map_err(|e| BackfillError::Processing(e.to_string()))?;
+                        report.blocks += 1;
+                    }
+                    None => {
+                        tracing::debug!("Slot {} was skipped", slot);
+                        self.storage.record_skipped_slot(slot).await?;
+                        report.skipped_slots += 1;
+                    }
+                }
+            }
+            tracing::info!("Backfilled slots {}..={}", chunk.start(), chunk.end());
+        }
+
+        Ok(report)
+    }
+
+    async fn backfill_program(
+        &self,
+        processor: &Processor,
+        program_id: &Pubkey,
+        before: Option<Signature>,
+        until: Option<Signature>,
+    ) -> Result<BackfillReport, BackfillError> {
+        let transaction_config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        let mut report = BackfillReport::default();
+        let mut before = before;
+        // Pages come newest first. Each page is indexed oldest first like the
+        // live path before the next, older, page is requested.
+        loop {
+            let page = self
+                .retry
+                .run("getSignaturesForAddress", || {
+                    self.endpoints.call(|client| async move {
+                        let config = GetConfirmedSignaturesForAddress2Config {
+                            before,
+                            until,
+                            limit: Some(SIGNATURES_PAGE_LIMIT),
+                            commitment: Some(CommitmentConfig::confirmed()),
+                        };
+                        client
+                            .get_signatures_for_address_with_config(program_id, config)
+                            .await
+                    })
+                })
+                .await?;
+            let mut signatures = page
+                .iter()
+                .map(|status| {
+                    status.signature.parse::<Signature>().map_err(|_| {
+                        BackfillError::InvalidRange(format!(
+                            "invalid signature {}",
+                            status.signature
+                        ))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            before = signatures.last().copied();
+            signatures.reverse();
+
+            let mut transactions = stream::iter(signatures)
+                .map(|signature| {
+                    let endpoints = self.endpoints.clone();
+                    let retry = self.retry;
+                    spawn_fetch(async move {
+                        retry
+                            .run("getTransaction", || {
+                                endpoints.call(|client| async move {
+                                    client
+                                        .get_transaction_with_config(&signature, transaction_config)
+                                        .await
+                                })
+                            })
+                            .await
+                    })
+                })
+                .buffered(self.concurrency);
+
+            while let Some(transaction) = transactions.next().await {
+                let transaction = transaction?;
+                let slot = transaction.slot;
+                processor
+                    .process_transaction(transaction, slot)
+                    .await
+                    .map_err(|e| BackfillError::Processing(e.to_string()))?;
+                report.transactions += 1;
+            }
+            tracing::info!(
+                "Backfilled {} transactions for program {}",
+                report.transactions,
+                program_id
+            );
+
+            if page.len() < SIGNATURES_PAGE_LIMIT {
+                break;
+            }
+        }
+
+        Ok(report)
+    }
+}
+
+/// Fetches a block, or `None` when the slot was skipped or no endpoint can
+/// serve it any more.
+async fn fetch_block(
+    endpoints: &EndpointPool,
+    slot: u64,
+    retry: Retry,
+) -> Result<Option<solana_transaction_status::UiConfirmedBlock>, BackfillError> {
+    let config = RpcBlockConfig {
+        encoding: Some(UiTransactionEncoding::Json),
+        transaction_details: Some(TransactionDetails::Full),
+        rewards: Some(false),
+        commitment: Some(CommitmentConfig::confirmed()),
+        max_supported_transaction_version: Some(0),
+    };
+    let block = retry
+        .run("getBlock", || {
+            endpoints.call(|client| async move { client.get_block_with_config(slot, config).await })
+        })
+        .await;
+    match block {
+        Ok(block) => Ok(Some(block)),
+        Err(e) if classify(&e) == RpcErrorClass::Unavailable => {
+            tracing::warn!("Block {} is no longer available over RPC: {}", slot, e);
+            Ok(None)
+        }
+        Err(e) if classify(&e) == RpcErrorClass::Skipped => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Runs `fetch` on its own task. `buffered` only polls its futures while the
+/// stream is polled, so without this the fetches stall while a result is being
+/// processed.
+fn spawn_fetch<T: Send + 'static>(
+    fetch: impl Future<Output = T> + Send + 'static,
+) -> impl Future<Output = T> {
+    let handle = tokio::spawn(fetch);
+    async move { handle.await.expect("backfill fetch task panicked") }
+}
+
+/// Slots in `range` that `getBlocks` left out of `slots`, which produced no
+/// block.
+fn missing_slots(range: RangeInclusive<u64>, slots: &[u64]) -> Vec<u64> {
+    let mut confirmed = slots.iter().copied().peekable();
+    range
+        .filter(|&slot| confirmed.next_if_eq(&slot).is_none())
+        .collect()
+}
+
+/// Splits `start..=end` into ranges `getBlocks` accepts.
+fn slot_chunks(start: u64, end: u64) -> impl Iterator<Item = RangeInclusive<u64>> {
+    (start..=end)
+        .step_by(MAX_GET_BLOCKS_RANGE as usize)
+        .map(move |chunk_start| chunk_start..=end.min(chunk_start + MAX_GET_BLOCKS_RANGE - 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_chunks() {
+        let chunks: Vec<_> = slot_chunks(10, 20).collect();
+        assert_eq!(chunks, vec![10..=20]);
+
+        let chunks: Vec<_> = slot_chunks(0, MAX_GET_BLOCKS_RANGE * 2).collect();
+        assert_eq!(
+            chunks,
+            vec![
+                0..=MAX_GET_BLOCKS_RANGE - 1,
+                MAX_GET_BLOCKS_RANGE..=MAX_GET_BLOCKS_RANGE * 2 - 1,
+                MAX_GET_BLOCKS_RANGE * 2..=MAX_GET_BLOCKS_RANGE * 2,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_slots() {
+        assert_eq!(missing_slots(10..=15, &[10, 12, 13]), vec![11, 14, 15]);
+        assert!(missing_slots(1..=2, &[1, 2]).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_spawned_fetch_runs_before_it_is_awaited() {
+        let (sender, receiver) = tokio::sync::oneshot::channel();
+        let fetch = spawn_fetch(async move {
+            sender.send(()).unwrap();
+            7
+        });
+        // Completes even though nothing has polled `fetch` yet.
+        receiver.await.unwrap();
+        assert_eq!(fetch.await, 7);
+    }
+}
@@ -0,0 +1,367 @@
+use super::endpoint_pool::EndpointPool;
+use super::rpc_errors::{classify, RpcErrorClass};
+use super::snapshots::SlotStatus;
+use super::subscription::{reconnect, SubscriptionError};
+use crate::config::{Config, WebsocketConfig};
+use crate::processing::Processor;
+use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{
+    RpcBlockConfig, RpcBlockSubscribeConfig, RpcBlockSubscribeFilter,
+};
+use solana_client::rpc_response::{Response, RpcBlockUpdate, SlotInfo, SlotUpdate};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{TransactionDetails, UiTransactionEncoding};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::time::Duration;
+
+enum SlotNotification {
+    Update(SlotUpdate),
+    Info(SlotInfo),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SlotEvent {
+    slot: u64,
+    parent: Option<u64>,
+    status: SlotStatus,
+}
+
+/// Turns `slotsUpdatesSubscribe` or `slotSubscribe` notifications into slot
+/// status changes, remembering parents until their slot is rooted. A new root
+/// also roots its unrooted ancestors, as far as their parents are known.
+#[derive(Default)]
+struct SlotTracker {
+    parents: BTreeMap<u64, u64>,
+    root: Option<u64>,
+}
+
+impl SlotTracker {
+    fn observe(&mut self, notification: SlotNotification) -> Vec<SlotEvent> {
+        match notification {
+            SlotNotification::Update(update) => {
+                let (slot, status) = match update {
+                    SlotUpdate::CreatedBank { slot, parent, .. } => {
+                        self.parents.insert(slot, parent);
+                        return Vec::new();
+                    }
+                    SlotUpdate::Frozen { slot, .. } => (slot, SlotStatus::Processed),
+                    SlotUpdate::OptimisticConfirmation { slot, .. } => {
+                        (slot, SlotStatus::Confirmed)
+                    }
+                    SlotUpdate::Root { slot, .. } => return self.root(slot),
+                    SlotUpdate::Dead { slot, .. } => {
+                        let event = self.event(slot, SlotStatus::Dead);
+                        self.parents.remove(&slot);
+                        return vec![event];
+                    }
+                    _ => return Vec::new(),
+                };
+                vec![self.event(slot, status)]
+            }
+            SlotNotification::Info(info) => {
+                self.parents.insert(info.slot, info.parent);
+                let mut events = vec![self.event(info.slot, SlotStatus::Processed)];
+                events.extend(self.root(info.root));
+                events
+            }
+        }
+    }
+
+    /// Roots `root` and every ancestor above the previous root, oldest first.
+    fn root(&mut self, root: u64) -> Vec<SlotEvent> {
+        if self.root >= Some(root) {
+            return Vec::new();
+        }
+        let mut chain = vec![root];
+        let mut slot = root;
+        while let Some(&parent) = self.parents.get(&slot) {
+            if self.root >= Some(parent) {
+                break;
+            }
+            chain.push(parent);
+            slot = parent;
+        }
+        let events = chain
+            .into_iter()
+            .rev()
+            .map(|slot| self.event(slot, SlotStatus::Rooted))
+            .collect();
+        self.set_root(root);
+        events
+    }
+
+    fn event(&self, slot: u64, status: SlotStatus) -> SlotEvent {
+        SlotEvent {
+            slot,
+            parent: self.parents.get(&slot).copied(),
+            status,
+        }
+    }
+
+    fn set_root(&mut self, root: u64) {
+        self.root = Some(root);
+        self.parents = self.parents.split_off(&root);
+    }
+}
+
+/// Push-based block ingestion: whole blocks from `blockSubscribe` and slot
+/// status from `slotsUpdatesSubscribe`. Nodes without `blockSubscribe` get
+/// blocks fetched over RPC as slots are confirmed, and nodes without slot
+/// updates fall back to `slotSubscribe`.
+pub struct BlockSubscriber {
+    endpoints: Arc<EndpointPool>,
+    config: WebsocketConfig,
+}
+
+impl BlockSubscriber {
+    pub fn new(config: &Config, endpoints: Arc<EndpointPool>) -> Self {
+        Self {
+            endpoints,
+            config: config.websocket.clone(),
+        }
+    }
+
+    async fn run_session(&self, url: &str, processor: &Processor) -> Result<(), SubscriptionError> {
+        let client = PubsubClient::new(url).await?;
+
+        // Without slot updates there is no confirmed notification, so fetched
+        // blocks wait for the root instead.
+        let (mut slots, fetch_on): (BoxStream<'_, SlotNotification>, _) =
+            match client.slot_updates_subscribe().await {
+                Ok((updates, _unsubscribe)) => (
+                    updates.map(SlotNotification::Update).boxed(),
+                    SlotStatus::Confirmed,
+                ),
+                Err(e) => {
+                    tracing::info!("slotsUpdatesSubscribe unavailable on {}: {}", url, e);
+                    let (updates, _unsubscribe) = client.slot_subscribe().await?;
+                    (updates.map(SlotNotification::Info).boxed(), SlotStatus::Rooted)
+                }
+            };
+
+        let block_config = RpcBlockSubscribeConfig {
+            commitment: Some(CommitmentConfig::confirmed()),
+            encoding: Some(UiTransactionEncoding::Json),
+            transaction_details: Some(TransactionDetails::Full),
+            show_rewards: Some(false),
+            max_supported_transaction_version: Some(0),
+        };
+        let (mut blocks, push_blocks): (BoxStream<'_, Response<RpcBlockUpdate>>, _) = match client
+            .block_subscribe(RpcBlockSubscribeFilter::All, Some(block_config))
+            .await
+        {
+            Ok((blocks, _unsubscribe)) => (blocks, true),
+            Err(e) => {
+                tracing::info!(
+                    "blockSubscribe unavailable on {}, fetching blocks over RPC: {}",
+                    url,
+                    e
+                );
+                (stream::pending().boxed(), false)
+            }
+        };
+        tracing::info!("Subscribed to slots and blocks on {}", url);
+
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        let mut tracker = SlotTracker::default();
+        let result = loop {
+            tokio::select! {
+                notification = slots.next() => match notification {
+                    Some(notification) => {
+                        for event in tracker.observe(notification) {
+                            if let Err(e) = processor
+                                .process_slot_status(event.slot, event.parent, event.status)
+                                .await
+                            {
+                                tracing::error!("Failed to process slot {}: {:?}", event.slot, e);
+                            }
+                            if !push_blocks && event.status == fetch_on {
+                                self.fetch_block(processor, event.slot).await;
+                            }
+                        }
+                    }
+                    None => break Err(SubscriptionError::StreamEnded),
+                },
+                update = blocks.next() => match update {
+                    Some(update) => self.handle_block_update(processor, update.value).await,
+                    None => break Err(SubscriptionError::StreamEnded),
+                },
+                _ = tokio::time::sleep(idle_timeout) => break Err(SubscriptionError::Idle(idle_timeout)),
+            }
+        };
+
+        drop(slots);
+        drop(blocks);
+        let _ = client.shutdown().await;
+        result
+    }
+
+    async fn handle_block_update(&self, processor: &Processor, update: RpcBlockUpdate) {
+        match (update.block, update.err) {
+            (Some(block), _) => {
+                metrics::counter!("cypher_pubsub_blocks_total").increment(1);
+                if let Err(e) = processor.process_block(block, update.slot).await {
+                    tracing::error!("Failed to process block {}: {:?}", update.slot, e);
+                }
+            }
+            (None, Some(err)) => {
+                tracing::warn!("blockSubscribe error for slot {}: {:?}", update.slot, err)
+            }
+            (None, None) => tracing::debug!("Slot {} has no block", update.slot),
+        }
+    }
+
+    async fn fetch_block(&self, processor: &Processor, slot: u64) {
+        let config = RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            transaction_details: Some(TransactionDetails::Full),
+            rewards: Some(false),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        let block = self
+            .endpoints
+            .call(|client| async move { client.get_block_with_config(slot, config).await })
+            .await;
+        match block {
+            Ok(block) => {
+                metrics::counter!("cypher_pubsub_blocks_total").increment(1);
+                if let Err(e) = processor.process_block(block, slot).await {
+                    tracing::error!("Failed to process block {}: {:?}", slot, e);
+                }
+            }
+            Err(e) if classify(&e) == RpcErrorClass::Skipped => {
+                tracing::debug!("Slot {} was skipped", slot)
+            }
+            Err(e) => tracing::warn!("Failed to fetch block {}: {}", slot, e),
+        }
+    }
+}
+
+#[async_trait]
+impl super::IngestionSource for BlockSubscriber {
+    async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
+        if !self.config.subscribe_blocks {
+            return Ok(());
+        }
+
+        let processor = &processor;
+        reconnect(
+            &self.endpoints,
+            &self.config,
+            "Block subscriptions",
+            |url| async move { self.run_session(&url, processor).await },
+        )
+        .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_slot_updates() {
+        let mut tracker = SlotTracker::default();
+        assert!(tracker
+            .observe(SlotNotification::Update(SlotUpdate::CreatedBank {
+                slot: 11,
+                parent: 10,
+                timestamp: 0,
+            }))
+            .is_empty());
+
+        assert_eq!(
+            tracker.observe(SlotNotification::Update(SlotUpdate::OptimisticConfirmation {
+                slot: 11,
+                timestamp: 0,
+            })),
+            vec![SlotEvent {
+                slot: 11,
+                parent: Some(10),
+                status: SlotStatus::Confirmed,
+            }]
+        );
+
+        tracker.observe(SlotNotification::Update(SlotUpdate::Root {
+            slot: 11,
+            timestamp: 0,
+        }));
+        assert_eq!(tracker.root, Some(11));
+        assert_eq!(tracker.parents.keys().copied().collect::<Vec<_>>(), vec![11]);
+    }
+
+    #[test]
+    fn test_slot_info() {
+        let mut tracker = SlotTracker::default();
+        let events = tracker.observe(SlotNotification::Info(SlotInfo {
+            slot: 100,
+            parent: 99,
+            root: 68,
+        }));
+        assert_eq!(
+            events,
+            vec![
+                SlotEvent {
+                    slot: 100,
+                    parent: Some(99),
+                    status: SlotStatus::Processed,
+                },
+                SlotEvent {
+                    slot: 68,
+                    parent: None,
+                    status: SlotStatus::Rooted,
+                },
+            ]
+        );
+
+        // The root has not moved, so only the new slot is reported.
+        let events = tracker.observe(SlotNotification::Info(SlotInfo {
+            slot: 101,
+            parent: 100,
+            root: 68,
+        }));
+        assert_eq!(events.len(), 1);
+
+        // The root jumps past 99, so 99 is rooted along with 100.
+        let events = tracker.observe(SlotNotification::Info(SlotInfo {
+            slot: 102,
+            parent: 101,
+            root: 100,
+        }));
+        let rooted: Vec<_> = events
+            .iter()
+            .filter(|event| event.status == SlotStatus::Rooted)
+            .map(|event| event.slot)
+            .collect();
+        assert_eq!(rooted, vec![99, 100]);
+    }
+
+    #[test]
+    fn test_dead_slot() {
+        let mut tracker = SlotTracker::default();
+        tracker.observe(SlotNotification::Update(SlotUpdate::CreatedBank {
+            slot: 11,
+            parent: 10,
+            timestamp: 0,
+        }));
+
+        assert_eq!(
+            tracker.observe(SlotNotification::Update(SlotUpdate::Dead {
+                slot: 11,
+                timestamp: 0,
+                err: "duplicate block".to_string(),
+            })),
+            vec![SlotEvent {
+                slot: 11,
+                parent: Some(10),
+                status: SlotStatus::Dead,
+            }]
+        );
+        assert!(tracker.parents.is_empty());
+    }
+}
@@ -0,0 +1,268 @@
+use crate::config::DedupConfig;
+use parking_lot::Mutex;
+use solana_sdk::hash::Hash as DataHash;
+use solana_sdk::pubkey::Pubkey;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+
+/// Drops transactions and account updates already delivered by another
+/// ingestion source. Keys are remembered for `window_slots` behind the
+/// highest slot seen and at most `ttl`, so memory stays bounded; anything
+/// older is let through.
+pub struct Deduplicator {
+    transactions: Mutex<DedupWindow<(String, u64)>>,
+    accounts: Mutex<DedupWindow<(Pubkey, u64, AccountVersion)>>,
+}
+
+/// Tells apart updates of one account within a slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AccountVersion {
+    WriteVersion(u64),
+    /// For sources without write versions, e.g. `accountSubscribe`. These
+    /// only match copies from other sources without write versions.
+    DataHash(DataHash),
+}
+
+/// A key claimed for indexing. Dropping it without `commit`, e.g. because
+/// indexing failed, releases the key to a duplicate waiting on it.
+#[must_use]
+pub struct DedupClaim<'a, K: Hash + Eq + Clone> {
+    window: &'a Mutex<DedupWindow<K>>,
+    slot: u64,
+    key: K,
+    stored: Option<watch::Sender<bool>>,
+}
+
+impl<K: Hash + Eq + Clone> DedupClaim<'_, K> {
+    pub fn commit(mut self) {
+        if let Some(stored) = self.stored.take() {
+            stored.send_replace(true);
+        }
+    }
+}
+
+impl<K: Hash + Eq + Clone> Drop for DedupClaim<'_, K> {
+    fn drop(&mut self) {
+        if let Some(stored) = self.stored.take() {
+            // Remove the key before waking waiters, so one can claim it.
+            self.window.lock().remove(self.slot, &self.key, &stored);
+        }
+    }
+}
+
+impl Deduplicator {
+    pub fn new(config: &DedupConfig) -> Self {
+        let ttl = Duration::from_secs(config.ttl_secs);
+        Self {
+            transactions: Mutex::new(DedupWindow::new(config.window_slots, ttl)),
+            accounts: Mutex::new(DedupWindow::new(config.window_slots, ttl)),
+        }
+    }
+
+    /// Claims `(signature, slot)` for indexing, or returns `None` once
+    /// another copy has been indexed. While another copy is still being
+    /// indexed this waits for it, and claims the key if that copy fails.
+    pub async fn claim_transaction(
+        &self,
+        signature: &str,
+        slot: u64,
+    ) -> Option<DedupClaim<'_, (String, u64)>> {
+        claim(
+            &self.transactions,
+            slot,
+            (signature.to_string(), slot),
+            "transaction",
+        )
+        .await
+    }
+
+    /// Claims an account update, like `claim_transaction`.
+    pub async fn claim_account(
+        &self,
+        pubkey: &Pubkey,
+        slot: u64,
+        version: AccountVersion,
+    ) -> Option<DedupClaim<'_, (Pubkey, u64, AccountVersion)>> {
+        claim(&self.accounts, slot, (*pubkey, slot, version), "account").await
+    }
+}
+
+async fn claim<'a, K: Hash + Eq + Clone>(
+    window: &'a Mutex<DedupWindow<K>>,
+    slot: u64,
+    key: K,
+    kind: &'static str,
+) -> Option<DedupClaim<'a, K>> {
+    loop {
+        let mut pending = match window.lock().claim(slot, key.clone()) {
+            Ok(stored) => {
+                return Some(DedupClaim {
+                    window,
+                    slot,
+                    key,
+                    stored: Some(stored),
+                })
+            }
+            Err(pending) => pending,
+        };
+        // An error means the other copy failed and released the key.
+        if pending.wait_for(|stored| *stored).await.is_ok() {
+            metrics::counter!("cypher_dedup_dropped_total", "kind" => kind).increment(1);
+            return None;
+        }
+    }
+}
+
+struct DedupWindow<K> {
+    window_slots: u64,
+    ttl: Duration,
+    highest_slot: u64,
+    /// Whether each key's copy has been indexed yet.
+    seen: HashMap<K, watch::Receiver<bool>>,
+    /// Keys by slot, with when the slot was first seen, for eviction.
+    slots: BTreeMap<u64, (Instant, Vec<K>)>,
+}
+
+impl<K: Hash + Eq + Clone> DedupWindow<K> {
+    fn new(window_slots: u64, ttl: Duration) -> Self {
+        Self {
+            window_slots,
+            ttl,
+            highest_slot: 0,
+            seen: HashMap::new(),
+            slots: BTreeMap::new(),
+        }
+    }
+
+    /// Claims a new key, or returns the state of the copy already claimed.
+    fn claim(&mut self, slot: u64, key: K) -> Result<watch::Sender<bool>, watch::Receiver<bool>> {
+        self.evict(slot);
+        if let Some(stored) = self.seen.get(&key) {
+            return Err(stored.clone());
+        }
+        let (sender, receiver) = watch::channel(false);
+        self.seen.insert(key.clone(), receiver);
+        self.slots
+            .entry(slot)
+            .or_insert_with(|| (Instant::now(), Vec::new()))
+            .1
+            .push(key);
+        Ok(sender)
+    }
+
+    /// Releases a claim, unless the key was evicted and claimed again since.
+    fn remove(&mut self, slot: u64, key: &K, claim: &watch::Sender<bool>) {
+        let claimed = self
+            .seen
+            .get(key)
+            .is_some_and(|stored| stored.same_channel(&claim.subscribe()));
+        if claimed {
+            self.seen.remove(key);
+            if let Some((_, keys)) = self.slots.get_mut(&slot) {
+                keys.retain(|k| k != key);
+            }
+        }
+    }
+
+    fn evict(&mut self, slot: u64) {
+        self.highest_slot = self.highest_slot.max(slot);
+        let min_slot = self.highest_slot.saturating_sub(self.window_slots);
+        let now = Instant::now();
+        while let Some(entry) = self.slots.first_entry() {
+            let (first_seen, _) = entry.get();
+            if *entry.key() >= min_slot && now.duration_since(*first_seen) < self.ttl {
+                break;
+            }
+            for key in entry.remove().1 {
+                self.seen.remove(&key);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dedup() -> Deduplicator {
+        Deduplicator::new(&DedupConfig {
+            enabled: true,
+            window_slots: 10,
+            ttl_secs: 60,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_drops_duplicates_within_window() {
+        let dedup = dedup();
+        let pubkey = Pubkey::new_unique();
+
+        dedup.claim_transaction("sig", 5).await.unwrap().commit();
+        assert!(dedup.claim_transaction("sig", 5).await.is_none());
+        // The same signature in another slot is a different fork's copy.
+        assert!(dedup.claim_transaction("sig", 6).await.is_some());
+
+        let version = AccountVersion::WriteVersion(1);
+        dedup
+            .claim_account(&pubkey, 5, version)
+            .await
+            .unwrap()
+            .commit();
+        assert!(dedup.claim_account(&pubkey, 5, version).await.is_none());
+        assert!(dedup
+            .claim_account(&pubkey, 5, AccountVersion::WriteVersion(2))
+            .await
+            .is_some());
+
+        // Updates without a write version are told apart by their data.
+        let version = AccountVersion::DataHash(solana_sdk::hash::hash(&[1, 2, 3]));
+        dedup
+            .claim_account(&pubkey, 5, version)
+            .await
+            .unwrap()
+            .commit();
+        assert!(dedup.claim_account(&pubkey, 5, version).await.is_none());
+        assert!(dedup
+            .claim_account(
+                &pubkey,
+                5,
+                AccountVersion::DataHash(solana_sdk::hash::hash(&[4]))
+            )
+            .await
+            .is_some());
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_waits_for_failed_copy() {
+        let dedup = dedup();
+        let first = dedup.claim_transaction("sig", 5).await.unwrap();
+        let second = dedup.claim_transaction("sig", 5);
+        tokio::pin!(second);
+        assert!(futures::poll!(&mut second).is_pending());
+
+        // The first copy failed to index, so the duplicate takes over.
+        drop(first);
+        let second = second.await.unwrap();
+        let third = dedup.claim_transaction("sig", 5);
+        tokio::pin!(third);
+        assert!(futures::poll!(&mut third).is_pending());
+        second.commit();
+        assert!(third.await.is_none());
+    }
+
+    #[test]
+    fn test_evicts_old_slots() {
+        let mut window = DedupWindow::new(10, Duration::from_secs(60));
+        let _a = window.claim(5, "a").unwrap();
+        let _b = window.claim(20, "b").unwrap();
+        // Slot 5 fell out of the window when slot 20 arrived.
+        assert!(window.claim(5, "a").is_ok());
+        assert!(window.claim(20, "b").is_err());
+
+        let mut window = DedupWindow::new(10, Duration::ZERO);
+        let _a = window.claim(5, "a").unwrap();
+        assert!(window.claim(5, "a").is_ok());
+    }
+}
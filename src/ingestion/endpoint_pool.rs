@@ -0,0 +1,330 @@
+use super::rate_limiter::RateLimiter;
+use super::rpc_errors::{classify, is_endpoint_failure, RpcErrorClass};
+use crate::config::{Config, RpcEndpointConfig};
+use parking_lot::Mutex;
+use rand::Rng;
+use solana_client::client_error::ClientError;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_sdk::commitment_config::CommitmentConfig;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Consecutive failed calls before an endpoint is taken out of rotation until
+/// its next successful health check.
+const MAX_CONSECUTIVE_FAILURES: u32 = 3;
+/// Weight of the newest sample in the latency moving average.
+const LATENCY_EWMA_ALPHA: f64 = 0.2;
+const INITIAL_LATENCY_MS: f64 = 100.0;
+
+pub struct Endpoint {
+    pub rpc_url: String,
+    pub websocket_url: Option<String>,
+    client: Arc<RpcClient>,
+    rate_limiter: RateLimiter,
+    weight: f64,
+    health: Mutex<EndpointHealth>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct EndpointHealth {
+    healthy: bool,
+    latency_ms: f64,
+    consecutive_failures: u32,
+}
+
+impl Endpoint {
+    pub fn new(
+        rpc_url: String,
+        websocket_url: Option<String>,
+        client: RpcClient,
+        rate_limiter: RateLimiter,
+        weight: f64,
+    ) -> Self {
+        Self {
+            rpc_url,
+            websocket_url,
+            client: Arc::new(client),
+            rate_limiter,
+            weight: weight.max(0.0),
+            health: Mutex::new(EndpointHealth {
+                healthy: true,
+                latency_ms: INITIAL_LATENCY_MS,
+                consecutive_failures: 0,
+            }),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.health.lock().healthy
+    }
+
+    pub fn latency_ms(&self) -> f64 {
+        self.health.lock().latency_ms
+    }
+
+    fn score(&self) -> f64 {
+        self.weight / self.latency_ms().max(1.0)
+    }
+
+    fn record_success(&self, latency: Duration) {
+        let mut health = self.health.lock();
+        let sample = latency.as_secs_f64() * 1000.0;
+        health.latency_ms =
+            LATENCY_EWMA_ALPHA * sample + (1.0 - LATENCY_EWMA_ALPHA) * health.latency_ms;
+        health.consecutive_failures = 0;
+        health.healthy = true;
+    }
+
+    fn record_failure(&self) {
+        let mut health = self.health.lock();
+        health.consecutive_failures += 1;
+        if health.consecutive_failures >= MAX_CONSECUTIVE_FAILURES && health.healthy {
+            tracing::warn!("RPC endpoint {} marked unhealthy", self.rpc_url);
+            health.healthy = false;
+        }
+    }
+}
+
+/// RPC endpoints shared by the ingestion sources. Calls go to a healthy
+/// endpoint picked at random, weighted by configured weight over observed
+/// latency, and fail over to the others in turn.
+pub struct EndpointPool {
+    endpoints: Vec<Arc<Endpoint>>,
+}
+
+impl EndpointPool {
+    pub fn new(endpoints: Vec<Endpoint>) -> Self {
+        assert!(
+            !endpoints.is_empty(),
+            "endpoint pool needs at least one endpoint"
+        );
+        Self {
+            endpoints: endpoints.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    /// Builds the pool from `rpc_endpoints`, falling back to `solana_rpc_url`
+    /// and `websocket_url` when none are configured.
+    pub fn from_config(config: &Config) -> Self {
+        let endpoints = if config.rpc_endpoints.is_empty() {
+            vec![RpcEndpointConfig {
+                rpc_url: config.solana_rpc_url.clone(),
+                websocket_url: Some(config.websocket_url.clone()),
+                requests_per_second: None,
+                burst: None,
+                weight: None,
+            }]
+        } else {
+            config.rpc_endpoints.clone()
+        };
+
+        Self::new(
+            endpoints
+                .into_iter()
+                .map(|endpoint| {
+                    Endpoint::new(
+                        endpoint.rpc_url.clone(),
+                        endpoint.websocket_url,
+                        RpcClient::new_with_commitment(
+                            endpoint.rpc_url,
+                            CommitmentConfig::confirmed(),
+                        ),
+                        RateLimiter::new(
+                            endpoint
+                                .requests_per_second
+                                .unwrap_or(config.rpc_poller.requests_per_second),
+                            endpoint.burst.unwrap_or(config.rpc_poller.burst),
+                        ),
+                        endpoint.weight.unwrap_or(1.0),
+                    )
+                })
+                .collect(),
+        )
+    }
+
+    pub fn endpoints(&self) -> &[Arc<Endpoint>] {
+        &self.endpoints
+    }
+
+    /// WebSocket URLs of healthy endpoints first, then the rest.
+    pub fn websocket_urls(&self) -> Vec<String> {
+        let (healthy, unhealthy): (Vec<_>, Vec<_>) =
+            self.endpoints.iter().partition(|e| e.is_healthy());
+        healthy
+            .into_iter()
+            .chain(unhealthy)
+            .filter_map(|e| e.websocket_url.clone())
+            .collect()
+    }
+
+    /// Endpoint indices in the order a call should try them: a weighted random
+    /// pick among healthy endpoints, the remaining healthy ones by score, then
+    /// unhealthy ones as a last resort.
+    fn attempt_order(&self) -> Vec<usize> {
+        let mut healthy: Vec<usize> = (0..self.endpoints.len())
+            .filter(|&i| self.endpoints[i].is_healthy())
+            .collect();
+        let mut unhealthy: Vec<usize> = (0..self.endpoints.len())
+            .filter(|&i| !self.endpoints[i].is_healthy())
+            .collect();
+
+        let by_score = |a: &usize, b: &usize| {
+            self.endpoints[*b]
+                .score()
+                .partial_cmp(&self.endpoints[*a].score())
+                .unwrap_or(std::cmp::Ordering::Equal)
+        };
+        healthy.sort_by(by_score);
+        unhealthy.sort_by(by_score);
+
+        let total: f64 = healthy.iter().map(|&i| self.endpoints[i].score()).sum();
+        if total > 0.0 {
+            let mut pick = rand::thread_rng().gen_range(0.0..total);
+            if let Some(position) = healthy.iter().position(|&i| {
+                pick -= self.endpoints[i].score();
+                pick < 0.0
+            }) {
+                let first = healthy.remove(position);
+                healthy.insert(0, first);
+            }
+        }
+
+        healthy.extend(unhealthy);
+        healthy
+    }
+
+    /// Runs `f` against endpoints until one succeeds. A skipped slot is an
+    /// answer rather than an endpoint fault, so it is returned without
+    /// failing over. Only connection, timeout and server errors count
+    /// against an endpoint's health; a rate-limited endpoint is backed off
+    /// instead.
+    pub async fn call<T, F, Fut>(&self, f: F) -> Result<T, ClientError>
+    where
+        F: Fn(Arc<RpcClient>) -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut last_error = None;
+        for index in self.attempt_order() {
+            let endpoint = &self.endpoints[index];
+            endpoint.rate_limiter.acquire().await;
+
+            let started = Instant::now();
+            match f(endpoint.client.clone()).await {
+                Ok(value) => {
+                    endpoint.record_success(started.elapsed());
+                    return Ok(value);
+                }
+                Err(e) if classify(&e) == RpcErrorClass::Skipped => {
+                    endpoint.record_success(started.elapsed());
+                    return Err(e);
+                }
+                Err(e) => {
+                    tracing::debug!("RPC call to {} failed: {}", endpoint.rpc_url, e);
+                    if classify(&e) == RpcErrorClass::RateLimited {
+                        endpoint.rate_limiter.back_off().await;
+                    } else if is_endpoint_failure(&e) {
+                        metrics::counter!("cypher_rpc_endpoint_failures_total", "endpoint" => endpoint.rpc_url.clone())
+                            .increment(1);
+                        endpoint.record_failure();
+                    }
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.expect("endpoint pool is never empty"))
+    }
+
+    /// Probes every endpoint with `getHealth` on `interval`, restoring
+    /// endpoints that recover and refreshing latency for idle ones.
+    pub async fn run_health_checks(self: Arc<Self>, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            for endpoint in &self.endpoints {
+                let started = Instant::now();
+                match endpoint.client.get_health().await {
+                    Ok(()) => endpoint.record_success(started.elapsed()),
+                    Err(e) => {
+                        tracing::warn!("Health check failed for {}: {}", endpoint.rpc_url, e);
+                        endpoint.health.lock().healthy = false;
+                    }
+                }
+                metrics::gauge!("cypher_rpc_endpoint_latency_ms", "endpoint" => endpoint.rpc_url.clone())
+                    .set(endpoint.latency_ms());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_request::RpcRequest;
+    use std::collections::HashMap;
+
+    fn endpoint(url: &str, slot: u64) -> Endpoint {
+        weighted_endpoint(url, slot, 1.0)
+    }
+
+    fn weighted_endpoint(url: &str, slot: u64, weight: f64) -> Endpoint {
+        let mut mocks = HashMap::new();
+        mocks.insert(RpcRequest::GetSlot, serde_json::json!(slot));
+        Endpoint::new(
+            url.to_string(),
+            Some(format!("ws://{}", url)),
+            RpcClient::new_mock_with_mocks(url.to_string(), mocks),
+            RateLimiter::new(1000, 1000),
+            weight,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_fails_over_to_next_endpoint() {
+        let pool = EndpointPool::new(vec![
+            Endpoint::new(
+                "fails".to_string(),
+                None,
+                RpcClient::new_mock("fails".to_string()),
+                RateLimiter::new(1000, 1000),
+                1.0,
+            ),
+            // Weight 0 keeps the weighted pick on the failing endpoint while
+            // it is healthy, so every call below has to fail over.
+            weighted_endpoint("succeeds", 42, 0.0),
+        ]);
+
+        for failures in 1..=MAX_CONSECUTIVE_FAILURES {
+            assert_eq!(pool.attempt_order(), vec![0, 1]);
+            let slot = pool
+                .call(|client| async move { client.get_slot().await })
+                .await
+                .unwrap();
+            assert_eq!(slot, 42);
+            assert_eq!(
+                pool.endpoints()[0].health.lock().consecutive_failures,
+                failures
+            );
+        }
+        // The failing endpoint is out of rotation once it has failed enough.
+        assert!(!pool.endpoints()[0].is_healthy());
+        assert_eq!(pool.attempt_order()[0], 1);
+        assert_eq!(pool.websocket_urls(), vec!["ws://succeeds".to_string()]);
+    }
+
+    #[test]
+    fn test_prefers_low_latency() {
+        let pool = EndpointPool::new(vec![endpoint("slow", 1), endpoint("fast", 2)]);
+        for _ in 0..20 {
+            pool.endpoints()[0].record_success(Duration::from_millis(1000));
+            pool.endpoints()[1].record_success(Duration::from_millis(10));
+        }
+
+        let fast_first = (0..1000).filter(|_| pool.attempt_order()[0] == 1).count();
+        assert!(
+            fast_first > 900,
+            "fast endpoint chosen {} times",
+            fast_first
+        );
+    }
+}
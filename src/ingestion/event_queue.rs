@@ -0,0 +1,453 @@
+use super::geyser_plugin::GeyserEvent;
+use super::snapshots::{AccountSnapshot, BlockSnapshot, EntrySnapshot, SlotStatus};
+use crate::config::{EventQueueConfig, OverflowPolicy};
+use parking_lot::{Condvar, Mutex};
+use serde::{Deserialize, Serialize};
+use solana_transaction_status::EncodedConfirmedTransaction;
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+#[derive(Debug, Error)]
+pub enum EventQueueError {
+    #[error("Spill file error: {0}")]
+    SpillIo(#[from] io::Error),
+    #[error("Spill serialization error: {0}")]
+    SpillSerialization(#[from] serde_json::Error),
+    #[error("Encode error: {0}")]
+    Encode(#[from] solana_transaction_status::EncodeError),
+}
+
+/// Bounded queue between the validator notification threads and the async
+/// processor. `push` never fails; overflow is handled by the configured
+/// `OverflowPolicy` and reported through metrics.
+pub struct EventQueue {
+    state: Mutex<QueueState>,
+    /// Locked separately so disk I/O never holds up `state`.
+    spill: Option<Mutex<SpillFile>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    not_full: Condvar,
+    not_empty: Notify,
+}
+
+struct QueueState {
+    events: VecDeque<GeyserEvent>,
+    /// Events sent to the spill file, including ones still being written.
+    spilled: usize,
+    closed: bool,
+}
+
+impl EventQueue {
+    pub fn new(config: &EventQueueConfig) -> Result<Self, EventQueueError> {
+        let spill = match &config.overflow {
+            OverflowPolicy::SpillToDisk { path } => Some(SpillFile::open(path)?),
+            _ => None,
+        };
+
+        Ok(Self {
+            state: Mutex::new(QueueState {
+                events: VecDeque::with_capacity(config.capacity),
+                spilled: spill.as_ref().map_or(0, |spill| spill.pending),
+                closed: false,
+            }),
+            spill: spill.map(Mutex::new),
+            capacity: config.capacity.max(1),
+            policy: config.overflow.clone(),
+            not_full: Condvar::new(),
+            not_empty: Notify::new(),
+        })
+    }
+
+    pub fn push(&self, event: GeyserEvent) {
+        let mut state = self.state.lock();
+        if state.closed {
+            return;
+        }
+
+        match &self.policy {
+            OverflowPolicy::Block { timeout_ms } => {
+                let deadline = Instant::now() + Duration::from_millis(*timeout_ms);
+                while state.events.len() >= self.capacity && !state.closed {
+                    if self.not_full.wait_until(&mut state, deadline).timed_out() {
+                        break;
+                    }
+                }
+                if state.closed {
+                    return;
+                }
+                if state.events.len() >= self.capacity {
+                    record_drop("block_timeout");
+                    return;
+                }
+                state.events.push_back(event);
+            }
+            OverflowPolicy::DropOldest => {
+                if state.events.len() >= self.capacity {
+                    state.events.pop_front();
+                    record_drop("drop_oldest");
+                }
+                state.events.push_back(event);
+            }
+            OverflowPolicy::DropAccountUpdatesFirst => {
+                if state.events.len() >= self.capacity {
+                    if is_evictable(&event) {
+                        record_drop("account_update");
+                        return;
+                    }
+                    match state.events.iter().position(is_evictable) {
+                        Some(index) => {
+                            state.events.remove(index);
+                            record_drop("account_update");
+                        }
+                        None => {
+                            state.events.pop_front();
+                            record_drop("drop_oldest");
+                        }
+                    }
+                }
+                state.events.push_back(event);
+            }
+            OverflowPolicy::SpillToDisk { .. } => {
+                if state.events.len() >= self.capacity || state.spilled > 0 {
+                    // Once anything is on disk, newer events follow it there so
+                    // replay order matches arrival order.
+                    state.spilled += 1;
+                    drop(state);
+                    self.spill_event(event);
+                    return;
+                }
+                state.events.push_back(event);
+            }
+        }
+
+        metrics::gauge!("cypher_geyser_event_queue_depth").set(state.events.len() as f64);
+        drop(state);
+        self.not_empty.notify_one();
+    }
+
+    /// Appends an event already counted in `spilled` to the spill file,
+    /// outside the queue lock.
+    fn spill_event(&self, event: GeyserEvent) {
+        let result = match &self.spill {
+            Some(spill) => spill.lock().push(event),
+            None => Ok(()),
+        };
+        match result {
+            Ok(()) => metrics::counter!("cypher_geyser_events_spilled_total").increment(1),
+            Err(e) => {
+                tracing::error!("Failed to spill Geyser event: {:?}", e);
+                record_drop("spill_error");
+                self.state.lock().spilled -= 1;
+            }
+        }
+        self.not_empty.notify_one();
+    }
+
+    pub async fn pop(&self) -> Option<GeyserEvent> {
+        loop {
+            let spilled = {
+                let mut state = self.state.lock();
+                if let Some(event) = state.events.pop_front() {
+                    metrics::gauge!("cypher_geyser_event_queue_depth")
+                        .set(state.events.len() as f64);
+                    self.not_full.notify_one();
+                    return Some(event);
+                }
+                if state.closed && state.spilled == 0 {
+                    return None;
+                }
+                state.spilled
+            };
+            if let Some(spill) = self.spill.as_ref().filter(|_| spilled > 0) {
+                let mut spill = spill.lock();
+                let pending = spill.pending;
+                let result = spill.pop();
+                let read = pending - spill.pending;
+                drop(spill);
+                self.state.lock().spilled -= read;
+                match result {
+                    Ok(Some(event)) => return Some(event.into()),
+                    // Counted but not written yet; its writer notifies.
+                    Ok(None) => {}
+                    Err(e) => {
+                        // `pop` always moves past what it failed to read,
+                        // so retrying cannot spin on the same error.
+                        tracing::error!("Failed to read spilled Geyser event: {:?}", e);
+                        metrics::counter!(
+                            "cypher_geyser_events_dropped_total",
+                            "reason" => "spill_error"
+                        )
+                        .increment(read as u64);
+                        continue;
+                    }
+                }
+            }
+            self.not_empty.notified().await;
+        }
+    }
+
+    /// Stops accepting events and wakes any blocked producers. Events already
+    /// queued are still returned by `pop`.
+    pub fn close(&self) {
+        self.state.lock().closed = true;
+        self.not_full.notify_all();
+        self.not_empty.notify_one();
+    }
+
+    pub fn len(&self) -> usize {
+        let state = self.state.lock();
+        state.events.len() + state.spilled
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn is_evictable(event: &GeyserEvent) -> bool {
+    matches!(event, GeyserEvent::AccountUpdate(account) if !account.is_startup)
+}
+
+fn record_drop(reason: &'static str) {
+    metrics::counter!("cypher_geyser_events_dropped_total", "reason" => reason).increment(1);
+}
+
+/// On-disk form of a `GeyserEvent`. Transactions are stored encoded because
+/// `TransactionStatusMeta` has no serde support.
+#[derive(Serialize, Deserialize)]
+enum SpilledEvent {
+    AccountUpdate(AccountSnapshot),
+    Transaction(Box<EncodedConfirmedTransaction>),
+    BlockMetadata(BlockSnapshot),
+    Entry(EntrySnapshot),
+    SlotStatusChange(u64, Option<u64>, SlotStatus),
+    EndOfStartup,
+}
+
+impl TryFrom<GeyserEvent> for SpilledEvent {
+    type Error = EventQueueError;
+
+    fn try_from(event: GeyserEvent) -> Result<Self, Self::Error> {
+        Ok(match event {
+            GeyserEvent::AccountUpdate(account) => SpilledEvent::AccountUpdate(account),
+            GeyserEvent::TransactionNotify(transaction) => {
+                SpilledEvent::Transaction(Box::new(transaction.encode()?))
+            }
+            GeyserEvent::EncodedTransaction(transaction) => SpilledEvent::Transaction(transaction),
+            GeyserEvent::BlockMetadata(block) => SpilledEvent::BlockMetadata(block),
+            GeyserEvent::Entry(entry) => SpilledEvent::Entry(entry),
+            GeyserEvent::SlotStatusChange(slot, parent, status) => {
+                SpilledEvent::SlotStatusChange(slot, parent, status)
+            }
+            GeyserEvent::EndOfStartup => SpilledEvent::EndOfStartup,
+        })
+    }
+}
+
+impl From<SpilledEvent> for GeyserEvent {
+    fn from(event: SpilledEvent) -> Self {
+        match event {
+            SpilledEvent::AccountUpdate(account) => GeyserEvent::AccountUpdate(account),
+            SpilledEvent::Transaction(transaction) => GeyserEvent::EncodedTransaction(transaction),
+            SpilledEvent::BlockMetadata(block) => GeyserEvent::BlockMetadata(block),
+            SpilledEvent::Entry(entry) => GeyserEvent::Entry(entry),
+            SpilledEvent::SlotStatusChange(slot, parent, status) => {
+                GeyserEvent::SlotStatusChange(slot, parent, status)
+            }
+            SpilledEvent::EndOfStartup => GeyserEvent::EndOfStartup,
+        }
+    }
+}
+
+/// Append-only JSON lines file. Events left over from a previous run are
+/// replayed before new ones, and the file is truncated whenever it drains.
+struct SpillFile {
+    writer: File,
+    reader: BufReader<File>,
+    pending: usize,
+}
+
+impl SpillFile {
+    fn open<P: AsRef<Path>>(path: P) -> Result<Self, EventQueueError> {
+        let writer = OpenOptions::new().create(true).append(true).open(&path)?;
+        let mut reader = BufReader::new(File::open(&path)?);
+        let pending = (&mut reader).lines().count();
+        reader.seek(SeekFrom::Start(0))?;
+
+        Ok(Self {
+            writer,
+            reader,
+            pending,
+        })
+    }
+
+    fn push(&mut self, event: GeyserEvent) -> Result<(), EventQueueError> {
+        let mut line = serde_json::to_vec(&SpilledEvent::try_from(event)?)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.pending += 1;
+        Ok(())
+    }
+
+    fn pop(&mut self) -> Result<Option<SpilledEvent>, EventQueueError> {
+        if self.pending == 0 {
+            return Ok(None);
+        }
+
+        let mut line = String::new();
+        if let Err(e) = self.reader.read_line(&mut line) {
+            // Where the next event starts is unknown after a failed read, so
+            // the rest of the file is given up on.
+            self.pending = 0;
+            self.truncate();
+            return Err(e.into());
+        }
+        self.pending -= 1;
+        if self.pending == 0 {
+            self.truncate();
+        }
+
+        Ok(Some(serde_json::from_str(&line)?))
+    }
+
+    fn truncate(&mut self) {
+        let result = self
+            .writer
+            .set_len(0)
+            .and_then(|()| self.reader.seek(SeekFrom::Start(0)));
+        if let Err(e) = result {
+            tracing::error!("Failed to truncate Geyser spill file: {:?}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn account_update(slot: u64, is_startup: bool) -> GeyserEvent {
+        GeyserEvent::AccountUpdate(AccountSnapshot {
+            pubkey: Pubkey::new_unique(),
+            owner: Pubkey::new_unique(),
+            lamports: 1,
+            executable: false,
+            rent_epoch: 0,
+            data: vec![],
+            write_version: slot,
+            txn_signature: None,
+            slot,
+            is_startup,
+        })
+    }
+
+    fn slot_of(event: &GeyserEvent) -> u64 {
+        match event {
+            GeyserEvent::AccountUpdate(account) => account.slot,
+            GeyserEvent::SlotStatusChange(slot, _, _) => *slot,
+            _ => unreachable!(),
+        }
+    }
+
+    fn queue(capacity: usize, overflow: OverflowPolicy) -> EventQueue {
+        EventQueue::new(&EventQueueConfig { capacity, overflow }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let queue = queue(2, OverflowPolicy::DropOldest);
+        for slot in 1..=3 {
+            queue.push(account_update(slot, false));
+        }
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(slot_of(&queue.pop().await.unwrap()), 2);
+        assert_eq!(slot_of(&queue.pop().await.unwrap()), 3);
+    }
+
+    #[tokio::test]
+    async fn test_drop_account_updates_first() {
+        let queue = queue(2, OverflowPolicy::DropAccountUpdatesFirst);
+        queue.push(account_update(1, true));
+        queue.push(account_update(2, false));
+        queue.push(GeyserEvent::SlotStatusChange(
+            3,
+            None,
+            SlotStatus::Processed,
+        ));
+        queue.push(account_update(4, false));
+
+        assert_eq!(queue.len(), 2);
+        assert_eq!(slot_of(&queue.pop().await.unwrap()), 1);
+        assert_eq!(slot_of(&queue.pop().await.unwrap()), 3);
+    }
+
+    #[tokio::test]
+    async fn test_block_times_out() {
+        let queue = queue(1, OverflowPolicy::Block { timeout_ms: 10 });
+        queue.push(account_update(1, false));
+
+        let started = Instant::now();
+        queue.push(account_update(2, false));
+        assert!(started.elapsed() >= Duration::from_millis(10));
+
+        assert_eq!(queue.len(), 1);
+        assert_eq!(slot_of(&queue.pop().await.unwrap()), 1);
+    }
+
+    #[tokio::test]
+    async fn test_spill_to_disk_preserves_order() {
+        let path =
+            std::env::temp_dir().join(format!("cypher_spill_{}.jsonl", Pubkey::new_unique()));
+        let queue = queue(
+            1,
+            OverflowPolicy::SpillToDisk {
+                path: path.to_string_lossy().to_string(),
+            },
+        );
+        for slot in 1..=3 {
+            queue.push(account_update(slot, false));
+        }
+
+        assert_eq!(queue.len(), 3);
+        for slot in 1..=3 {
+            assert_eq!(slot_of(&queue.pop().await.unwrap()), slot);
+        }
+        assert!(queue.is_empty());
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 0);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_spill_read_error_discards_pending() {
+        let path =
+            std::env::temp_dir().join(format!("cypher_spill_{}.jsonl", Pubkey::new_unique()));
+        // Reading a directory fails on every call.
+        let mut spill = SpillFile {
+            writer: File::create(&path).unwrap(),
+            reader: BufReader::new(File::open(std::env::temp_dir()).unwrap()),
+            pending: 3,
+        };
+
+        assert!(matches!(spill.pop(), Err(EventQueueError::SpillIo(_))));
+        assert_eq!(spill.pending, 0);
+        assert!(spill.pop().unwrap().is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_close_drains_then_ends() {
+        let queue = queue(4, OverflowPolicy::DropOldest);
+        queue.push(account_update(1, false));
+        queue.close();
+        queue.push(account_update(2, false));
+
+        assert_eq!(slot_of(&queue.pop().await.unwrap()), 1);
+        assert!(queue.pop().await.is_none());
+    }
+}
@@ -1,76 +1,283 @@
-use crate::config::Config;
+use super::event_queue::{EventQueue, EventQueueError};
+use super::selectors::{AccountsFilter, TransactionsFilter};
+use super::snapshots::{
+    AccountSnapshot, BlockSnapshot, EntrySnapshot, SlotStatus, TransactionSnapshot,
+};
+use crate::config::{Config, GeyserPluginConfig};
 use crate::processing::Processor;
+use crate::storage::{ipfs::IpfsStorage, Storage};
 use async_trait::async_trait;
 use parking_lot::RwLock;
 use solana_geyser_plugin_interface::geyser_plugin_interface::{
-    GeyserPlugin, ReplicaAccountInfoVersions, ReplicaBlockInfoVersions,
-    ReplicaTransactionInfoVersions, SlotStatus,
+    GeyserPlugin as GeyserPluginInterface, GeyserPluginError as InterfaceError,
+    ReplicaAccountInfoVersions, ReplicaBlockInfoVersions, ReplicaEntryInfoVersions,
+    ReplicaTransactionInfoVersions, SlotStatus as GeyserSlotStatus,
 };
 use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::EncodedConfirmedTransaction;
 use std::sync::Arc;
+use std::time::Instant;
 use thiserror::Error;
-use tokio::sync::mpsc;
+use tokio::runtime::{Builder, Runtime};
+
+const UNLOAD_DRAIN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 #[derive(Debug, Error)]
 pub enum GeyserPluginError {
     #[error("Processing error: {0}")]
     ProcessingError(String),
-    #[error("Channel send error: {0}")]
-    ChannelSendError(#[from] tokio::sync::mpsc::error::SendError<GeyserEvent>),
+    #[error("Event queue error: {0}")]
+    EventQueueError(#[from] EventQueueError),
+    #[error("Config error: {0}")]
+    ConfigError(String),
+    #[error("Selector error: {0}")]
+    SelectorError(#[from] super::selectors::SelectorError),
+    #[error("Plugin is not loaded")]
+    NotLoaded,
 }
 
+#[derive(Default)]
 pub struct GeyserPlugin {
-    config: Config,
+    config: Option<GeyserPluginConfig>,
+    accounts_filter: AccountsFilter,
+    transactions_filter: Option<TransactionsFilter>,
     processor: Arc<RwLock<Option<Processor>>>,
-    event_sender: mpsc::Sender<GeyserEvent>,
+    event_queue: Option<Arc<EventQueue>>,
+    runtime: Option<Runtime>,
 }
 
 #[derive(Debug)]
-enum GeyserEvent {
-    AccountUpdate(Box<ReplicaAccountInfoVersions<'static>>, u64, bool),
-    TransactionNotify(Box<ReplicaTransactionInfoVersions<'static>>, u64),
-    BlockMetadata(Box<ReplicaBlockInfoVersions<'static>>),
+pub enum GeyserEvent {
+    AccountUpdate(AccountSnapshot),
+    TransactionNotify(Box<TransactionSnapshot>),
+    /// Transactions replayed from the spill file are already encoded.
+    EncodedTransaction(Box<EncodedConfirmedTransaction>),
+    BlockMetadata(BlockSnapshot),
+    Entry(EntrySnapshot),
     SlotStatusChange(u64, Option<u64>, SlotStatus),
+    EndOfStartup,
 }
 
 impl GeyserPlugin {
+    /// Creates a plugin driven by an already running indexer. Events are
+    /// consumed on the caller's runtime once `start` hands over a `Processor`.
     pub fn new(config: &Config) -> Self {
-        let (event_sender, mut event_receiver) = mpsc::channel(1000); // Adjust buffer size as needed
         let processor = Arc::new(RwLock::new(None));
-        let processor_clone = Arc::clone(&processor);
-
-        tokio::spawn(async move {
-            while let Some(event) = event_receiver.recv().await {
-                if let Some(processor) = processor_clone.read().as_ref() {
-                    match event {
-                        GeyserEvent::AccountUpdate(account, slot, is_startup) => {
-                            let _ = processor
-                                .process_account_update(account, slot, is_startup)
-                                .await;
-                        }
-                        GeyserEvent::TransactionNotify(transaction, slot) => {
-                            let _ = processor.process_transaction(transaction, slot).await;
+        let event_queue = Arc::new(
+            EventQueue::new(&config.geyser_plugin_config.event_queue).unwrap_or_else(|e| {
+                tracing::error!("Invalid Geyser event queue, using defaults: {:?}", e);
+                EventQueue::new(&Default::default()).expect("default event queue")
+            }),
+        );
+        Self::spawn_event_loop(
+            &tokio::runtime::Handle::current(),
+            &event_queue,
+            &processor,
+            config.geyser_plugin_config.startup_batch_size,
+        );
+        let (accounts_filter, transactions_filter) =
+            Self::build_filters(&config.geyser_plugin_config).unwrap_or_else(|e| {
+                tracing::error!("Invalid Geyser selector, selecting nothing: {:?}", e);
+                (AccountsFilter::default(), None)
+            });
+
+        Self {
+            config: Some(config.geyser_plugin_config.clone()),
+            accounts_filter,
+            transactions_filter,
+            processor,
+            event_queue: Some(event_queue),
+            runtime: None,
+        }
+    }
+
+    fn build_filters(
+        config: &GeyserPluginConfig,
+    ) -> Result<(AccountsFilter, Option<TransactionsFilter>), GeyserPluginError> {
+        let accounts_filter = AccountsFilter::from_config(&config.accounts_selector)?;
+        let transactions_filter = config
+            .transaction_selector
+            .as_ref()
+            .map(TransactionsFilter::from_config)
+            .transpose()?;
+        Ok((accounts_filter, transactions_filter))
+    }
+
+    fn is_account_selected(&self, account: &ReplicaAccountInfoVersions) -> bool {
+        let (pubkey, owner, data) = match account {
+            ReplicaAccountInfoVersions::V0_0_1(info) => (info.pubkey, info.owner, info.data),
+            ReplicaAccountInfoVersions::V0_0_2(info) => (info.pubkey, info.owner, info.data),
+            ReplicaAccountInfoVersions::V0_0_3(info) => (info.pubkey, info.owner, info.data),
+        };
+        self.accounts_filter.is_selected(pubkey, owner, data)
+    }
+
+    fn is_transaction_selected(&self, transaction: &ReplicaTransactionInfoVersions) -> bool {
+        let filter = match &self.transactions_filter {
+            Some(filter) => filter,
+            None => return false,
+        };
+        let (sanitized, is_vote, meta) = match transaction {
+            ReplicaTransactionInfoVersions::V0_0_1(info) => {
+                (info.transaction, info.is_vote, info.transaction_status_meta)
+            }
+            ReplicaTransactionInfoVersions::V0_0_2(info) => {
+                (info.transaction, info.is_vote, info.transaction_status_meta)
+            }
+        };
+        let message = sanitized.message();
+        filter.is_selected(
+            is_vote,
+            meta.status.is_err(),
+            message.account_keys().iter(),
+            message
+                .program_instructions_iter()
+                .map(|(program_id, _)| program_id as &Pubkey),
+        )
+    }
+
+    fn spawn_event_loop(
+        handle: &tokio::runtime::Handle,
+        event_queue: &Arc<EventQueue>,
+        processor: &Arc<RwLock<Option<Processor>>>,
+        startup_batch_size: usize,
+    ) {
+        let event_queue = Arc::clone(event_queue);
+        let processor = Arc::clone(processor);
+
+        handle.spawn(async move {
+            let mut startup_batch: Vec<AccountSnapshot> = Vec::with_capacity(startup_batch_size);
+            let mut startup_started: Option<Instant> = None;
+
+            while let Some(event) = event_queue.pop().await {
+                let processor = match processor.read().clone() {
+                    Some(processor) => processor,
+                    None => continue,
+                };
+                match event {
+                    GeyserEvent::AccountUpdate(account) if account.is_startup => {
+                        startup_started.get_or_insert_with(Instant::now);
+                        startup_batch.push(account);
+                        if startup_batch.len() >= startup_batch_size {
+                            Self::flush_startup_batch(&processor, &mut startup_batch).await;
                         }
-                        GeyserEvent::BlockMetadata(block_info) => {
-                            let _ = processor.process_block_metadata(block_info).await;
+                    }
+                    GeyserEvent::EndOfStartup => {
+                        Self::flush_startup_batch(&processor, &mut startup_batch).await;
+                        if let Some(started) = startup_started.take() {
+                            tracing::info!(
+                                "Startup snapshot loaded in {:?}",
+                                started.elapsed()
+                            );
                         }
-                        GeyserEvent::SlotStatusChange(slot, parent, status) => {
-                            let _ = processor.process_slot_status(slot, parent, status).await;
+                    }
+                    event => {
+                        // Anything after a startup account must observe it, so
+                        // flush the pending batch first.
+                        Self::flush_startup_batch(&processor, &mut startup_batch).await;
+                        if let Err(e) = Self::dispatch_event(&processor, event).await {
+                            tracing::error!("Failed to process Geyser event: {:?}", e);
                         }
                     }
                 }
             }
         });
+    }
 
-        Self {
-            config: config.clone(),
-            processor,
-            event_sender,
+    async fn flush_startup_batch(processor: &Processor, batch: &mut Vec<AccountSnapshot>) {
+        if batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::take(batch);
+        let len = batch.len();
+        if let Err(e) = processor.process_startup_accounts(batch).await {
+            tracing::error!("Failed to bulk load {} startup accounts: {:?}", len, e);
+        }
+    }
+
+    pub(super) async fn dispatch_event(
+        processor: &Processor,
+        event: GeyserEvent,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        match event {
+            GeyserEvent::AccountUpdate(account) => {
+                processor
+                    .process_account_update(
+                        account.pubkey,
+                        account.data,
+                        account.owner,
+                        account.slot,
+                        Some(account.write_version),
+                    )
+                    .await
+            }
+            GeyserEvent::TransactionNotify(transaction) => {
+                let encoded = transaction.encode()?;
+                processor.process_transaction(encoded, transaction.slot).await
+            }
+            GeyserEvent::EncodedTransaction(transaction) => {
+                let slot = transaction.slot;
+                processor.process_transaction(*transaction, slot).await
+            }
+            GeyserEvent::BlockMetadata(block) => processor.process_block_metadata(block).await,
+            // Entries are forwarded for consumers of the event model but are
+            // not indexed yet.
+            GeyserEvent::Entry(_) | GeyserEvent::EndOfStartup => Ok(()),
+            GeyserEvent::SlotStatusChange(slot, parent, status) => {
+                processor.process_slot_status(slot, parent, status).await
+            }
         }
     }
 
+    fn build_runtime(config: &GeyserPluginConfig) -> Result<Runtime, GeyserPluginError> {
+        let mut builder = Builder::new_multi_thread();
+        builder.enable_all().thread_name("cypher-geyser");
+        if let Some(worker_threads) = config.output.as_ref().and_then(|o| o.worker_threads) {
+            builder.worker_threads(worker_threads);
+        }
+        builder
+            .build()
+            .map_err(|e| GeyserPluginError::ConfigError(e.to_string()))
+    }
+
+    fn load(&mut self, config_file: &str) -> Result<(), GeyserPluginError> {
+        let config = GeyserPluginConfig::load_from(config_file)
+            .map_err(|e| GeyserPluginError::ConfigError(e.to_string()))?;
+        let output = config.output.clone().ok_or_else(|| {
+            GeyserPluginError::ConfigError("missing `output` section".to_string())
+        })?;
+        let indexer_config = Config::load_from(&output.indexer_config_path)
+            .map_err(|e| GeyserPluginError::ConfigError(e.to_string()))?;
+
+        let (accounts_filter, transactions_filter) = Self::build_filters(&config)?;
+        let event_queue = Arc::new(EventQueue::new(&config.event_queue)?);
+        let runtime = Self::build_runtime(&config)?;
+        let storage = runtime
+            .block_on(Storage::new(&indexer_config))
+            .map_err(|e| GeyserPluginError::ProcessingError(e.to_string()))?;
+        let ipfs_storage = IpfsStorage::new(&indexer_config.ipfs_api_url);
+
+        *self.processor.write() = Some(Processor::new(storage, ipfs_storage));
+        Self::spawn_event_loop(
+            runtime.handle(),
+            &event_queue,
+            &self.processor,
+            config.startup_batch_size,
+        );
+        self.event_queue = Some(event_queue);
+        self.runtime = Some(runtime);
+        self.accounts_filter = accounts_filter;
+        self.transactions_filter = transactions_filter;
+        self.config = Some(config);
+        Ok(())
+    }
+
     fn send_event(&self, event: GeyserEvent) -> Result<(), GeyserPluginError> {
-        self.event_sender.try_send(event).map_err(Into::into)
+        self.event_queue
+            .as_ref()
+            .ok_or(GeyserPluginError::NotLoaded)?
+            .push(event);
+        Ok(())
     }
 }
 
@@ -82,17 +289,49 @@ impl super::IngestionSource for GeyserPlugin {
     }
 }
 
-impl GeyserPlugin for GeyserPlugin {
+impl std::fmt::Debug for GeyserPlugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("GeyserPlugin")
+            .field("config", &self.config)
+            .field("loaded", &self.runtime.is_some())
+            .finish()
+    }
+}
+
+impl GeyserPluginInterface for GeyserPlugin {
     fn name(&self) -> &'static str {
         "CypherIndexerGeyserPlugin"
     }
 
     fn on_load(
         &mut self,
-        _config_file: &str,
+        config_file: &str,
+        _is_reload: bool,
     ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
-        // Initialize plugin
-        Ok(())
+        self.load(config_file).map_err(|e| InterfaceError::ConfigFileReadError {
+            msg: e.to_string(),
+        })
+    }
+
+    fn on_unload(&mut self) {
+        let event_queue = self.event_queue.take();
+        if let Some(runtime) = self.runtime.take() {
+            if let Some(event_queue) = event_queue {
+                event_queue.close();
+                runtime.block_on(async {
+                    let drained = async {
+                        while !event_queue.is_empty() {
+                            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                        }
+                    };
+                    if tokio::time::timeout(UNLOAD_DRAIN_TIMEOUT, drained).await.is_err() {
+                        tracing::warn!("Dropping {} queued Geyser events on unload", event_queue.len());
+                    }
+                });
+            }
+            runtime.shutdown_timeout(UNLOAD_DRAIN_TIMEOUT);
+        }
+        *self.processor.write() = None;
     }
 
     fn update_account(
@@ -101,13 +340,12 @@ impl GeyserPlugin for GeyserPlugin {
         slot: u64,
         is_startup: bool,
     ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
-        let account = Box::new(unsafe { std::mem::transmute(account) });
-        self.send_event(GeyserEvent::AccountUpdate(account, slot, is_startup))
-            .map_err(|e| {
-                solana_geyser_plugin_interface::geyser_plugin_interface::GeyserPluginError::Custom(
-                    Box::new(e),
-                )
-            })
+        if !self.is_account_selected(&account) {
+            return Ok(());
+        }
+        let account = AccountSnapshot::from_replica(&account, slot, is_startup);
+        self.send_event(GeyserEvent::AccountUpdate(account))
+            .map_err(|e| InterfaceError::Custom(Box::new(e)))
     }
 
     fn notify_transaction(
@@ -115,43 +353,69 @@ impl GeyserPlugin for GeyserPlugin {
         transaction: ReplicaTransactionInfoVersions,
         slot: u64,
     ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
-        let transaction = Box::new(unsafe { std::mem::transmute(transaction) });
-        self.send_event(GeyserEvent::TransactionNotify(transaction, slot))
-            .map_err(|e| {
-                solana_geyser_plugin_interface::geyser_plugin_interface::GeyserPluginError::Custom(
-                    Box::new(e),
-                )
-            })
+        if !self.is_transaction_selected(&transaction) {
+            return Ok(());
+        }
+        let transaction = Box::new(TransactionSnapshot::from_replica(&transaction, slot));
+        self.send_event(GeyserEvent::TransactionNotify(transaction))
+            .map_err(|e| InterfaceError::Custom(Box::new(e)))
     }
 
     fn notify_block_metadata(
         &self,
         blockinfo: ReplicaBlockInfoVersions,
     ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
-        let blockinfo = Box::new(unsafe { std::mem::transmute(blockinfo) });
-        self.send_event(GeyserEvent::BlockMetadata(blockinfo))
-            .map_err(|e| {
-                solana_geyser_plugin_interface::geyser_plugin_interface::GeyserPluginError::Custom(
-                    Box::new(e),
-                )
-            })
+        self.send_event(GeyserEvent::BlockMetadata(BlockSnapshot::from_replica(
+            &blockinfo,
+        )))
+        .map_err(|e| InterfaceError::Custom(Box::new(e)))
+    }
+
+    fn notify_entry(
+        &self,
+        entry: ReplicaEntryInfoVersions,
+    ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
+        self.send_event(GeyserEvent::Entry(EntrySnapshot::from_replica(&entry)))
+            .map_err(|e| InterfaceError::Custom(Box::new(e)))
+    }
+
+    fn notify_end_of_startup(
+        &self,
+    ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
+        self.send_event(GeyserEvent::EndOfStartup)
+            .map_err(|e| InterfaceError::Custom(Box::new(e)))
     }
 
     fn update_slot_status(
         &self,
         slot: u64,
         parent: Option<u64>,
-        status: SlotStatus,
+        status: GeyserSlotStatus,
     ) -> solana_geyser_plugin_interface::geyser_plugin_interface::Result<()> {
-        self.send_event(GeyserEvent::SlotStatusChange(slot, parent, status))
-            .map_err(|e| {
-                solana_geyser_plugin_interface::geyser_plugin_interface::GeyserPluginError::Custom(
-                    Box::new(e),
-                )
-            })
+        self.send_event(GeyserEvent::SlotStatusChange(slot, parent, (&status).into()))
+            .map_err(|e| InterfaceError::Custom(Box::new(e)))
+    }
+
+    fn account_data_notifications_enabled(&self) -> bool {
+        true
+    }
+
+    fn transaction_notifications_enabled(&self) -> bool {
+        self.transactions_filter.is_some()
     }
+}
 
-    fn on_unload(&mut self) {}
+/// Entry point looked up by the validator when it loads the `cdylib`.
+///
+/// # Safety
+///
+/// The returned pointer is owned by the validator, which frees it through the
+/// plugin manager when the plugin is unloaded.
+#[no_mangle]
+#[allow(improper_ctypes_definitions)]
+pub unsafe extern "C" fn _create_plugin() -> *mut dyn GeyserPluginInterface {
+    let plugin: Box<dyn GeyserPluginInterface> = Box::new(GeyserPlugin::default());
+    Box::into_raw(plugin)
 }
 
 #[cfg(test)]
@@ -159,10 +423,58 @@ mod tests {
     use super::*;
     use crate::config::Config;
 
-    #[test]
-    fn test_geyser_plugin_creation() {
+    #[tokio::test]
+    async fn test_geyser_plugin_creation() {
         let config = Config::default();
         let plugin = GeyserPlugin::new(&config);
         assert_eq!(plugin.name(), "CypherIndexerGeyserPlugin");
     }
+
+    #[test]
+    fn test_plugin_config_parsing() {
+        let path = std::env::temp_dir().join(format!(
+            "cypher_geyser_plugin_config_{}.json",
+            Pubkey::new_unique()
+        ));
+        std::fs::write(
+            &path,
+            r#"{
+                "libpath": "/path/to/libcypher_indexer.so",
+                "accounts_selector": {
+                    "owners": ["CyphrkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"],
+                    "filters": [{ "data_size": 165 }, { "memcmp": { "offset": 0, "bytes": "2" } }]
+                },
+                "transaction_selector": {
+                    "mentions": ["CyphrkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"],
+                    "include_failed": false
+                },
+                "output": { "indexer_config_path": "config.json", "worker_threads": 4 }
+            }"#,
+        )
+        .unwrap();
+
+        let config = GeyserPluginConfig::load_from(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.accounts_selector.owners.len(), 1);
+        assert_eq!(config.accounts_selector.filters.len(), 2);
+        let transaction_selector = config.transaction_selector.clone().unwrap();
+        assert_eq!(transaction_selector.mentions.len(), 1);
+        assert!(!transaction_selector.include_votes);
+        assert!(!transaction_selector.include_failed);
+        assert!(GeyserPlugin::build_filters(&config).is_ok());
+        let output = config.output.unwrap();
+        assert_eq!(output.indexer_config_path, "config.json");
+        assert_eq!(output.worker_threads, Some(4));
+    }
+
+    #[test]
+    fn test_unloaded_plugin_rejects_events() {
+        let plugin = GeyserPlugin::default();
+        let result = plugin.send_event(GeyserEvent::SlotStatusChange(
+            1,
+            None,
+            SlotStatus::Processed,
+        ));
+        assert!(matches!(result, Err(GeyserPluginError::NotLoaded)));
+    }
 }
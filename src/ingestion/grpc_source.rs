@@ -0,0 +1,703 @@
+use super::geyser_plugin::{GeyserEvent, GeyserPlugin};
+use super::snapshots::{
+    AccountSnapshot, BlockSnapshot, EntrySnapshot, SlotStatus, TransactionSnapshot,
+};
+use crate::config::{
+    AccountFilter, AccountsSelector, GrpcCommitment, GrpcSourceConfig, TransactionSelector,
+};
+use crate::processing::Processor;
+use crate::storage::Commitment;
+use async_trait::async_trait;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tonic::metadata::AsciiMetadataValue;
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use yellowstone_grpc_proto::convert_from::{create_reward, create_tx_meta, create_tx_versioned};
+use yellowstone_grpc_proto::geyser::geyser_client::GeyserClient;
+use yellowstone_grpc_proto::geyser::subscribe_request_filter_accounts_filter::Filter as AccountsFilterOneof;
+use yellowstone_grpc_proto::geyser::subscribe_request_filter_accounts_filter_memcmp::Data as MemcmpDataOneof;
+use yellowstone_grpc_proto::geyser::subscribe_update::UpdateOneof;
+use yellowstone_grpc_proto::geyser::{
+    CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts,
+    SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp,
+    SubscribeRequestFilterBlocks, SubscribeRequestFilterBlocksMeta, SubscribeRequestFilterEntry,
+    SubscribeRequestFilterSlots, SubscribeRequestFilterTransactions, SubscribeRequestPing,
+    SubscribeUpdate, SubscribeUpdateAccountInfo, SubscribeUpdateBlockMeta,
+    SubscribeUpdateTransactionInfo,
+};
+
+/// Name given to every filter in the subscribe request.
+const FILTER_NAME: &str = "cypher";
+const EVENT_CHANNEL_CAPACITY: usize = 10_000;
+/// A reconnect replays from this many slots before the last one seen, to
+/// cover updates for earlier slots still in flight when the stream dropped.
+const RESUME_MARGIN_SLOTS: u64 = 32;
+
+#[derive(Debug, Error)]
+pub enum GrpcSourceError {
+    #[error("Transport error: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("gRPC status: {0}")]
+    Status(#[from] tonic::Status),
+    #[error("Invalid x-token")]
+    InvalidToken,
+    #[error("Invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("Subscription stream ended")]
+    StreamEnded,
+    #[error("No pong for ping {0}")]
+    Stale(i32),
+    #[error("Event receiver closed")]
+    Closed,
+}
+
+/// Pings sent on the subscription stream. Each has its own id so the
+/// server's pong can be matched to it.
+#[derive(Debug, Default)]
+struct Keepalive {
+    last_id: i32,
+    awaiting: Option<i32>,
+}
+
+impl Keepalive {
+    /// The next ping, or `Stale` if the previous one was never answered.
+    fn ping(&mut self) -> Result<SubscribeRequest, GrpcSourceError> {
+        if let Some(id) = self.awaiting {
+            return Err(GrpcSourceError::Stale(id));
+        }
+        self.last_id = self.last_id.wrapping_add(1);
+        self.awaiting = Some(self.last_id);
+        Ok(SubscribeRequest {
+            ping: Some(SubscribeRequestPing { id: self.last_id }),
+            ..Default::default()
+        })
+    }
+
+    fn pong(&mut self, id: i32) {
+        if self.awaiting == Some(id) {
+            self.awaiting = None;
+        }
+    }
+}
+
+/// Streams from a Yellowstone gRPC endpoint and feeds the same events as the
+/// Geyser plugin into the processor.
+pub struct GrpcSource {
+    config: GrpcSourceConfig,
+    x_token: Option<AsciiMetadataValue>,
+}
+
+impl GrpcSource {
+    pub fn new(config: GrpcSourceConfig) -> Result<Self, GrpcSourceError> {
+        let x_token = config
+            .x_token
+            .as_deref()
+            .map(|token| token.parse().map_err(|_| GrpcSourceError::InvalidToken))
+            .transpose()?;
+        Ok(Self { config, x_token })
+    }
+
+    /// The commitment updates are streamed at.
+    pub fn commitment(&self) -> Commitment {
+        match self.config.commitment {
+            GrpcCommitment::Processed => Commitment::Processed,
+            GrpcCommitment::Confirmed => Commitment::Confirmed,
+            GrpcCommitment::Finalized => Commitment::Finalized,
+        }
+    }
+
+    fn commitment_level(&self) -> CommitmentLevel {
+        match self.config.commitment {
+            GrpcCommitment::Processed => CommitmentLevel::Processed,
+            GrpcCommitment::Confirmed => CommitmentLevel::Confirmed,
+            GrpcCommitment::Finalized => CommitmentLevel::Finalized,
+        }
+    }
+
+    /// The subscription for the configured selectors, replaying from
+    /// `from_slot` after a reconnect.
+    fn subscribe_request(&self, from_slot: Option<u64>) -> SubscribeRequest {
+        let mut request = SubscribeRequest {
+            commitment: Some(self.commitment_level() as i32),
+            from_slot,
+            ..Default::default()
+        };
+
+        if let Some(filter) = accounts_filter(&self.config.accounts_selector) {
+            request.accounts.insert(FILTER_NAME.to_string(), filter);
+        }
+        if let Some(selector) = &self.config.transaction_selector {
+            request
+                .transactions
+                .insert(FILTER_NAME.to_string(), transactions_filter(selector));
+        }
+        if self.config.slots {
+            request.slots.insert(
+                FILTER_NAME.to_string(),
+                SubscribeRequestFilterSlots {
+                    filter_by_commitment: Some(false),
+                    ..Default::default()
+                },
+            );
+        }
+        if self.config.blocks {
+            request.blocks.insert(
+                FILTER_NAME.to_string(),
+                SubscribeRequestFilterBlocks {
+                    include_transactions: Some(true),
+                    ..Default::default()
+                },
+            );
+        }
+        if self.config.blocks_meta {
+            request
+                .blocks_meta
+                .insert(FILTER_NAME.to_string(), SubscribeRequestFilterBlocksMeta::default());
+        }
+        if self.config.entries {
+            request
+                .entry
+                .insert(FILTER_NAME.to_string(), SubscribeRequestFilterEntry::default());
+        }
+        request
+    }
+
+    /// Runs one subscription until the stream ends, sending converted events
+    /// to `events`. `last_slot` carries the highest slot seen at the
+    /// subscribed commitment across reconnects.
+    async fn run_session(
+        &self,
+        events: &mpsc::Sender<GeyserEvent>,
+        last_slot: &mut Option<u64>,
+    ) -> Result<(), GrpcSourceError> {
+        let mut endpoint = Endpoint::from_shared(self.config.endpoint.clone())?;
+        if self.config.endpoint.starts_with("https") {
+            endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+        }
+        let channel = endpoint.connect().await?;
+
+        let x_token = self.x_token.clone();
+        let mut client =
+            GeyserClient::with_interceptor(channel, move |mut request: tonic::Request<()>| {
+                if let Some(x_token) = &x_token {
+                    request.metadata_mut().insert("x-token", x_token.clone());
+                }
+                Ok(request)
+            });
+
+        let from_slot = last_slot.map(|slot| slot.saturating_sub(RESUME_MARGIN_SLOTS));
+        let (requests, request_stream) = mpsc::channel(16);
+        requests
+            .send(self.subscribe_request(from_slot))
+            .await
+            .map_err(|_| GrpcSourceError::Closed)?;
+        let mut updates = client
+            .subscribe(ReceiverStream::new(request_stream))
+            .await?
+            .into_inner();
+        tracing::info!("Subscribed to gRPC stream at {}", self.config.endpoint);
+
+        let ping_interval = Duration::from_secs(self.config.ping_interval_secs.max(1));
+        let mut pings = tokio::time::interval_at(Instant::now() + ping_interval, ping_interval);
+        let mut keepalive = Keepalive::default();
+        loop {
+            let update = tokio::select! {
+                update = updates.message() => match update? {
+                    Some(update) => update,
+                    None => break,
+                },
+                _ = pings.tick() => {
+                    let _ = requests.send(keepalive.ping()?).await;
+                    continue;
+                }
+            };
+            match update.update_oneof {
+                // Load balancers drop streams that look idle, so answer the
+                // server's pings unless one of ours is already in flight.
+                Some(UpdateOneof::Ping(_)) => {
+                    if keepalive.awaiting.is_none() {
+                        let _ = requests.send(keepalive.ping()?).await;
+                    }
+                    continue;
+                }
+                Some(UpdateOneof::Pong(pong)) => {
+                    keepalive.pong(pong.id);
+                    continue;
+                }
+                _ => {}
+            }
+
+            if let Some(slot) = update_slot(&update, self.commitment_level()) {
+                *last_slot = Some(last_slot.map_or(slot, |last| last.max(slot)));
+            }
+            match events_from_update(update) {
+                Ok(converted) => {
+                    for event in converted {
+                        events.send(event).await.map_err(|_| GrpcSourceError::Closed)?;
+                    }
+                }
+                Err(e) => tracing::warn!("Dropping gRPC update: {}", e),
+            }
+        }
+
+        Err(GrpcSourceError::StreamEnded)
+    }
+}
+
+#[async_trait]
+impl super::IngestionSource for GrpcSource {
+    async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
+        let (events, mut receiver) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        tokio::spawn(async move {
+            while let Some(event) = receiver.recv().await {
+                if let Err(e) = GeyserPlugin::dispatch_event(&processor, event).await {
+                    tracing::error!("Failed to process gRPC event: {:?}", e);
+                }
+            }
+        });
+
+        let initial_backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let max_backoff = Duration::from_millis(self.config.max_backoff_ms);
+        let mut backoff = initial_backoff;
+        let mut last_slot = None;
+
+        loop {
+            let started = Instant::now();
+            match self.run_session(&events, &mut last_slot).await {
+                Err(GrpcSourceError::Closed) | Ok(()) => return Ok(()),
+                Err(e) => tracing::warn!("gRPC stream from {} lost: {}", self.config.endpoint, e),
+            }
+            metrics::counter!("cypher_grpc_reconnects_total").increment(1);
+
+            if started.elapsed() >= max_backoff {
+                backoff = initial_backoff;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(max_backoff);
+        }
+    }
+}
+
+fn accounts_filter(selector: &AccountsSelector) -> Option<SubscribeRequestFilterAccounts> {
+    if selector.accounts.is_empty() && selector.owners.is_empty() && selector.filters.is_empty() {
+        return None;
+    }
+    // Empty account and owner lists select every account on the server.
+    let wildcard = selector.accounts.iter().any(|account| account == "*");
+    Some(SubscribeRequestFilterAccounts {
+        account: if wildcard { Vec::new() } else { selector.accounts.clone() },
+        owner: if wildcard { Vec::new() } else { selector.owners.clone() },
+        filters: selector.filters.iter().map(account_filter).collect(),
+        ..Default::default()
+    })
+}
+
+fn account_filter(filter: &AccountFilter) -> SubscribeRequestFilterAccountsFilter {
+    let filter = match filter {
+        AccountFilter::DataSize(size) => AccountsFilterOneof::Datasize(*size),
+        AccountFilter::Memcmp { offset, bytes } => {
+            AccountsFilterOneof::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                offset: *offset as u64,
+                data: Some(MemcmpDataOneof::Base58(bytes.clone())),
+            })
+        }
+    };
+    SubscribeRequestFilterAccountsFilter {
+        filter: Some(filter),
+    }
+}
+
+fn transactions_filter(selector: &TransactionSelector) -> SubscribeRequestFilterTransactions {
+    let wildcard = selector.mentions.iter().any(|mention| mention == "*");
+    let account_include = if wildcard {
+        Vec::new()
+    } else {
+        selector
+            .mentions
+            .iter()
+            .chain(&selector.program_ids)
+            .cloned()
+            .collect()
+    };
+    SubscribeRequestFilterTransactions {
+        vote: (!selector.include_votes).then_some(false),
+        failed: (!selector.include_failed).then_some(false),
+        account_include,
+        ..Default::default()
+    }
+}
+
+/// The slot a reconnect can resume from after `update`. Slot updates arrive
+/// at every commitment and entries are not filtered by it, so only data
+/// updates and slot updates at `commitment` count.
+fn update_slot(update: &SubscribeUpdate, commitment: CommitmentLevel) -> Option<u64> {
+    match update.update_oneof.as_ref()? {
+        UpdateOneof::Account(account) => Some(account.slot),
+        UpdateOneof::Slot(slot) => (slot.status == commitment as i32).then_some(slot.slot),
+        UpdateOneof::Transaction(transaction) => Some(transaction.slot),
+        UpdateOneof::Block(block) => Some(block.slot),
+        UpdateOneof::BlockMeta(block_meta) => Some(block_meta.slot),
+        _ => None,
+    }
+}
+
+/// Maps a stream update onto the Geyser plugin's event model. Full blocks
+/// become one event per transaction followed by the block metadata.
+fn events_from_update(update: SubscribeUpdate) -> Result<Vec<GeyserEvent>, GrpcSourceError> {
+    let Some(update) = update.update_oneof else {
+        return Ok(Vec::new());
+    };
+    let events = match update {
+        UpdateOneof::Account(update) => match update.account {
+            Some(account) => vec![GeyserEvent::AccountUpdate(account_snapshot(
+                account,
+                update.slot,
+                update.is_startup,
+            )?)],
+            None => Vec::new(),
+        },
+        UpdateOneof::Transaction(update) => match update.transaction {
+            Some(transaction) => vec![GeyserEvent::TransactionNotify(Box::new(
+                transaction_snapshot(transaction, update.slot)?,
+            ))],
+            None => Vec::new(),
+        },
+        UpdateOneof::Slot(update) => {
+            let status = match CommitmentLevel::try_from(update.status) {
+                Ok(CommitmentLevel::Processed) => SlotStatus::Processed,
+                Ok(CommitmentLevel::Confirmed) => SlotStatus::Confirmed,
+                Ok(CommitmentLevel::Finalized) => SlotStatus::Rooted,
+                Err(_) => {
+                    return Err(GrpcSourceError::InvalidMessage(format!(
+                        "unknown slot status {}",
+                        update.status
+                    )))
+                }
+            };
+            vec![GeyserEvent::SlotStatusChange(update.slot, update.parent, status)]
+        }
+        UpdateOneof::BlockMeta(block_meta) => {
+            vec![GeyserEvent::BlockMetadata(block_snapshot(block_meta)?)]
+        }
+        UpdateOneof::Block(block) => {
+            let mut events = block
+                .transactions
+                .into_iter()
+                .map(|transaction| {
+                    transaction_snapshot(transaction, block.slot)
+                        .map(|snapshot| GeyserEvent::TransactionNotify(Box::new(snapshot)))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            events.push(GeyserEvent::BlockMetadata(block_snapshot(
+                SubscribeUpdateBlockMeta {
+                    slot: block.slot,
+                    blockhash: block.blockhash,
+                    rewards: block.rewards,
+                    block_time: block.block_time,
+                    block_height: block.block_height,
+                    parent_slot: block.parent_slot,
+                    parent_blockhash: block.parent_blockhash,
+                    executed_transaction_count: block.executed_transaction_count,
+                    entries_count: block.entries_count,
+                    ..Default::default()
+                },
+            )?));
+            events
+        }
+        UpdateOneof::Entry(entry) => vec![GeyserEvent::Entry(EntrySnapshot {
+            slot: entry.slot,
+            index: entry.index as usize,
+            num_hashes: entry.num_hashes,
+            hash: entry.hash,
+            executed_transaction_count: entry.executed_transaction_count,
+            starting_transaction_index: Some(entry.starting_transaction_index as usize),
+        })],
+        _ => Vec::new(),
+    };
+    Ok(events)
+}
+
+fn pubkey(bytes: &[u8]) -> Result<Pubkey, GrpcSourceError> {
+    Pubkey::try_from(bytes)
+        .map_err(|_| GrpcSourceError::InvalidMessage(format!("invalid pubkey {:?}", bytes)))
+}
+
+fn signature(bytes: &[u8]) -> Result<Signature, GrpcSourceError> {
+    Signature::try_from(bytes)
+        .map_err(|_| GrpcSourceError::InvalidMessage("invalid signature".to_string()))
+}
+
+fn account_snapshot(
+    account: SubscribeUpdateAccountInfo,
+    slot: u64,
+    is_startup: bool,
+) -> Result<AccountSnapshot, GrpcSourceError> {
+    Ok(AccountSnapshot {
+        pubkey: pubkey(&account.pubkey)?,
+        owner: pubkey(&account.owner)?,
+        lamports: account.lamports,
+        executable: account.executable,
+        rent_epoch: account.rent_epoch,
+        data: account.data,
+        write_version: account.write_version,
+        txn_signature: account
+            .txn_signature
+            .as_deref()
+            .map(signature)
+            .transpose()?,
+        slot,
+        is_startup,
+    })
+}
+
+fn transaction_snapshot(
+    info: SubscribeUpdateTransactionInfo,
+    slot: u64,
+) -> Result<TransactionSnapshot, GrpcSourceError> {
+    let invalid = |e: &str| GrpcSourceError::InvalidMessage(e.to_string());
+    let transaction = info.transaction.ok_or_else(|| invalid("missing transaction"))?;
+    let meta = info.meta.ok_or_else(|| invalid("missing transaction meta"))?;
+    Ok(TransactionSnapshot {
+        signature: signature(&info.signature)?,
+        is_vote: info.is_vote,
+        index: Some(info.index as usize),
+        transaction: create_tx_versioned(transaction).map_err(invalid)?,
+        meta: create_tx_meta(meta).map_err(invalid)?,
+        slot,
+    })
+}
+
+fn block_snapshot(block: SubscribeUpdateBlockMeta) -> Result<BlockSnapshot, GrpcSourceError> {
+    let (rewards, num_reward_partitions) = match block.rewards {
+        Some(rewards) => (
+            rewards
+                .rewards
+                .iter()
+                .map(create_reward)
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| GrpcSourceError::InvalidMessage(e.to_string()))?,
+            rewards.num_partitions.map(|p| p.num_partitions),
+        ),
+        None => (Vec::new(), None),
+    };
+    Ok(BlockSnapshot {
+        slot: block.slot,
+        blockhash: block.blockhash,
+        parent_slot: Some(block.parent_slot),
+        parent_blockhash: Some(block.parent_blockhash),
+        rewards,
+        num_reward_partitions,
+        block_time: block.block_time.map(|t| t.timestamp),
+        block_height: block.block_height.map(|h| h.block_height),
+        executed_transaction_count: Some(block.executed_transaction_count),
+        entry_count: Some(block.entries_count),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::stream::{self, Stream};
+    use parking_lot::Mutex;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+    use tonic::{Request, Response, Status, Streaming};
+    use yellowstone_grpc_proto::geyser::geyser_server::{Geyser, GeyserServer};
+    use yellowstone_grpc_proto::geyser::{
+        GetBlockHeightRequest, GetBlockHeightResponse, GetLatestBlockhashRequest,
+        GetLatestBlockhashResponse, GetSlotRequest, GetSlotResponse, GetVersionRequest,
+        GetVersionResponse, IsBlockhashValidRequest, IsBlockhashValidResponse, PingRequest,
+        PongResponse, SubscribeUpdateAccount, SubscribeUpdatePing, SubscribeUpdateSlot,
+    };
+
+    /// Replays a fixed list of updates to every subscriber and records the
+    /// subscribe requests it receives.
+    struct MockGeyser {
+        updates: Vec<SubscribeUpdate>,
+        requests: Arc<Mutex<Vec<SubscribeRequest>>>,
+    }
+
+    #[tonic::async_trait]
+    impl Geyser for MockGeyser {
+        type SubscribeStream =
+            Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send + 'static>>;
+
+        async fn subscribe(
+            &self,
+            request: Request<Streaming<SubscribeRequest>>,
+        ) -> Result<Response<Self::SubscribeStream>, Status> {
+            let mut requests = request.into_inner();
+            if let Some(request) = requests.message().await? {
+                self.requests.lock().push(request);
+            }
+            let updates = self.updates.clone().into_iter().map(Ok);
+            Ok(Response::new(Box::pin(stream::iter(updates))))
+        }
+
+        async fn ping(&self, _: Request<PingRequest>) -> Result<Response<PongResponse>, Status> {
+            Err(Status::unimplemented("ping"))
+        }
+
+        async fn get_latest_blockhash(
+            &self,
+            _: Request<GetLatestBlockhashRequest>,
+        ) -> Result<Response<GetLatestBlockhashResponse>, Status> {
+            Err(Status::unimplemented("get_latest_blockhash"))
+        }
+
+        async fn get_block_height(
+            &self,
+            _: Request<GetBlockHeightRequest>,
+        ) -> Result<Response<GetBlockHeightResponse>, Status> {
+            Err(Status::unimplemented("get_block_height"))
+        }
+
+        async fn get_slot(
+            &self,
+            _: Request<GetSlotRequest>,
+        ) -> Result<Response<GetSlotResponse>, Status> {
+            Err(Status::unimplemented("get_slot"))
+        }
+
+        async fn is_blockhash_valid(
+            &self,
+            _: Request<IsBlockhashValidRequest>,
+        ) -> Result<Response<IsBlockhashValidResponse>, Status> {
+            Err(Status::unimplemented("is_blockhash_valid"))
+        }
+
+        async fn get_version(
+            &self,
+            _: Request<GetVersionRequest>,
+        ) -> Result<Response<GetVersionResponse>, Status> {
+            Err(Status::unimplemented("get_version"))
+        }
+    }
+
+    fn update(update: UpdateOneof) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(update),
+            ..Default::default()
+        }
+    }
+
+    fn config(endpoint: String) -> GrpcSourceConfig {
+        serde_json::from_value(serde_json::json!({
+            "endpoint": endpoint,
+            "accounts_selector": { "owners": [Pubkey::default().to_string()] },
+        }))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_session_against_mock_server() {
+        let account = Pubkey::new_unique();
+        let updates = vec![
+            update(UpdateOneof::Ping(SubscribeUpdatePing {})),
+            update(UpdateOneof::Account(SubscribeUpdateAccount {
+                account: Some(SubscribeUpdateAccountInfo {
+                    pubkey: account.to_bytes().to_vec(),
+                    owner: Pubkey::default().to_bytes().to_vec(),
+                    lamports: 42,
+                    data: vec![1, 2, 3],
+                    ..Default::default()
+                }),
+                slot: 100,
+                is_startup: false,
+            })),
+            update(UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: 101,
+                parent: Some(100),
+                status: CommitmentLevel::Confirmed as i32,
+                ..Default::default()
+            })),
+            // Ahead of the subscribed commitment, so not a resume point.
+            update(UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: 105,
+                parent: Some(104),
+                status: CommitmentLevel::Processed as i32,
+                ..Default::default()
+            })),
+        ];
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let mock = MockGeyser {
+            updates,
+            requests: requests.clone(),
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(GeyserServer::new(mock))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let source = GrpcSource::new(config(format!("http://{}", address))).unwrap();
+        let (events, mut receiver) = mpsc::channel(16);
+        let mut last_slot = None;
+        let result = source.run_session(&events, &mut last_slot).await;
+        assert!(matches!(result, Err(GrpcSourceError::StreamEnded)));
+        assert_eq!(last_slot, Some(101));
+
+        match receiver.recv().await.unwrap() {
+            GeyserEvent::AccountUpdate(snapshot) => {
+                assert_eq!(snapshot.pubkey, account);
+                assert_eq!(snapshot.lamports, 42);
+                assert_eq!(snapshot.data, vec![1, 2, 3]);
+                assert_eq!(snapshot.slot, 100);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert!(matches!(
+            receiver.recv().await.unwrap(),
+            GeyserEvent::SlotStatusChange(101, Some(100), SlotStatus::Confirmed)
+        ));
+        assert!(matches!(
+            receiver.recv().await.unwrap(),
+            GeyserEvent::SlotStatusChange(105, Some(104), SlotStatus::Processed)
+        ));
+
+        // A reconnect resumes a margin before the last confirmed slot.
+        source.run_session(&events, &mut last_slot).await.unwrap_err();
+        let requests = requests.lock();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].from_slot, None);
+        assert_eq!(requests[1].from_slot, Some(101 - RESUME_MARGIN_SLOTS));
+        assert!(requests[0].accounts.contains_key(FILTER_NAME));
+        assert!(requests[0].slots.contains_key(FILTER_NAME));
+    }
+
+    #[test]
+    fn test_keepalive() {
+        let ping_id = |request: SubscribeRequest| request.ping.unwrap().id;
+        let mut keepalive = Keepalive::default();
+
+        assert_eq!(ping_id(keepalive.ping().unwrap()), 1);
+        // A pong for some other ping does not count.
+        keepalive.pong(7);
+        assert!(matches!(keepalive.ping(), Err(GrpcSourceError::Stale(1))));
+
+        keepalive.pong(1);
+        assert_eq!(ping_id(keepalive.ping().unwrap()), 2);
+    }
+
+    #[test]
+    fn test_transactions_filter() {
+        let selector: TransactionSelector = serde_json::from_value(serde_json::json!({
+            "mentions": ["A"],
+            "program_ids": ["B"],
+        }))
+        .unwrap();
+        let filter = transactions_filter(&selector);
+        assert_eq!(filter.account_include, vec!["A".to_string(), "B".to_string()]);
+        assert_eq!(filter.vote, Some(false));
+        assert_eq!(filter.failed, None);
+    }
+}
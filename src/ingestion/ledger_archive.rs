@@ -0,0 +1,374 @@
+use super::backfill::BackfillReport;
+use crate::config::LedgerArchiveConfig;
+use crate::processing::Processor;
+use serde::{Deserialize, Serialize};
+use solana_bigtable_connection::{
+    bigtable::{self, BigTableConnection, CellData, RowKey},
+    CredentialType,
+};
+use solana_sdk::clock::UnixTimestamp;
+use solana_sdk::deserialize_utils::default_on_eof;
+use solana_sdk::message::v0::LoadedAddresses;
+use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+use solana_storage_proto::convert::generated;
+use solana_transaction_status::{
+    BlockEncodingOptions, ConfirmedBlock, EncodeError, Reward, TransactionDetails,
+    TransactionStatusMeta, TransactionWithStatusMeta, UiConfirmedBlock, UiTransactionEncoding,
+    VersionedTransactionWithStatusMeta,
+};
+use std::time::Duration;
+use thiserror::Error;
+
+const BLOCKS_TABLE: &str = "blocks";
+
+#[derive(Debug, Error)]
+pub enum LedgerArchiveError {
+    #[error("Bigtable error: {0}")]
+    Bigtable(#[from] bigtable::Error),
+    #[error("Corrupt block in slot {0}")]
+    CorruptBlock(u64),
+    #[error("Encode error: {0}")]
+    Encode(#[from] EncodeError),
+    #[error("Processing error: {0}")]
+    Processing(String),
+    #[error("Invalid range: {0}")]
+    InvalidRange(String),
+}
+
+/// Reads confirmed blocks from a Solana ledger-archive Bigtable instance (the
+/// `blocks` table written by `solana-ledger-tool bigtable upload`). Setting
+/// `BIGTABLE_EMULATOR_HOST` connects to the emulator instead.
+pub struct LedgerArchive {
+    connection: BigTableConnection,
+    batch_size: usize,
+}
+
+impl LedgerArchive {
+    pub async fn new(config: &LedgerArchiveConfig) -> Result<Self, LedgerArchiveError> {
+        let connection = BigTableConnection::new(
+            &config.instance_name,
+            &config.app_profile_id,
+            true,
+            config.timeout_secs.map(Duration::from_secs),
+            CredentialType::Filepath(config.credential_path.clone()),
+        )
+        .await?;
+
+        Ok(Self {
+            connection,
+            batch_size: config.batch_size.max(1),
+        })
+    }
+
+    /// Processes every archived block in `start..=end` in slot order.
+    pub async fn run(
+        &self,
+        processor: &Processor,
+        start: u64,
+        end: u64,
+    ) -> Result<BackfillReport, LedgerArchiveError> {
+        if start > end {
+            return Err(LedgerArchiveError::InvalidRange(format!(
+                "start slot {} is after end slot {}",
+                start, end
+            )));
+        }
+
+        let mut report = BackfillReport::default();
+        let mut next = start;
+        while next <= end {
+            let slots = self.confirmed_slots(next, end).await?;
+            let Some(&last) = slots.last() else {
+                break;
+            };
+
+            for (slot, block) in self.fetch_blocks(&slots).await? {
+                let transactions = block.transactions.as_ref().map_or(0, Vec::len);
+                processor
+                    .process_block(block, slot)
+                    .await
+                    .map_err(|e| LedgerArchiveError::Processing(e.to_string()))?;
+                report.blocks += 1;
+                report.transactions += transactions as u64;
+            }
+            tracing::info!("Backfilled slots {}..={} from Bigtable", next, last);
+            next = last + 1;
+        }
+        report.skipped_slots = (end - start + 1) - report.blocks;
+
+        Ok(report)
+    }
+
+    /// Slots in `start..=end` that have a block, up to one batch.
+    async fn confirmed_slots(&self, start: u64, end: u64) -> Result<Vec<u64>, LedgerArchiveError> {
+        let mut client = self.connection.client();
+        let keys = client
+            .get_row_keys(
+                BLOCKS_TABLE,
+                Some(slot_to_key(start)),
+                Some(slot_to_key(end)),
+                self.batch_size as i64,
+            )
+            .await?;
+        Ok(keys.iter().filter_map(|key| key_to_slot(key)).collect())
+    }
+
+    async fn fetch_blocks(
+        &self,
+        slots: &[u64],
+    ) -> Result<Vec<(u64, UiConfirmedBlock)>, LedgerArchiveError> {
+        let mut client = self.connection.client();
+        let cells = client
+            .get_protobuf_or_bincode_cells::<StoredConfirmedBlock, generated::ConfirmedBlock>(
+                BLOCKS_TABLE,
+                slots.iter().copied().map(slot_to_key),
+            )
+            .await?;
+
+        let mut blocks = Vec::with_capacity(slots.len());
+        for (key, cell) in cells {
+            let slot = key_to_slot(&key).ok_or_else(|| {
+                LedgerArchiveError::InvalidRange(format!("invalid block row key {}", key))
+            })?;
+            blocks.push((slot, decode_block(slot, cell)?));
+        }
+        blocks.sort_by_key(|(slot, _)| *slot);
+        Ok(blocks)
+    }
+}
+
+/// Row keys are zero-padded hex so they sort in slot order.
+fn slot_to_key(slot: u64) -> RowKey {
+    format!("{:016x}", slot)
+}
+
+fn key_to_slot(key: &str) -> Option<u64> {
+    u64::from_str_radix(key, 16).ok()
+}
+
+fn decode_block(
+    slot: u64,
+    cell: CellData<StoredConfirmedBlock, generated::ConfirmedBlock>,
+) -> Result<UiConfirmedBlock, LedgerArchiveError> {
+    let block: ConfirmedBlock = match cell {
+        CellData::Bincode(block) => block
+            .try_into()
+            .map_err(|_| LedgerArchiveError::CorruptBlock(slot))?,
+        CellData::Protobuf(block) => block
+            .try_into()
+            .map_err(|_| LedgerArchiveError::CorruptBlock(slot))?,
+    };
+    Ok(block.encode_with_options(
+        UiTransactionEncoding::Json,
+        BlockEncodingOptions {
+            transaction_details: TransactionDetails::Full,
+            show_rewards: false,
+            max_supported_transaction_version: Some(0),
+        },
+    )?)
+}
+
+/// Legacy bincode layout of the `blocks` table, still found in older rows.
+/// Newer rows are protobuf.
+#[derive(Serialize, Deserialize)]
+struct StoredConfirmedBlock {
+    previous_blockhash: String,
+    blockhash: String,
+    parent_slot: u64,
+    transactions: Vec<StoredConfirmedBlockTransaction>,
+    rewards: Vec<StoredConfirmedBlockReward>,
+    block_time: Option<UnixTimestamp>,
+    #[serde(deserialize_with = "default_on_eof")]
+    block_height: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredConfirmedBlockTransaction {
+    transaction: VersionedTransaction,
+    meta: Option<StoredConfirmedBlockTransactionStatusMeta>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredConfirmedBlockTransactionStatusMeta {
+    err: Option<TransactionError>,
+    fee: u64,
+    pre_balances: Vec<u64>,
+    post_balances: Vec<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct StoredConfirmedBlockReward {
+    pubkey: String,
+    lamports: i64,
+}
+
+/// A versioned transaction stored without metadata, which the legacy layout
+/// never wrote.
+#[derive(Debug)]
+struct MissingMeta;
+
+impl TryFrom<StoredConfirmedBlock> for ConfirmedBlock {
+    type Error = MissingMeta;
+
+    fn try_from(block: StoredConfirmedBlock) -> Result<Self, MissingMeta> {
+        Ok(Self {
+            previous_blockhash: block.previous_blockhash,
+            blockhash: block.blockhash,
+            parent_slot: block.parent_slot,
+            transactions: block
+                .transactions
+                .into_iter()
+                .map(TryInto::try_into)
+                .collect::<Result<_, _>>()?,
+            rewards: block
+                .rewards
+                .into_iter()
+                .map(|reward| Reward {
+                    pubkey: reward.pubkey,
+                    lamports: reward.lamports,
+                    post_balance: 0,
+                    reward_type: None,
+                    commission: None,
+                })
+                .collect(),
+            num_partitions: None,
+            block_time: block.block_time,
+            block_height: block.block_height,
+        })
+    }
+}
+
+impl TryFrom<StoredConfirmedBlockTransaction> for TransactionWithStatusMeta {
+    type Error = MissingMeta;
+
+    fn try_from(stored: StoredConfirmedBlockTransaction) -> Result<Self, MissingMeta> {
+        Ok(match stored.meta {
+            // Legacy rows only lack metadata for legacy transactions.
+            None => Self::MissingMetadata(
+                stored
+                    .transaction
+                    .into_legacy_transaction()
+                    .ok_or(MissingMeta)?,
+            ),
+            Some(meta) => Self::Complete(VersionedTransactionWithStatusMeta {
+                transaction: stored.transaction,
+                meta: TransactionStatusMeta {
+                    status: meta.err.map_or(Ok(()), Err),
+                    fee: meta.fee,
+                    pre_balances: meta.pre_balances,
+                    post_balances: meta.post_balances,
+                    inner_instructions: None,
+                    log_messages: None,
+                    pre_token_balances: None,
+                    post_token_balances: None,
+                    rewards: None,
+                    loaded_addresses: LoadedAddresses::default(),
+                    return_data: None,
+                    compute_units_consumed: None,
+                },
+            }),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::pubkey::Pubkey;
+    use solana_sdk::transaction::Transaction;
+
+    fn stored_block(transactions: Vec<StoredConfirmedBlockTransaction>) -> StoredConfirmedBlock {
+        StoredConfirmedBlock {
+            previous_blockhash: "11111111111111111111111111111111".to_string(),
+            blockhash: "4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn".to_string(),
+            parent_slot: 41,
+            transactions,
+            rewards: vec![],
+            block_time: Some(1_700_000_000),
+            block_height: Some(40),
+        }
+    }
+
+    fn stored_transfer() -> StoredConfirmedBlockTransaction {
+        let transaction = Transaction::new_with_payer(&[], Some(&Pubkey::new_unique()));
+        StoredConfirmedBlockTransaction {
+            transaction: VersionedTransaction::from(transaction),
+            meta: Some(StoredConfirmedBlockTransactionStatusMeta {
+                err: None,
+                fee: 5000,
+                pre_balances: vec![10_000],
+                post_balances: vec![5000],
+            }),
+        }
+    }
+
+    #[test]
+    fn test_slot_keys_sort_in_slot_order() {
+        assert_eq!(slot_to_key(255), "00000000000000ff");
+        assert_eq!(key_to_slot(&slot_to_key(123_456_789)), Some(123_456_789));
+        assert!(slot_to_key(9) < slot_to_key(10));
+        assert_eq!(key_to_slot("not-a-slot"), None);
+    }
+
+    #[test]
+    fn test_decode_legacy_bincode_block() {
+        let stored = stored_block(vec![stored_transfer()]);
+        let stored: StoredConfirmedBlock =
+            bincode::deserialize(&bincode::serialize(&stored).unwrap()).unwrap();
+
+        let block = decode_block(42, CellData::Bincode(stored)).unwrap();
+        assert_eq!(block.parent_slot, 41);
+        assert_eq!(block.block_height, Some(40));
+        let transactions = block.transactions.unwrap();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].meta.as_ref().unwrap().fee, 5000);
+    }
+
+    #[test]
+    fn test_versioned_transaction_without_meta_is_corrupt() {
+        let stored = stored_block(vec![StoredConfirmedBlockTransaction {
+            transaction: VersionedTransaction {
+                signatures: vec![],
+                message: VersionedMessage::V0(v0::Message::default()),
+            },
+            meta: None,
+        }]);
+
+        assert!(matches!(
+            decode_block(42, CellData::Bincode(stored)),
+            Err(LedgerArchiveError::CorruptBlock(42))
+        ));
+    }
+
+    /// Runs against the Bigtable emulator when `BIGTABLE_EMULATOR_HOST` is
+    /// set, which needs a `blocks` table with an `x` column family.
+    #[tokio::test]
+    async fn test_fetch_blocks_from_emulator() {
+        if std::env::var("BIGTABLE_EMULATOR_HOST").is_err() {
+            return;
+        }
+        let archive = LedgerArchive::new(&LedgerArchiveConfig::default())
+            .await
+            .unwrap();
+        let mut client = archive.connection.client();
+        client
+            .put_bincode_cells_with_retry(
+                BLOCKS_TABLE,
+                &[
+                    (slot_to_key(1_000), stored_block(vec![stored_transfer()])),
+                    (slot_to_key(1_002), stored_block(vec![])),
+                ],
+            )
+            .await
+            .unwrap();
+
+        let slots = archive.confirmed_slots(1_000, 1_002).await.unwrap();
+        assert_eq!(slots, vec![1_000, 1_002]);
+        let blocks = archive.fetch_blocks(&slots).await.unwrap();
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].0, 1_000);
+        assert_eq!(blocks[0].1.transactions.as_ref().unwrap().len(), 1);
+        assert_eq!(blocks[1].1.transactions.as_ref().unwrap().len(), 0);
+    }
+}
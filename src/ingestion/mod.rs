@@ -1,10 +1,32 @@
+mod account_subscriber;
+pub mod backfill;
+mod block_subscriber;
+mod dedup;
+mod endpoint_pool;
+mod event_queue;
 mod geyser_plugin;
+mod grpc_source;
+mod ledger_archive;
+mod rate_limiter;
+pub mod recording;
+mod rpc_errors;
 mod rpc_poller;
+pub mod selectors;
+pub mod snapshots;
+mod subscription;
 mod websocket_listener;
 
+pub use account_subscriber::AccountSubscriber;
+pub use backfill::{Backfill, BackfillRange, BackfillReport, BackfillSource};
+pub use block_subscriber::BlockSubscriber;
+pub use dedup::{AccountVersion, Deduplicator};
+pub use endpoint_pool::{Endpoint, EndpointPool};
 pub use geyser_plugin::GeyserPlugin;
-pub use rpc_poller::RpcPoller;
-pub use websocket_listener::WebsocketListener;
+pub use grpc_source::GrpcSource;
+pub use ledger_archive::LedgerArchive;
+pub use recording::{Recorder, ReplaySource};
+pub use rpc_poller::{RpcPoller, RPC_POLLER_CHECKPOINT};
+pub use websocket_listener::{SlotGap, WebsocketListener};
 
 use async_trait::async_trait;
 use crate::processing::Processor;
@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Token bucket shared by all requests to one RPC endpoint. Holds up to
+/// `burst` tokens and refills at `requests_per_second`.
+pub struct RateLimiter {
+    requests_per_second: f64,
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_second: u32, burst: u32) -> Self {
+        let burst = burst.max(1) as f64;
+        Self {
+            requests_per_second: requests_per_second.max(1) as f64,
+            burst,
+            state: Mutex::new(BucketState {
+                tokens: burst,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().await;
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.last_refill).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.requests_per_second).min(self.burst);
+                state.last_refill = now;
+
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.tokens) / self.requests_per_second)
+            };
+            tokio::time::sleep(wait).await;
+        }
+    }
+
+    /// Empties the bucket, and then some, so requests wait about as long as
+    /// a full refill after the endpoint has said it is rate limiting us.
+    pub async fn back_off(&self) {
+        let mut state = self.state.lock().await;
+        state.tokens = state.tokens.min(0.0) - self.burst;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_burst_then_throttle() {
+        let limiter = RateLimiter::new(100, 2);
+        let started = Instant::now();
+
+        limiter.acquire().await;
+        limiter.acquire().await;
+        assert!(started.elapsed() < Duration::from_millis(5));
+
+        limiter.acquire().await;
+        assert!(started.elapsed() >= Duration::from_millis(9));
+    }
+
+    #[tokio::test]
+    async fn test_back_off_waits_past_a_full_refill() {
+        let limiter = RateLimiter::new(100, 2);
+        limiter.back_off().await;
+        let started = Instant::now();
+
+        limiter.acquire().await;
+        assert!(started.elapsed() >= Duration::from_millis(29));
+    }
+}
@@ -0,0 +1,522 @@
+use super::snapshots::{BlockSnapshot, SlotStatus};
+use crate::config::RecordingFormat;
+use crate::processing::Processor;
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{EncodedConfirmedTransaction, UiConfirmedBlock};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum RecordingError {
+    #[error("IO error: {0}")]
+    Io(#[from] io::Error),
+    #[error("JSON error: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Bincode error: {0}")]
+    Bincode(#[from] bincode::Error),
+    #[error("Truncated record at end of file")]
+    Truncated,
+}
+
+/// One processor input, as written by `Recorder` and read back by
+/// `ReplaySource`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum RecordedEvent {
+    Block {
+        slot: u64,
+        block: UiConfirmedBlock,
+    },
+    BlockMetadata(BlockSnapshot),
+    Transaction {
+        slot: u64,
+        transaction: EncodedConfirmedTransaction,
+    },
+    AccountUpdate {
+        pubkey: Pubkey,
+        owner: Pubkey,
+        data: Vec<u8>,
+        slot: u64,
+        write_version: Option<u64>,
+    },
+    SlotStatus {
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    },
+}
+
+/// Bincode frame of a `RecordedEvent`. The UI block and transaction types use
+/// untagged enums that bincode cannot read back, so they are stored as JSON.
+#[derive(Serialize, Deserialize)]
+enum BincodeEvent {
+    Block {
+        slot: u64,
+        block: Vec<u8>,
+    },
+    BlockMetadata(BlockSnapshot),
+    Transaction {
+        slot: u64,
+        transaction: Vec<u8>,
+    },
+    AccountUpdate {
+        pubkey: Pubkey,
+        owner: Pubkey,
+        data: Vec<u8>,
+        slot: u64,
+        write_version: Option<u64>,
+    },
+    SlotStatus {
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    },
+}
+
+impl BincodeEvent {
+    fn encode(event: &RecordedEvent) -> Result<Self, RecordingError> {
+        Ok(match event {
+            RecordedEvent::Block { slot, block } => BincodeEvent::Block {
+                slot: *slot,
+                block: serde_json::to_vec(block)?,
+            },
+            RecordedEvent::BlockMetadata(block) => BincodeEvent::BlockMetadata(block.clone()),
+            RecordedEvent::Transaction { slot, transaction } => BincodeEvent::Transaction {
+                slot: *slot,
+                transaction: serde_json::to_vec(transaction)?,
+            },
+            RecordedEvent::AccountUpdate {
+                pubkey,
+                owner,
+                data,
+                slot,
+                write_version,
+            } => BincodeEvent::AccountUpdate {
+                pubkey: *pubkey,
+                owner: *owner,
+                data: data.clone(),
+                slot: *slot,
+                write_version: *write_version,
+            },
+            RecordedEvent::SlotStatus {
+                slot,
+                parent,
+                status,
+            } => BincodeEvent::SlotStatus {
+                slot: *slot,
+                parent: *parent,
+                status: *status,
+            },
+        })
+    }
+
+    fn decode(self) -> Result<RecordedEvent, RecordingError> {
+        Ok(match self {
+            BincodeEvent::Block { slot, block } => RecordedEvent::Block {
+                slot,
+                block: serde_json::from_slice(&block)?,
+            },
+            BincodeEvent::BlockMetadata(block) => RecordedEvent::BlockMetadata(block),
+            BincodeEvent::Transaction { slot, transaction } => RecordedEvent::Transaction {
+                slot,
+                transaction: serde_json::from_slice(&transaction)?,
+            },
+            BincodeEvent::AccountUpdate {
+                pubkey,
+                owner,
+                data,
+                slot,
+                write_version,
+            } => RecordedEvent::AccountUpdate {
+                pubkey,
+                owner,
+                data,
+                slot,
+                write_version,
+            },
+            BincodeEvent::SlotStatus {
+                slot,
+                parent,
+                status,
+            } => RecordedEvent::SlotStatus {
+                slot,
+                parent,
+                status,
+            },
+        })
+    }
+}
+
+/// Appends events to a recording as JSON lines or `u32` little-endian
+/// length-prefixed bincode frames. Events are serialized by the caller and
+/// written by a background thread, which flushes whenever it catches up, so a
+/// crash leaves a readable file. Dropping the recorder waits for the queue to
+/// be written.
+pub struct Recorder {
+    format: RecordingFormat,
+    sender: Option<mpsc::Sender<Vec<u8>>>,
+    writer: Option<JoinHandle<()>>,
+}
+
+impl Recorder {
+    pub fn create<P: AsRef<Path>>(
+        path: P,
+        format: Option<RecordingFormat>,
+    ) -> Result<Self, RecordingError> {
+        let format = format.unwrap_or_else(|| RecordingFormat::from_path(path.as_ref()));
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (sender, receiver) = mpsc::channel();
+        let writer = std::thread::Builder::new()
+            .name("recorder".to_string())
+            .spawn(move || write_events(BufWriter::new(file), receiver))?;
+        Ok(Self {
+            format,
+            sender: Some(sender),
+            writer: Some(writer),
+        })
+    }
+
+    pub fn record(&self, event: &RecordedEvent) -> Result<(), RecordingError> {
+        let bytes = match self.format {
+            RecordingFormat::Jsonl => {
+                let mut line = serde_json::to_vec(event)?;
+                line.push(b'\n');
+                line
+            }
+            RecordingFormat::Bincode => {
+                let payload = bincode::serialize(&BincodeEvent::encode(event)?)?;
+                let mut frame = (payload.len() as u32).to_le_bytes().to_vec();
+                frame.extend_from_slice(&payload);
+                frame
+            }
+        };
+        let sender = self.sender.as_ref().expect("sender is only taken on drop");
+        sender
+            .send(bytes)
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "recorder thread exited"))?;
+        metrics::counter!("cypher_recorded_events_total").increment(1);
+        Ok(())
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+fn write_events(mut writer: BufWriter<File>, events: mpsc::Receiver<Vec<u8>>) {
+    while let Ok(bytes) = events.recv() {
+        let mut result = writer.write_all(&bytes);
+        while result.is_ok() {
+            match events.try_recv() {
+                Ok(bytes) => result = writer.write_all(&bytes),
+                Err(_) => break,
+            }
+        }
+        if let Err(e) = result.and_then(|()| writer.flush()) {
+            metrics::counter!("cypher_recording_write_errors_total").increment(1);
+            tracing::warn!("Failed to write recording: {}", e);
+        }
+    }
+}
+
+/// Reads a recording back in the order it was written.
+pub struct RecordingReader {
+    format: RecordingFormat,
+    reader: BufReader<File>,
+}
+
+impl RecordingReader {
+    pub fn open<P: AsRef<Path>>(
+        path: P,
+        format: Option<RecordingFormat>,
+    ) -> Result<Self, RecordingError> {
+        let format = format.unwrap_or_else(|| RecordingFormat::from_path(path.as_ref()));
+        Ok(Self {
+            format,
+            reader: BufReader::new(File::open(path)?),
+        })
+    }
+
+    /// The next event, or `None` at the end of the file.
+    pub fn next_event(&mut self) -> Result<Option<RecordedEvent>, RecordingError> {
+        match self.format {
+            RecordingFormat::Jsonl => loop {
+                let mut line = String::new();
+                if self.reader.read_line(&mut line)? == 0 {
+                    return Ok(None);
+                }
+                if !line.trim().is_empty() {
+                    return Ok(Some(serde_json::from_str(&line)?));
+                }
+            },
+            RecordingFormat::Bincode => {
+                let mut len = [0u8; 4];
+                if !read_frame(&mut self.reader, &mut len)? {
+                    return Ok(None);
+                }
+                // Grown as bytes arrive, so a corrupt length cannot allocate
+                // more than the rest of the file.
+                let len = u32::from_le_bytes(len) as u64;
+                let mut payload = Vec::new();
+                (&mut self.reader).take(len).read_to_end(&mut payload)?;
+                if payload.len() as u64 != len {
+                    return Err(RecordingError::Truncated);
+                }
+                let event: BincodeEvent = bincode::deserialize(&payload)?;
+                Ok(Some(event.decode()?))
+            }
+        }
+    }
+}
+
+/// Fills `buf`, returning false on a clean end of file. A partial read is an
+/// error.
+fn read_frame<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, RecordingError> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 if filled == 0 => return Ok(false),
+            0 => return Err(RecordingError::Truncated),
+            n => filled += n,
+        }
+    }
+    Ok(true)
+}
+
+/// Feeds a recording through the processor, one event at a time, so runs are
+/// deterministic and need no cluster.
+pub struct ReplaySource {
+    path: PathBuf,
+    format: Option<RecordingFormat>,
+}
+
+impl ReplaySource {
+    pub fn new<P: Into<PathBuf>>(path: P, format: Option<RecordingFormat>) -> Self {
+        Self {
+            path: path.into(),
+            format,
+        }
+    }
+}
+
+#[async_trait]
+impl super::IngestionSource for ReplaySource {
+    async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
+        let mut reader = RecordingReader::open(&self.path, self.format)?;
+        let mut replayed = 0u64;
+        while let Some(event) = reader.next_event()? {
+            replayed += 1;
+            let result = match event {
+                RecordedEvent::Block { slot, block } => processor.process_block(block, slot).await,
+                RecordedEvent::BlockMetadata(block) => {
+                    processor.process_block_metadata(block).await
+                }
+                RecordedEvent::Transaction { slot, transaction } => {
+                    processor.process_transaction(transaction, slot).await
+                }
+                RecordedEvent::AccountUpdate {
+                    pubkey,
+                    owner,
+                    data,
+                    slot,
+                    write_version,
+                } => {
+                    processor
+                        .process_account_update(pubkey, data, owner, slot, write_version)
+                        .await
+                }
+                RecordedEvent::SlotStatus {
+                    slot,
+                    parent,
+                    status,
+                } => processor.process_slot_status(slot, parent, status).await,
+            };
+            if let Err(e) = result {
+                tracing::error!("Failed to replay event {}: {:?}", replayed, e);
+            }
+        }
+        tracing::info!("Replayed {} events from {}", replayed, self.path.display());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::signature::{Keypair, Signer};
+    use solana_sdk::system_instruction;
+    use solana_sdk::transaction::Transaction;
+
+    fn sample_events() -> Vec<RecordedEvent> {
+        let block: UiConfirmedBlock = serde_json::from_value(serde_json::json!({
+            "previousBlockhash": "11111111111111111111111111111111",
+            "blockhash": "4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn",
+            "parentSlot": 41,
+            "transactions": [],
+            "blockTime": 1700000000,
+            "blockHeight": 40,
+        }))
+        .unwrap();
+        // A transfer, encoded as `getTransaction` returns it.
+        let payer = Keypair::new();
+        let transfer = Transaction::new_signed_with_payer(
+            &[system_instruction::transfer(
+                &payer.pubkey(),
+                &Pubkey::new_unique(),
+                1_000_000,
+            )],
+            Some(&payer.pubkey()),
+            &[&payer],
+            Hash::default(),
+        );
+        let transaction: EncodedConfirmedTransaction = serde_json::from_value(serde_json::json!({
+            "slot": 42,
+            "transaction": {
+                "transaction": [
+                    bs58::encode(bincode::serialize(&transfer).unwrap()).into_string(),
+                    "base58"
+                ],
+                "meta": null
+            },
+            "blockTime": 1700000000,
+        }))
+        .unwrap();
+
+        vec![
+            RecordedEvent::SlotStatus {
+                slot: 42,
+                parent: Some(41),
+                status: SlotStatus::Confirmed,
+            },
+            RecordedEvent::Block { slot: 42, block },
+            RecordedEvent::Transaction {
+                slot: 42,
+                transaction,
+            },
+            RecordedEvent::BlockMetadata(BlockSnapshot {
+                slot: 42,
+                blockhash: "4sGjMW1sUnHzSxGspuhpqLDx6wiyjNtZAMdL4VZHirAn".to_string(),
+                parent_slot: Some(41),
+                parent_blockhash: Some("11111111111111111111111111111111".to_string()),
+                rewards: Vec::new(),
+                num_reward_partitions: None,
+                block_time: Some(1700000000),
+                block_height: Some(40),
+                executed_transaction_count: Some(1),
+                entry_count: Some(2),
+            }),
+            RecordedEvent::AccountUpdate {
+                pubkey: Pubkey::new_unique(),
+                owner: Pubkey::new_unique(),
+                data: vec![1, 2, 3],
+                slot: 42,
+                write_version: Some(7),
+            },
+        ]
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("cypher_recording_{}", Pubkey::new_unique()))
+    }
+
+    fn write_events(path: &Path, format: RecordingFormat, events: &[RecordedEvent]) {
+        let recorder = Recorder::create(path, Some(format)).unwrap();
+        for event in events {
+            recorder.record(event).unwrap();
+        }
+    }
+
+    fn read_events(path: &Path, format: RecordingFormat) -> Vec<RecordedEvent> {
+        let mut reader = RecordingReader::open(path, Some(format)).unwrap();
+        let mut events = Vec::new();
+        while let Some(event) = reader.next_event().unwrap() {
+            events.push(event);
+        }
+        events
+    }
+
+    fn roundtrip(format: RecordingFormat) {
+        let path = temp_path();
+        let events = sample_events();
+
+        write_events(&path, format, &events);
+        let replayed = read_events(&path, format);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(replayed, events);
+    }
+
+    #[test]
+    fn test_jsonl_roundtrip() {
+        roundtrip(RecordingFormat::Jsonl);
+    }
+
+    #[test]
+    fn test_bincode_roundtrip() {
+        roundtrip(RecordingFormat::Bincode);
+    }
+
+    #[test]
+    fn test_truncated_bincode_frame() {
+        // A frame announcing 10 bytes with only 4 left in the file.
+        assert!(matches!(
+            read_frame(&mut &[0u8; 4][..], &mut [0u8; 10]),
+            Err(RecordingError::Truncated)
+        ));
+        assert!(!read_frame(&mut &[][..], &mut [0u8; 4]).unwrap());
+
+        // A corrupt length is not trusted for the allocation.
+        let path = temp_path();
+        let mut frame = u32::MAX.to_le_bytes().to_vec();
+        frame.extend_from_slice(&[1, 2, 3]);
+        std::fs::write(&path, frame).unwrap();
+        let mut reader = RecordingReader::open(&path, Some(RecordingFormat::Bincode)).unwrap();
+        let result = reader.next_event();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(RecordingError::Truncated)));
+    }
+
+    /// Replays a recording into a processor that records its own input, which
+    /// must come out the same. Needs the services in `config.json`.
+    #[tokio::test]
+    async fn test_replay_through_processor() {
+        use crate::config::Config;
+        use crate::ingestion::IngestionSource;
+        use crate::storage::ipfs::IpfsStorage;
+        use crate::storage::Storage;
+        use std::sync::Arc;
+
+        let config = Config::load().unwrap();
+        let storage = Storage::new(&config).await.unwrap();
+        let ipfs_storage = IpfsStorage::new(&config.ipfs_api_url);
+
+        let (input, output) = (temp_path(), temp_path());
+        let events = sample_events();
+        write_events(&input, RecordingFormat::Jsonl, &events);
+        let recorder = Arc::new(Recorder::create(&output, Some(RecordingFormat::Jsonl)).unwrap());
+        let processor = Processor::new(storage, ipfs_storage).with_recorder(recorder.clone());
+
+        ReplaySource::new(&input, Some(RecordingFormat::Jsonl))
+            .start(processor)
+            .await
+            .unwrap();
+        // The processor is gone, so this waits for the writer to finish.
+        drop(recorder);
+
+        let replayed = read_events(&output, RecordingFormat::Jsonl);
+        std::fs::remove_file(&input).unwrap();
+        std::fs::remove_file(&output).unwrap();
+        assert_eq!(replayed, events);
+    }
+}
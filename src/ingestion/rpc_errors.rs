@@ -0,0 +1,196 @@
+use crate::config::RpcPollerConfig;
+use solana_client::client_error::{ClientError, ClientErrorKind};
+use solana_client::rpc_custom_error::{
+    JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP, JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE,
+    JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET,
+    JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED,
+    JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED, JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY,
+    JSON_RPC_SERVER_ERROR_SLOT_SKIPPED, JSON_RPC_SERVER_ERROR_TRANSACTION_HISTORY_NOT_AVAILABLE,
+};
+use solana_client::rpc_request::RpcError;
+use std::future::Future;
+use std::time::Duration;
+
+const HTTP_TOO_MANY_REQUESTS: u16 = 429;
+/// Sent by newer nodes whose long-term storage cannot be reached; not yet in
+/// `rpc_custom_error`.
+const JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_UNREACHABLE: i64 = -32019;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RpcErrorClass {
+    /// The leader skipped the slot, or it was pruned from long-term storage.
+    /// There is no block and there never will be.
+    Skipped,
+    /// The node does not have the block yet; ask again later.
+    NotYetAvailable,
+    /// The node cleaned the block up and cannot read it from long-term
+    /// storage. Asking it again will not help; a Bigtable backfill can.
+    Unavailable,
+    RateLimited,
+    Fatal,
+}
+
+impl RpcErrorClass {
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            RpcErrorClass::NotYetAvailable | RpcErrorClass::RateLimited
+        )
+    }
+
+    /// Whether no block can be fetched for the slot, so it is recorded as
+    /// skipped rather than failing the caller.
+    pub fn has_no_block(self) -> bool {
+        matches!(self, RpcErrorClass::Skipped | RpcErrorClass::Unavailable)
+    }
+}
+
+pub fn classify(error: &ClientError) -> RpcErrorClass {
+    match error.kind() {
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => match *code {
+            JSON_RPC_SERVER_ERROR_SLOT_SKIPPED
+            | JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED => RpcErrorClass::Skipped,
+            JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+            | JSON_RPC_SERVER_ERROR_BLOCK_STATUS_NOT_AVAILABLE_YET
+            | JSON_RPC_SERVER_ERROR_MIN_CONTEXT_SLOT_NOT_REACHED
+            | JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY => RpcErrorClass::NotYetAvailable,
+            JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP
+            | JSON_RPC_SERVER_ERROR_TRANSACTION_HISTORY_NOT_AVAILABLE
+            | JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_UNREACHABLE => RpcErrorClass::Unavailable,
+            code if code == HTTP_TOO_MANY_REQUESTS as i64 => RpcErrorClass::RateLimited,
+            _ => RpcErrorClass::Fatal,
+        },
+        ClientErrorKind::Reqwest(e) => {
+            if e.status().map(|status| status.as_u16()) == Some(HTTP_TOO_MANY_REQUESTS) {
+                RpcErrorClass::RateLimited
+            } else if e.is_timeout() || e.is_connect() {
+                RpcErrorClass::NotYetAvailable
+            } else {
+                RpcErrorClass::Fatal
+            }
+        }
+        ClientErrorKind::Io(_) => RpcErrorClass::NotYetAvailable,
+        _ => RpcErrorClass::Fatal,
+    }
+}
+
+/// Whether the error is the endpoint's fault: it could not be reached, timed
+/// out, failed on the server side or sent back something unparseable. Rate
+/// limits and blocks the node does not have yet say nothing about its health.
+pub fn is_endpoint_failure(error: &ClientError) -> bool {
+    match error.kind() {
+        ClientErrorKind::Io(_) | ClientErrorKind::SerdeJson(_) => true,
+        ClientErrorKind::Reqwest(e) => {
+            e.is_timeout()
+                || e.is_connect()
+                || e.status().is_some_and(|status| status.is_server_error())
+        }
+        ClientErrorKind::RpcError(RpcError::RpcResponseError { code, .. }) => {
+            *code == JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        }
+        _ => false,
+    }
+}
+
+/// Retries RPC requests with the `rpc_poller` `max_retries` and
+/// `initial_backoff_ms` settings.
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    max_retries: u32,
+    initial_backoff: Duration,
+}
+
+impl Retry {
+    pub fn new(config: &RpcPollerConfig) -> Self {
+        Self {
+            max_retries: config.max_retries,
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+        }
+    }
+
+    /// Runs `request` until it succeeds, retrying errors with exponential
+    /// backoff. Errors saying there is no block are returned right away, as
+    /// are errors once the retries run out.
+    pub async fn run<T, F, Fut>(self, what: &str, mut request: F) -> Result<T, ClientError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, ClientError>>,
+    {
+        let mut backoff = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            match request().await {
+                Ok(value) => return Ok(value),
+                Err(e) if !classify(&e).has_no_block() && attempt < self.max_retries => {
+                    tracing::debug!("Retrying {} after error: {}", what, e);
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_client::rpc_request::RpcResponseErrorData;
+
+    fn rpc_error(code: i64) -> ClientError {
+        ClientError::from(RpcError::RpcResponseError {
+            code,
+            message: String::new(),
+            data: RpcResponseErrorData::Empty,
+        })
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify(&rpc_error(JSON_RPC_SERVER_ERROR_SLOT_SKIPPED)),
+            RpcErrorClass::Skipped
+        );
+        assert_eq!(
+            classify(&rpc_error(
+                JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_SLOT_SKIPPED
+            )),
+            RpcErrorClass::Skipped
+        );
+        assert_eq!(
+            classify(&rpc_error(JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE)),
+            RpcErrorClass::NotYetAvailable
+        );
+        assert_eq!(
+            classify(&rpc_error(JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP)),
+            RpcErrorClass::Unavailable
+        );
+        assert_eq!(
+            classify(&rpc_error(
+                JSON_RPC_SERVER_ERROR_LONG_TERM_STORAGE_UNREACHABLE
+            )),
+            RpcErrorClass::Unavailable
+        );
+        assert!(classify(&rpc_error(JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP)).has_no_block());
+        assert!(!classify(&rpc_error(JSON_RPC_SERVER_ERROR_BLOCK_CLEANED_UP)).is_retryable());
+        assert_eq!(classify(&rpc_error(429)), RpcErrorClass::RateLimited);
+        assert_eq!(classify(&rpc_error(-32603)), RpcErrorClass::Fatal);
+        assert!(classify(&rpc_error(429)).is_retryable());
+        assert!(!classify(&rpc_error(JSON_RPC_SERVER_ERROR_SLOT_SKIPPED)).is_retryable());
+    }
+
+    #[test]
+    fn test_is_endpoint_failure() {
+        assert!(is_endpoint_failure(&rpc_error(
+            JSON_RPC_SERVER_ERROR_NODE_UNHEALTHY
+        )));
+        assert!(!is_endpoint_failure(&rpc_error(429)));
+        assert!(!is_endpoint_failure(&rpc_error(
+            JSON_RPC_SERVER_ERROR_BLOCK_NOT_AVAILABLE
+        )));
+        assert!(is_endpoint_failure(&ClientError::from(
+            std::io::Error::from(std::io::ErrorKind::ConnectionRefused)
+        )));
+    }
+}
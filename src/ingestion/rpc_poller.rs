@@ -1,20 +1,177 @@
-use solana_client::rpc_client::RpcClient;
-use solana_transaction_status::UiConfirmedBlock;
-use crate::config::Config;
+use super::endpoint_pool::EndpointPool;
+use super::rpc_errors::{classify, RpcErrorClass};
+use super::websocket_listener::SlotGap;
+use solana_client::client_error::ClientError;
+use solana_client::rpc_config::RpcBlockConfig;
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_transaction_status::{TransactionDetails, UiConfirmedBlock, UiTransactionEncoding};
+use crate::config::{Config, RpcPollerConfig};
 use crate::processing::Processor;
+use crate::storage::Storage;
+use futures::stream::{self, StreamExt};
+use std::sync::Arc;
+use tokio::sync::{mpsc::UnboundedReceiver, Mutex};
 use tokio::time::{interval, Duration};
 use async_trait::async_trait;
 
+pub const RPC_POLLER_CHECKPOINT: &str = "rpc_poller";
+
+enum BlockFetch {
+    Block(UiConfirmedBlock),
+    Skipped,
+    NotYetAvailable,
+}
+
 pub struct RpcPoller {
-    rpc_client: RpcClient,
+    endpoints: Arc<EndpointPool>,
     poll_interval: Duration,
+    storage: Storage,
+    config: RpcPollerConfig,
+    gaps: Option<Mutex<UnboundedReceiver<SlotGap>>>,
 }
 
 impl RpcPoller {
-    pub fn new(config: &Config) -> Self {
+    pub fn new(
+        config: &Config,
+        endpoints: Arc<EndpointPool>,
+        storage: Storage,
+        gaps: Option<UnboundedReceiver<SlotGap>>,
+    ) -> Self {
         Self {
-            rpc_client: RpcClient::new(config.solana_rpc_url.clone()),
+            endpoints,
             poll_interval: Duration::from_secs(config.rpc_poll_interval),
+            storage,
+            config: config.rpc_poller.clone(),
+            gaps: gaps.map(Mutex::new),
+        }
+    }
+
+    /// Fetches the slots the WebSocket listener reports as missed. Checkpoints
+    /// are left alone since gaps arrive out of order.
+    async fn fill_gaps(&self, processor: &Processor) -> Result<(), Box<dyn std::error::Error>> {
+        let mut gaps = self
+            .gaps
+            .as_ref()
+            .ok_or("gap_fill_only requires gaps from the WebSocket listener")?
+            .lock()
+            .await;
+
+        while let Some(gap) = gaps.recv().await {
+            tracing::info!("Filling slots {}..={}", gap.start, gap.end);
+            let mut blocks = stream::iter(gap.start..=gap.end)
+                .map(|slot| async move { (slot, self.fetch_block(slot).await) })
+                .buffered(self.config.max_concurrent_requests.max(1));
+
+            while let Some((slot, block)) = blocks.next().await {
+                match block? {
+                    BlockFetch::Block(block) => processor.process_block(block, slot).await?,
+                    BlockFetch::Skipped => {
+                        metrics::counter!("cypher_rpc_skipped_slots_total").increment(1);
+                        self.storage.record_skipped_slot(slot).await?;
+                    }
+                    BlockFetch::NotYetAvailable => {
+                        tracing::warn!("Block for slot {} unavailable, leaving gap", slot);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Resumes after the persisted checkpoint, or starts at the current tip
+    /// when the poller has never run.
+    async fn starting_slot(&self) -> Result<u64, Box<dyn std::error::Error>> {
+        match self.storage.get_checkpoint(RPC_POLLER_CHECKPOINT).await? {
+            Some(slot) => {
+                tracing::info!("Resuming RPC poller from checkpoint slot {}", slot);
+                Ok(slot)
+            }
+            None => Ok(self.get_slot().await?),
+        }
+    }
+
+    async fn get_slot(&self) -> Result<u64, ClientError> {
+        let mut backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self
+                .endpoints
+                .call(|client| async move { client.get_slot().await })
+                .await
+            {
+                Err(e) if classify(&e).is_retryable() && attempt < self.config.max_retries => {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Moves the processor's finalized watermark to the cluster's finalized
+    /// slot. Failures are only logged; the next tick tries again.
+    async fn observe_finalized_slot(&self, processor: &Processor) {
+        let slot = self
+            .endpoints
+            .call(|client| async move {
+                client
+                    .get_slot_with_commitment(CommitmentConfig::finalized())
+                    .await
+            })
+            .await;
+        match slot {
+            Ok(slot) => {
+                if let Err(e) = processor.process_finalized_slot(slot).await {
+                    tracing::warn!("Failed to finalize slots up to {}: {:?}", slot, e);
+                }
+            }
+            Err(e) => tracing::warn!("Failed to fetch finalized slot: {}", e),
+        }
+    }
+
+    /// Fetches a block, backing off when every endpoint is rate limited or
+    /// none has the slot yet. Skipped slots and blocks still unavailable after
+    /// the retries are reported rather than returned as errors.
+    async fn fetch_block(&self, slot: u64) -> Result<BlockFetch, ClientError> {
+        let config = RpcBlockConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            transaction_details: Some(TransactionDetails::Full),
+            rewards: Some(false),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
+        };
+        let mut backoff = Duration::from_millis(self.config.initial_backoff_ms);
+        let mut attempt = 0;
+        loop {
+            let error = match self
+                .endpoints
+                .call(|client| async move {
+                    client.get_block_with_config(slot, config).await
+                })
+                .await
+            {
+                Ok(block) => return Ok(BlockFetch::Block(block)),
+                Err(e) => e,
+            };
+            match classify(&error) {
+                RpcErrorClass::Skipped => return Ok(BlockFetch::Skipped),
+                RpcErrorClass::Unavailable => {
+                    tracing::warn!("Block {} is no longer available over RPC: {}", slot, error);
+                    return Ok(BlockFetch::Skipped);
+                }
+                RpcErrorClass::NotYetAvailable | RpcErrorClass::RateLimited
+                    if attempt < self.config.max_retries =>
+                {
+                    attempt += 1;
+                    tokio::time::sleep(backoff).await;
+                    backoff *= 2;
+                }
+                RpcErrorClass::NotYetAvailable | RpcErrorClass::RateLimited => {
+                    return Ok(BlockFetch::NotYetAvailable)
+                }
+                RpcErrorClass::Fatal => return Err(error),
+            }
         }
     }
 }
@@ -22,27 +179,42 @@ impl RpcPoller {
 #[async_trait]
 impl super::IngestionSource for RpcPoller {
     async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
+        if self.config.gap_fill_only {
+            return self.fill_gaps(&processor).await;
+        }
+
         let mut interval = interval(self.poll_interval);
-        let mut last_slot = self.rpc_client.get_slot()?;
+        let mut last_slot = self.starting_slot().await?;
 
         loop {
             interval.tick().await;
-            let current_slot = self.rpc_client.get_slot()?;
-
-            for slot in last_slot + 1..=current_slot {
-                let block: UiConfirmedBlock = self.rpc_client.get_block_with_encoding(
-                    slot,
-                    solana_transaction_status::UiTransactionEncoding::Json
-                )?;
-                
-                for transaction in block.transactions {
-                    if let Some(transaction) = transaction {
-                        processor.process_transaction(transaction, slot).await?;
+            let current_slot = self.get_slot().await?;
+            self.observe_finalized_slot(&processor).await;
+
+            // Fetch up to `max_concurrent_requests` blocks at once; `buffered`
+            // yields them in slot order so checkpoints stay gap-free.
+            let mut blocks = stream::iter(last_slot + 1..=current_slot)
+                .map(|slot| async move { (slot, self.fetch_block(slot).await) })
+                .buffered(self.config.max_concurrent_requests.max(1));
+
+            while let Some((slot, block)) = blocks.next().await {
+                match block? {
+                    BlockFetch::Block(block) => processor.process_block(block, slot).await?,
+                    BlockFetch::Skipped => {
+                        metrics::counter!("cypher_rpc_skipped_slots_total").increment(1);
+                        self.storage.record_skipped_slot(slot).await?;
+                    }
+                    BlockFetch::NotYetAvailable => {
+                        // Leave the checkpoint before this slot and pick it up
+                        // again on the next tick.
+                        tracing::debug!("Block for slot {} not available yet", slot);
+                        break;
                     }
                 }
-            }
 
-            last_slot = current_slot;
+                self.storage.set_checkpoint(RPC_POLLER_CHECKPOINT, slot).await?;
+                last_slot = slot;
+            }
         }
     }
-}
\ No newline at end of file
+}
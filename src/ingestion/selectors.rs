@@ -0,0 +1,229 @@
+use crate::config::{AccountFilter, AccountsSelector, TransactionSelector};
+use solana_sdk::pubkey::Pubkey;
+use std::collections::HashSet;
+use thiserror::Error;
+
+const WILDCARD: &str = "*";
+
+#[derive(Debug, Error)]
+pub enum SelectorError {
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(String),
+    #[error("Invalid memcmp bytes: {0}")]
+    InvalidMemcmpBytes(String),
+}
+
+#[derive(Debug, Clone)]
+enum DataFilter {
+    DataSize(usize),
+    Memcmp { offset: usize, bytes: Vec<u8> },
+}
+
+impl DataFilter {
+    fn matches(&self, data: &[u8]) -> bool {
+        match self {
+            DataFilter::DataSize(size) => data.len() == *size,
+            DataFilter::Memcmp { offset, bytes } => offset
+                .checked_add(bytes.len())
+                .and_then(|end| data.get(*offset..end))
+                .is_some_and(|slice| slice == bytes.as_slice()),
+        }
+    }
+}
+
+/// Compiled form of `AccountsSelector`, checked on the validator thread.
+#[derive(Debug, Clone, Default)]
+pub struct AccountsFilter {
+    select_all: bool,
+    accounts: HashSet<Pubkey>,
+    owners: HashSet<Pubkey>,
+    filters: Vec<DataFilter>,
+}
+
+impl AccountsFilter {
+    pub fn from_config(selector: &AccountsSelector) -> Result<Self, SelectorError> {
+        let select_all = selector.accounts.iter().any(|a| a == WILDCARD);
+        let accounts = parse_pubkeys(selector.accounts.iter().filter(|a| *a != WILDCARD))?;
+        let owners = parse_pubkeys(selector.owners.iter())?;
+        let filters = selector
+            .filters
+            .iter()
+            .map(|filter| match filter {
+                AccountFilter::DataSize(size) => Ok(DataFilter::DataSize(*size as usize)),
+                AccountFilter::Memcmp { offset, bytes } => Ok(DataFilter::Memcmp {
+                    offset: *offset,
+                    bytes: bs58::decode(bytes)
+                        .into_vec()
+                        .map_err(|_| SelectorError::InvalidMemcmpBytes(bytes.clone()))?,
+                }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            select_all,
+            accounts,
+            owners,
+            filters,
+        })
+    }
+
+    pub fn is_selected(&self, pubkey: &[u8], owner: &[u8], data: &[u8]) -> bool {
+        let key_selected = self.select_all
+            || contains(&self.accounts, pubkey)
+            || contains(&self.owners, owner)
+            || (self.accounts.is_empty() && self.owners.is_empty() && !self.filters.is_empty());
+
+        key_selected && self.filters.iter().all(|filter| filter.matches(data))
+    }
+}
+
+/// Compiled form of `TransactionSelector`, checked on the validator thread.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionsFilter {
+    select_all: bool,
+    mentions: HashSet<Pubkey>,
+    program_ids: HashSet<Pubkey>,
+    include_votes: bool,
+    include_failed: bool,
+}
+
+impl TransactionsFilter {
+    pub fn from_config(selector: &TransactionSelector) -> Result<Self, SelectorError> {
+        Ok(Self {
+            select_all: selector.mentions.iter().any(|m| m == WILDCARD),
+            mentions: parse_pubkeys(selector.mentions.iter().filter(|m| *m != WILDCARD))?,
+            program_ids: parse_pubkeys(selector.program_ids.iter())?,
+            include_votes: selector.include_votes,
+            include_failed: selector.include_failed,
+        })
+    }
+
+    pub fn is_selected<'a>(
+        &self,
+        is_vote: bool,
+        is_failed: bool,
+        mut account_keys: impl Iterator<Item = &'a Pubkey>,
+        mut program_ids: impl Iterator<Item = &'a Pubkey>,
+    ) -> bool {
+        if (is_vote && !self.include_votes) || (is_failed && !self.include_failed) {
+            return false;
+        }
+        self.select_all
+            || account_keys.any(|key| self.mentions.contains(key))
+            || program_ids.any(|key| self.program_ids.contains(key))
+    }
+}
+
+fn parse_pubkeys<'a>(
+    keys: impl Iterator<Item = &'a String>,
+) -> Result<HashSet<Pubkey>, SelectorError> {
+    keys.map(|key| {
+        key.parse::<Pubkey>()
+            .map_err(|_| SelectorError::InvalidPubkey(key.clone()))
+    })
+    .collect()
+}
+
+fn contains(set: &HashSet<Pubkey>, key: &[u8]) -> bool {
+    Pubkey::try_from(key).is_ok_and(|key| set.contains(&key))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accounts_filter_by_owner_and_data() {
+        let owner = Pubkey::new_unique();
+        let filter = AccountsFilter::from_config(&AccountsSelector {
+            accounts: vec![],
+            owners: vec![owner.to_string()],
+            filters: vec![
+                AccountFilter::DataSize(4),
+                AccountFilter::Memcmp {
+                    offset: 1,
+                    bytes: bs58::encode([7u8, 8]).into_string(),
+                },
+            ],
+        })
+        .unwrap();
+
+        let pubkey = Pubkey::new_unique();
+        assert!(filter.is_selected(pubkey.as_ref(), owner.as_ref(), &[0, 7, 8, 9]));
+        assert!(!filter.is_selected(pubkey.as_ref(), owner.as_ref(), &[0, 7, 9, 9]));
+        assert!(!filter.is_selected(pubkey.as_ref(), owner.as_ref(), &[0, 7, 8]));
+        assert!(!filter.is_selected(
+            pubkey.as_ref(),
+            Pubkey::new_unique().as_ref(),
+            &[0, 7, 8, 9]
+        ));
+
+        let past_end = DataFilter::Memcmp {
+            offset: usize::MAX,
+            bytes: vec![7],
+        };
+        assert!(!past_end.matches(&[0, 7, 8, 9]));
+    }
+
+    #[test]
+    fn test_accounts_filter_by_pubkey_and_wildcard() {
+        let pubkey = Pubkey::new_unique();
+        let filter = AccountsFilter::from_config(&AccountsSelector {
+            accounts: vec![pubkey.to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filter.is_selected(pubkey.as_ref(), Pubkey::new_unique().as_ref(), &[]));
+        assert!(!filter.is_selected(
+            Pubkey::new_unique().as_ref(),
+            Pubkey::new_unique().as_ref(),
+            &[]
+        ));
+
+        let filter = AccountsFilter::from_config(&AccountsSelector {
+            accounts: vec!["*".to_string()],
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(filter.is_selected(
+            Pubkey::new_unique().as_ref(),
+            Pubkey::new_unique().as_ref(),
+            &[]
+        ));
+
+        assert!(!AccountsFilter::default().is_selected(
+            pubkey.as_ref(),
+            Pubkey::new_unique().as_ref(),
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_transactions_filter() {
+        let program_id = Pubkey::new_unique();
+        let mentioned = Pubkey::new_unique();
+        let filter = TransactionsFilter::from_config(&TransactionSelector {
+            mentions: vec![mentioned.to_string()],
+            program_ids: vec![program_id.to_string()],
+            include_votes: false,
+            include_failed: false,
+        })
+        .unwrap();
+
+        let other = Pubkey::new_unique();
+        assert!(filter.is_selected(false, false, [mentioned].iter(), [other].iter()));
+        assert!(filter.is_selected(false, false, [other].iter(), [program_id].iter()));
+        assert!(!filter.is_selected(false, false, [other].iter(), [other].iter()));
+        assert!(!filter.is_selected(true, false, [mentioned].iter(), [other].iter()));
+        assert!(!filter.is_selected(false, true, [mentioned].iter(), [other].iter()));
+    }
+
+    #[test]
+    fn test_invalid_selector() {
+        let result = AccountsFilter::from_config(&AccountsSelector {
+            owners: vec!["not-a-pubkey".to_string()],
+            ..Default::default()
+        });
+        assert!(matches!(result, Err(SelectorError::InvalidPubkey(_))));
+    }
+}
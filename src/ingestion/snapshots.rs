@@ -0,0 +1,330 @@
+use serde::{Deserialize, Serialize};
+use solana_geyser_plugin_interface::geyser_plugin_interface::{
+    ReplicaAccountInfoVersions, ReplicaBlockInfoVersions, ReplicaEntryInfoVersions,
+    ReplicaTransactionInfoVersions, SlotStatus as GeyserSlotStatus,
+};
+use solana_sdk::{
+    clock::UnixTimestamp, pubkey::Pubkey, signature::Signature,
+    transaction::VersionedTransaction,
+};
+use solana_transaction_status::{
+    EncodeError, EncodedConfirmedTransaction, Reward, TransactionStatusMeta,
+    UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountSnapshot {
+    pub pubkey: Pubkey,
+    pub owner: Pubkey,
+    pub lamports: u64,
+    pub executable: bool,
+    pub rent_epoch: u64,
+    pub data: Vec<u8>,
+    pub write_version: u64,
+    pub txn_signature: Option<Signature>,
+    pub slot: u64,
+    pub is_startup: bool,
+}
+
+impl AccountSnapshot {
+    pub fn from_replica(account: &ReplicaAccountInfoVersions, slot: u64, is_startup: bool) -> Self {
+        match account {
+            ReplicaAccountInfoVersions::V0_0_1(info) => Self {
+                pubkey: pubkey_from_bytes(info.pubkey),
+                owner: pubkey_from_bytes(info.owner),
+                lamports: info.lamports,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+                data: info.data.to_vec(),
+                write_version: info.write_version,
+                txn_signature: None,
+                slot,
+                is_startup,
+            },
+            ReplicaAccountInfoVersions::V0_0_2(info) => Self {
+                pubkey: pubkey_from_bytes(info.pubkey),
+                owner: pubkey_from_bytes(info.owner),
+                lamports: info.lamports,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+                data: info.data.to_vec(),
+                write_version: info.write_version,
+                txn_signature: info.txn_signature.copied(),
+                slot,
+                is_startup,
+            },
+            ReplicaAccountInfoVersions::V0_0_3(info) => Self {
+                pubkey: pubkey_from_bytes(info.pubkey),
+                owner: pubkey_from_bytes(info.owner),
+                lamports: info.lamports,
+                executable: info.executable,
+                rent_epoch: info.rent_epoch,
+                data: info.data.to_vec(),
+                write_version: info.write_version,
+                txn_signature: info.txn.map(|txn| *txn.signature()),
+                slot,
+                is_startup,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TransactionSnapshot {
+    pub signature: Signature,
+    pub is_vote: bool,
+    pub index: Option<usize>,
+    pub transaction: VersionedTransaction,
+    pub meta: TransactionStatusMeta,
+    pub slot: u64,
+}
+
+impl TransactionSnapshot {
+    pub fn from_replica(transaction: &ReplicaTransactionInfoVersions, slot: u64) -> Self {
+        match transaction {
+            ReplicaTransactionInfoVersions::V0_0_1(info) => Self {
+                signature: *info.signature,
+                is_vote: info.is_vote,
+                index: None,
+                transaction: info.transaction.to_versioned_transaction(),
+                meta: info.transaction_status_meta.clone(),
+                slot,
+            },
+            ReplicaTransactionInfoVersions::V0_0_2(info) => Self {
+                signature: *info.signature,
+                is_vote: info.is_vote,
+                index: Some(info.index),
+                transaction: info.transaction.to_versioned_transaction(),
+                meta: info.transaction_status_meta.clone(),
+                slot,
+            },
+        }
+    }
+
+    /// Encodes the snapshot the same way RPC returns transactions, so every
+    /// ingestion source feeds `Processor::process_transaction` one shape.
+    pub fn encode(&self) -> Result<EncodedConfirmedTransaction, EncodeError> {
+        let transaction = VersionedTransactionWithStatusMeta {
+            transaction: self.transaction.clone(),
+            meta: self.meta.clone(),
+        }
+        .encode(UiTransactionEncoding::Json, Some(0), true)?;
+
+        Ok(EncodedConfirmedTransaction {
+            slot: self.slot,
+            transaction,
+            block_time: None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BlockSnapshot {
+    pub slot: u64,
+    pub blockhash: String,
+    pub parent_slot: Option<u64>,
+    pub parent_blockhash: Option<String>,
+    pub rewards: Vec<Reward>,
+    pub num_reward_partitions: Option<u64>,
+    pub block_time: Option<UnixTimestamp>,
+    pub block_height: Option<u64>,
+    pub executed_transaction_count: Option<u64>,
+    pub entry_count: Option<u64>,
+}
+
+impl BlockSnapshot {
+    pub fn from_replica(block: &ReplicaBlockInfoVersions) -> Self {
+        match block {
+            ReplicaBlockInfoVersions::V0_0_1(info) => Self {
+                slot: info.slot,
+                blockhash: info.blockhash.to_string(),
+                parent_slot: None,
+                parent_blockhash: None,
+                rewards: info.rewards.to_vec(),
+                num_reward_partitions: None,
+                block_time: info.block_time,
+                block_height: info.block_height,
+                executed_transaction_count: None,
+                entry_count: None,
+            },
+            ReplicaBlockInfoVersions::V0_0_2(info) => Self {
+                slot: info.slot,
+                blockhash: info.blockhash.to_string(),
+                parent_slot: Some(info.parent_slot),
+                parent_blockhash: Some(info.parent_blockhash.to_string()),
+                rewards: info.rewards.to_vec(),
+                num_reward_partitions: None,
+                block_time: info.block_time,
+                block_height: info.block_height,
+                executed_transaction_count: Some(info.executed_transaction_count),
+                entry_count: None,
+            },
+            ReplicaBlockInfoVersions::V0_0_3(info) => Self {
+                slot: info.slot,
+                blockhash: info.blockhash.to_string(),
+                parent_slot: Some(info.parent_slot),
+                parent_blockhash: Some(info.parent_blockhash.to_string()),
+                rewards: info.rewards.to_vec(),
+                num_reward_partitions: None,
+                block_time: info.block_time,
+                block_height: info.block_height,
+                executed_transaction_count: Some(info.executed_transaction_count),
+                entry_count: Some(info.entry_count),
+            },
+            ReplicaBlockInfoVersions::V0_0_4(info) => Self {
+                slot: info.slot,
+                blockhash: info.blockhash.to_string(),
+                parent_slot: Some(info.parent_slot),
+                parent_blockhash: Some(info.parent_blockhash.to_string()),
+                rewards: info.rewards.rewards.clone(),
+                num_reward_partitions: info.rewards.num_partitions,
+                block_time: info.block_time,
+                block_height: info.block_height,
+                executed_transaction_count: Some(info.executed_transaction_count),
+                entry_count: Some(info.entry_count),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EntrySnapshot {
+    pub slot: u64,
+    pub index: usize,
+    pub num_hashes: u64,
+    pub hash: Vec<u8>,
+    pub executed_transaction_count: u64,
+    pub starting_transaction_index: Option<usize>,
+}
+
+impl EntrySnapshot {
+    pub fn from_replica(entry: &ReplicaEntryInfoVersions) -> Self {
+        match entry {
+            ReplicaEntryInfoVersions::V0_0_1(info) => Self {
+                slot: info.slot,
+                index: info.index,
+                num_hashes: info.num_hashes,
+                hash: info.hash.to_vec(),
+                executed_transaction_count: info.executed_transaction_count,
+                starting_transaction_index: None,
+            },
+            ReplicaEntryInfoVersions::V0_0_2(info) => Self {
+                slot: info.slot,
+                index: info.index,
+                num_hashes: info.num_hashes,
+                hash: info.hash.to_vec(),
+                executed_transaction_count: info.executed_transaction_count,
+                starting_transaction_index: Some(info.starting_transaction_index),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SlotStatus {
+    Processed,
+    Confirmed,
+    Rooted,
+    /// The slot will never be confirmed; its writes are rolled back.
+    Dead,
+}
+
+impl SlotStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SlotStatus::Processed => "processed",
+            SlotStatus::Confirmed => "confirmed",
+            SlotStatus::Rooted => "rooted",
+            SlotStatus::Dead => "dead",
+        }
+    }
+}
+
+impl From<&GeyserSlotStatus> for SlotStatus {
+    fn from(status: &GeyserSlotStatus) -> Self {
+        match status {
+            GeyserSlotStatus::Processed => SlotStatus::Processed,
+            GeyserSlotStatus::Confirmed => SlotStatus::Confirmed,
+            GeyserSlotStatus::Rooted => SlotStatus::Rooted,
+        }
+    }
+}
+
+fn pubkey_from_bytes(bytes: &[u8]) -> Pubkey {
+    Pubkey::try_from(bytes).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_geyser_plugin_interface::geyser_plugin_interface::{
+        ReplicaAccountInfoV2, ReplicaBlockInfoV2, ReplicaEntryInfo,
+    };
+
+    #[test]
+    fn test_account_snapshot_owns_data() {
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let signature = Signature::new_unique();
+        let snapshot = {
+            let data = vec![1, 2, 3];
+            let info = ReplicaAccountInfoV2 {
+                pubkey: pubkey.as_ref(),
+                lamports: 42,
+                owner: owner.as_ref(),
+                executable: false,
+                rent_epoch: 7,
+                data: &data,
+                write_version: 9,
+                txn_signature: Some(&signature),
+            };
+            AccountSnapshot::from_replica(&ReplicaAccountInfoVersions::V0_0_2(&info), 100, true)
+        };
+
+        assert_eq!(snapshot.pubkey, pubkey);
+        assert_eq!(snapshot.owner, owner);
+        assert_eq!(snapshot.data, vec![1, 2, 3]);
+        assert_eq!(snapshot.write_version, 9);
+        assert_eq!(snapshot.txn_signature, Some(signature));
+        assert_eq!(snapshot.slot, 100);
+        assert!(snapshot.is_startup);
+    }
+
+    #[test]
+    fn test_block_snapshot() {
+        let info = ReplicaBlockInfoV2 {
+            parent_slot: 99,
+            parent_blockhash: "parent",
+            slot: 100,
+            blockhash: "hash",
+            rewards: &[],
+            block_time: Some(1_700_000_000),
+            block_height: Some(90),
+            executed_transaction_count: 12,
+        };
+        let snapshot = BlockSnapshot::from_replica(&ReplicaBlockInfoVersions::V0_0_2(&info));
+
+        assert_eq!(snapshot.slot, 100);
+        assert_eq!(snapshot.parent_slot, Some(99));
+        assert_eq!(snapshot.parent_blockhash.as_deref(), Some("parent"));
+        assert_eq!(snapshot.executed_transaction_count, Some(12));
+        assert_eq!(snapshot.entry_count, None);
+    }
+
+    #[test]
+    fn test_entry_snapshot() {
+        let hash = [5u8; 32];
+        let info = ReplicaEntryInfo {
+            slot: 100,
+            index: 3,
+            num_hashes: 12_500,
+            hash: &hash,
+            executed_transaction_count: 4,
+        };
+        let snapshot = EntrySnapshot::from_replica(&ReplicaEntryInfoVersions::V0_0_1(&info));
+
+        assert_eq!(snapshot.index, 3);
+        assert_eq!(snapshot.hash, hash.to_vec());
+        assert_eq!(snapshot.starting_transaction_index, None);
+    }
+}
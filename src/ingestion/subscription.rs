@@ -0,0 +1,131 @@
+use super::endpoint_pool::EndpointPool;
+use crate::config::WebsocketConfig;
+use solana_client::nonblocking::pubsub_client::PubsubClientError;
+use std::future::Future;
+use thiserror::Error;
+use tokio::time::{Duration, Instant};
+
+/// Errors shared by the WebSocket subscription sources.
+#[derive(Debug, Error)]
+pub enum SubscriptionError {
+    #[error("Pubsub error: {0}")]
+    Pubsub(#[from] PubsubClientError),
+    #[error("No WebSocket endpoints configured")]
+    NoEndpoints,
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(String),
+    #[error("Subscription stream ended")]
+    StreamEnded,
+    #[error("No notifications for {0:?}")]
+    Idle(Duration),
+}
+
+/// Runs `session` against the pool's WebSocket URLs until it fails for good.
+/// Each session subscribes afresh. A long-lived session resets the backoff;
+/// a quick failure moves on to the next endpoint. `name` labels the lost
+/// connection in the logs.
+pub async fn reconnect<F, Fut>(
+    endpoints: &EndpointPool,
+    config: &WebsocketConfig,
+    name: &str,
+    mut session: F,
+) -> Result<(), SubscriptionError>
+where
+    F: FnMut(String) -> Fut,
+    Fut: Future<Output = Result<(), SubscriptionError>>,
+{
+    let initial_backoff = Duration::from_millis(config.initial_backoff_ms);
+    let max_backoff = Duration::from_millis(config.max_backoff_ms);
+    let mut backoff = initial_backoff;
+    let mut attempt = 0;
+
+    loop {
+        // Healthy endpoints come first; rotate through them on each attempt.
+        let urls = endpoints.websocket_urls();
+        if urls.is_empty() {
+            return Err(SubscriptionError::NoEndpoints);
+        }
+        let url = urls[attempt % urls.len()].clone();
+
+        let started = Instant::now();
+        if let Err(e) = session(url.clone()).await {
+            tracing::warn!("{} on {} lost: {}", name, url, e);
+        }
+        metrics::counter!("cypher_websocket_reconnects_total").increment(1);
+
+        if started.elapsed() >= max_backoff {
+            backoff = initial_backoff;
+        } else {
+            attempt += 1;
+        }
+        tokio::time::sleep(backoff).await;
+        backoff = (backoff * 2).min(max_backoff);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ingestion::endpoint_pool::Endpoint;
+    use crate::ingestion::rate_limiter::RateLimiter;
+    use solana_client::nonblocking::rpc_client::RpcClient;
+
+    fn endpoint(name: &str) -> Endpoint {
+        Endpoint::new(
+            name.to_string(),
+            Some(format!("ws://{}", name)),
+            RpcClient::new_mock(name.to_string()),
+            RateLimiter::new(1000, 1000),
+            1.0,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_resubscribes_on_next_endpoint() {
+        let endpoints = EndpointPool::new(vec![endpoint("a"), endpoint("b")]);
+        let config = WebsocketConfig {
+            initial_backoff_ms: 1,
+            max_backoff_ms: 10,
+            ..WebsocketConfig::default()
+        };
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let sessions = reconnect(&endpoints, &config, "Test subscriptions", |url| {
+            let sender = sender.clone();
+            async move {
+                sender.send(url).unwrap();
+                Err(SubscriptionError::Idle(Duration::from_secs(30)))
+            }
+        });
+
+        let urls = tokio::select! {
+            _ = sessions => panic!("reconnect loop returned"),
+            urls = async {
+                let mut urls = Vec::new();
+                for _ in 0..3 {
+                    urls.push(receiver.recv().await.unwrap());
+                }
+                urls
+            } => urls,
+        };
+        assert_eq!(urls, vec!["ws://a", "ws://b", "ws://a"]);
+    }
+
+    #[tokio::test]
+    async fn test_no_endpoints() {
+        let endpoints = EndpointPool::new(vec![Endpoint::new(
+            "a".to_string(),
+            None,
+            RpcClient::new_mock("a".to_string()),
+            RateLimiter::new(1000, 1000),
+            1.0,
+        )]);
+        let result = reconnect(
+            &endpoints,
+            &WebsocketConfig::default(),
+            "Test subscriptions",
+            |_| async { Ok(()) },
+        )
+        .await;
+        assert!(matches!(result, Err(SubscriptionError::NoEndpoints)));
+    }
+}
@@ -1,55 +1,339 @@
-use crate::config::Config;
+use super::endpoint_pool::EndpointPool;
+use super::rpc_errors::Retry;
+use super::subscription::{reconnect, SubscriptionError};
+use crate::config::{Config, WebsocketConfig};
 use crate::processing::Processor;
 use async_trait::async_trait;
-use futures::StreamExt;
-use solana_client::{
-    rpc_client::RpcClient,
-    rpc_config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter},
-    rpc_response::RpcLogsResponse,
+use futures::stream::{self, StreamExt};
+use solana_client::nonblocking::pubsub_client::PubsubClient;
+use solana_client::rpc_config::{
+    RpcTransactionConfig, RpcTransactionLogsConfig, RpcTransactionLogsFilter,
 };
+use solana_client::rpc_response::{Response, RpcLogsResponse};
+use solana_sdk::commitment_config::CommitmentConfig;
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
+use solana_transaction_status::UiTransactionEncoding;
+use std::collections::{HashSet, VecDeque};
+use std::str::FromStr;
+use std::sync::Arc;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::sync::Mutex;
+use tokio::time::Duration;
+
+/// Slot notifications are sent at `processed` while logs are `confirmed`, so
+/// a gap starts this far before the last slot seen to cover logs that had not
+/// been confirmed yet when the connection dropped.
+const CONFIRMATION_LAG_SLOTS: u64 = 32;
+/// Signatures remembered to drop duplicates from overlapping subscriptions.
+const RECENT_SIGNATURES_CAPACITY: usize = 100_000;
+
+/// Slots whose notifications may have been missed while disconnected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotGap {
+    pub start: u64,
+    pub end: u64,
+}
 
 pub struct WebsocketListener {
-    rpc_client: RpcClient,
+    endpoints: Arc<EndpointPool>,
+    config: WebsocketConfig,
+    program_ids: Vec<Pubkey>,
+    concurrency: usize,
+    retry: Retry,
+    gaps: Option<UnboundedSender<SlotGap>>,
 }
 
 impl WebsocketListener {
-    pub fn new(config: &Config) -> Self {
-        Self {
-            rpc_client: RpcClient::new(config.solana_rpc_url.clone()),
+    pub fn new(
+        config: &Config,
+        endpoints: Arc<EndpointPool>,
+        gaps: Option<UnboundedSender<SlotGap>>,
+    ) -> Result<Self, SubscriptionError> {
+        let program_ids = config
+            .websocket
+            .program_ids
+            .iter()
+            .map(|id| {
+                Pubkey::from_str(id).map_err(|_| SubscriptionError::InvalidPubkey(id.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self {
+            endpoints,
+            config: config.websocket.clone(),
+            program_ids,
+            concurrency: config.rpc_poller.max_concurrent_requests.max(1),
+            retry: Retry::new(&config.rpc_poller),
+            gaps,
+        })
+    }
+
+    fn logs_filters(&self) -> Vec<RpcTransactionLogsFilter> {
+        if self.program_ids.is_empty() {
+            return vec![RpcTransactionLogsFilter::All];
         }
+        self.program_ids
+            .iter()
+            .map(|program_id| RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]))
+            .collect()
     }
-}
 
-#[async_trait]
-impl super::IngestionSource for WebsocketListener {
-    async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
-        let logs_config = RpcTransactionLogsConfig {
-            commitment: Some(solana_sdk::commitment_config::CommitmentConfig::confirmed()),
-            kind: RpcTransactionLogsFilter::All,
+    /// Runs one connection until it drops or goes idle. `last_slot` and
+    /// `recent` carry the last slot and signatures seen across reconnects.
+    async fn run_session(
+        &self,
+        url: &str,
+        processor: &Processor,
+        last_slot: &mut Option<u64>,
+        recent: &mut RecentSignatures,
+    ) -> Result<(), SubscriptionError> {
+        let client = PubsubClient::new(url).await?;
+        let (mut slots, _slots_unsubscribe) = client.slot_subscribe().await?;
+        let mut subscriptions = Vec::new();
+        for filter in self.logs_filters() {
+            let (subscription, _unsubscribe) = client
+                .logs_subscribe(
+                    filter,
+                    RpcTransactionLogsConfig {
+                        commitment: Some(CommitmentConfig::confirmed()),
+                    },
+                )
+                .await?;
+            subscriptions.push(subscription);
+        }
+        let mut logs = stream::select_all(subscriptions);
+        tracing::info!("Subscribed to {}", url);
+
+        let idle_timeout = Duration::from_secs(self.config.idle_timeout_secs);
+        let batch_size = self.config.transaction_batch_size.max(1);
+        let mut flush = tokio::time::interval(Duration::from_millis(
+            self.config.transaction_batch_interval_ms.max(1),
+        ));
+        let mut pending = Vec::new();
+        let mut resumed = false;
+        let result = loop {
+            tokio::select! {
+                slot = slots.next() => match slot {
+                    Some(info) => {
+                        if !resumed {
+                            resumed = true;
+                            if let Some(gap) = gap_since(*last_slot, info.slot) {
+                                self.report_gap(gap);
+                            }
+                        }
+                        *last_slot = Some(info.slot);
+                        if let Err(e) = processor.process_finalized_slot(info.root).await {
+                            tracing::warn!("Failed to finalize slots up to {}: {:?}", info.root, e);
+                        }
+                    }
+                    None => break Err(SubscriptionError::StreamEnded),
+                },
+                response = logs.next() => match response {
+                    Some(response) => {
+                        if let Some(entry) = accept_logs(&response, recent) {
+                            pending.push(entry);
+                        }
+                        if pending.len() >= batch_size {
+                            self.process_batch(processor, std::mem::take(&mut pending)).await;
+                        }
+                    }
+                    None => break Err(SubscriptionError::StreamEnded),
+                },
+                _ = flush.tick(), if !pending.is_empty() => {
+                    self.process_batch(processor, std::mem::take(&mut pending)).await;
+                }
+                _ = tokio::time::sleep(idle_timeout) => break Err(SubscriptionError::Idle(idle_timeout)),
+            }
+        };
+
+        drop(slots);
+        drop(logs);
+        let _ = client.shutdown().await;
+        // Signatures already received are still fetched over HTTP.
+        self.process_batch(processor, pending).await;
+        result
+    }
+
+    /// Fetches a batch of transactions concurrently and processes them in
+    /// the order their notifications arrived. A transaction that cannot be
+    /// fetched after retrying has its slot reported as a gap, since its
+    /// signature will not be notified again.
+    async fn process_batch(&self, processor: &Processor, batch: Vec<(Signature, u64)>) {
+        if batch.is_empty() {
+            return;
+        }
+        metrics::counter!("cypher_websocket_transactions_fetched_total")
+            .increment(batch.len() as u64);
+
+        let config = RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Json),
+            commitment: Some(CommitmentConfig::confirmed()),
+            max_supported_transaction_version: Some(0),
         };
+        let mut transactions = stream::iter(batch)
+            .map(|(signature, slot)| async move {
+                let transaction = self
+                    .retry
+                    .run("getTransaction", || {
+                        self.endpoints.call(|client| async move {
+                            client.get_transaction_with_config(&signature, config).await
+                        })
+                    })
+                    .await;
+                (signature, slot, transaction)
+            })
+            .buffered(self.concurrency);
 
-        let (mut logs_notifications, logs_unsubscribe) =
-            self.rpc_client.on_logs_event(logs_config).await?;
-
-        while let Some(logs) = logs_notifications.next().await {
-            match logs {
-                RpcLogsResponse::Logs {
-                    signature, logs, ..
-                } => {
-                    let transaction = self
-                        .rpc_client
-                        .get_transaction(
-                            &signature,
-                            solana_transaction_status::UiTransactionEncoding::Json,
-                        )
-                        .await?;
-                    processor.process_transaction(transaction, logs).await?;
+        while let Some((signature, slot, transaction)) = transactions.next().await {
+            match transaction {
+                Ok(transaction) => {
+                    if let Err(e) = processor.process_transaction(transaction, slot).await {
+                        tracing::error!("Failed to process transaction {}: {:?}", signature, e);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to fetch transaction {}: {}", signature, e);
+                    self.report_gap(SlotGap {
+                        start: slot,
+                        end: slot,
+                    });
                 }
-                _ => {}
             }
         }
+    }
+
+    fn report_gap(&self, gap: SlotGap) {
+        tracing::warn!(
+            "WebSocket listener may have missed slots {}..={}",
+            gap.start,
+            gap.end
+        );
+        metrics::counter!("cypher_websocket_gap_slots_total").increment(gap.end - gap.start + 1);
+        if let Some(gaps) = &self.gaps {
+            if gaps.send(gap).is_err() {
+                tracing::warn!(
+                    "Gap receiver dropped; slots {}..={} will not be refilled",
+                    gap.start,
+                    gap.end
+                );
+            }
+        }
+    }
+}
 
-        logs_unsubscribe().await?;
+/// Signature and slot of a logs notification, unless it was already seen on
+/// another program's subscription.
+fn accept_logs(
+    response: &Response<RpcLogsResponse>,
+    recent: &mut RecentSignatures,
+) -> Option<(Signature, u64)> {
+    let signature = match Signature::from_str(&response.value.signature) {
+        Ok(signature) => signature,
+        Err(_) => {
+            tracing::warn!(
+                "Invalid signature in logs notification: {}",
+                response.value.signature
+            );
+            return None;
+        }
+    };
+    recent
+        .insert(signature)
+        .then_some((signature, response.context.slot))
+}
+
+/// Bounded set of the most recently seen signatures.
+struct RecentSignatures {
+    capacity: usize,
+    order: VecDeque<Signature>,
+    seen: HashSet<Signature>,
+}
+
+impl RecentSignatures {
+    fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            order: VecDeque::with_capacity(capacity),
+            seen: HashSet::with_capacity(capacity),
+        }
+    }
+
+    /// Returns false if the signature was already seen.
+    fn insert(&mut self, signature: Signature) -> bool {
+        if !self.seen.insert(signature) {
+            return false;
+        }
+        self.order.push_back(signature);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.seen.remove(&oldest);
+            }
+        }
+        true
+    }
+}
+
+/// The slots to refill after reconnecting at `resumed_at`, given the last slot
+/// seen before the connection dropped.
+fn gap_since(last_slot: Option<u64>, resumed_at: u64) -> Option<SlotGap> {
+    let last_slot = last_slot?;
+    (resumed_at > last_slot).then(|| SlotGap {
+        start: last_slot.saturating_sub(CONFIRMATION_LAG_SLOTS),
+        end: resumed_at,
+    })
+}
+
+#[async_trait]
+impl super::IngestionSource for WebsocketListener {
+    async fn start(&self, processor: Processor) -> Result<(), Box<dyn std::error::Error>> {
+        // Carried across sessions; only one session runs at a time.
+        let state = Mutex::new((None, RecentSignatures::new(RECENT_SIGNATURES_CAPACITY)));
+        let (processor, state) = (&processor, &state);
+        reconnect(
+            &self.endpoints,
+            &self.config,
+            "WebSocket connection",
+            |url| async move {
+                let mut state = state.lock().await;
+                let (last_slot, recent) = &mut *state;
+                self.run_session(&url, processor, last_slot, recent).await
+            },
+        )
+        .await?;
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap_since() {
+        assert_eq!(gap_since(None, 100), None);
+        assert_eq!(gap_since(Some(100), 100), None);
+        assert_eq!(
+            gap_since(Some(100), 150),
+            Some(SlotGap {
+                start: 100 - CONFIRMATION_LAG_SLOTS,
+                end: 150
+            })
+        );
+        assert_eq!(gap_since(Some(10), 20), Some(SlotGap { start: 0, end: 20 }));
+    }
+
+    #[test]
+    fn test_recent_signatures() {
+        let signatures: Vec<_> = (0..3).map(|_| Signature::new_unique()).collect();
+        let mut recent = RecentSignatures::new(2);
+
+        assert!(recent.insert(signatures[0]));
+        assert!(!recent.insert(signatures[0]));
+        assert!(recent.insert(signatures[1]));
+        assert!(recent.insert(signatures[2]));
+        // The oldest signature was evicted to stay within capacity.
+        assert!(recent.insert(signatures[0]));
+        assert!(!recent.insert(signatures[2]));
+    }
+}
@@ -7,11 +7,19 @@ pub mod utils;
 pub mod wasm;
 
 use crate::api::ApiServer;
-use crate::config::Config;
-use crate::ingestion::{GeyserPlugin, RpcPoller, WebsocketListener};
+use crate::config::{Config, RecordingFormat};
+use crate::ingestion::{
+    AccountSubscriber, Backfill, BackfillRange, BackfillSource, BlockSubscriber, Deduplicator,
+    EndpointPool, GeyserPlugin, GrpcSource, IngestionSource, LedgerArchive, Recorder, ReplaySource,
+    RpcPoller, WebsocketListener,
+};
+use crate::processing::lookup_tables::LookupTableCache;
 use crate::processing::Processor;
+use crate::storage::Commitment;
 use crate::storage::{ipfs::IpfsStorage, Storage};
 use crate::wasm::runtime::WasmRuntime;
+use std::sync::Arc;
+use std::time::Duration;
 
 pub async fn run_indexer() -> Result<(), Box<dyn std::error::Error>> {
     let config = Config::load()?;
@@ -24,28 +32,96 @@ pub async fn run_indexer() -> Result<(), Box<dyn std::error::Error>> {
     let wasm_bytes = std::fs::read(&config.wasm_module_path)?;
     wasm_runtime.run_module(&wasm_bytes, "start", &[])?;
 
-    let processor = Processor::new(storage.clone(), ipfs_storage);
+    let mut processor = Processor::new(storage.clone(), ipfs_storage);
+    if config.dedup.enabled {
+        processor = processor.with_deduplicator(Arc::new(Deduplicator::new(&config.dedup)));
+    }
+    if let Some(recording) = &config.recording {
+        let recorder = Recorder::create(&recording.path, recording.format)?;
+        tracing::info!("Recording events to {}", recording.path);
+        processor = processor.with_recorder(Arc::new(recorder));
+    }
+
+    let endpoints = Arc::new(EndpointPool::from_config(&config));
+    tokio::spawn(
+        endpoints
+            .clone()
+            .run_health_checks(Duration::from_secs(config.rpc_health_check_interval_secs)),
+    );
+    if config.lookup_tables.fetch_missing {
+        processor = processor.with_lookup_tables(Arc::new(LookupTableCache::new(
+            endpoints.clone(),
+            config.lookup_tables.max_cached_tables,
+        )));
+    }
+
+    // In gap-fill mode the poller only fetches slots the listener missed.
+    let (gap_sender, gap_receiver) = if config.rpc_poller.gap_fill_only {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        (Some(sender), Some(receiver))
+    } else {
+        (None, None)
+    };
 
     let geyser_plugin = GeyserPlugin::new(&config);
-    let rpc_poller = RpcPoller::new(&config);
-    let websocket_listener = WebsocketListener::new(&config);
+    let rpc_poller = RpcPoller::new(&config, endpoints.clone(), storage.clone(), gap_receiver);
+    let websocket_listener = WebsocketListener::new(&config, endpoints.clone(), gap_sender)?;
+    let account_subscriber = AccountSubscriber::new(&config, endpoints.clone())?;
+    let block_subscriber = BlockSubscriber::new(&config, endpoints.clone());
+    let grpc_source = config.grpc.clone().map(GrpcSource::new).transpose()?;
 
     let api_server = ApiServer::new(storage.clone(), &config);
 
     tokio::try_join!(
-        tokio::spawn(async move {
-            if let Err(e) = geyser_plugin.start(processor.clone()).await {
-                tracing::error!("Geyser plugin error: {:?}", e);
+        tokio::spawn({
+            let processor = processor.clone();
+            async move {
+                if let Err(e) = geyser_plugin.start(processor).await {
+                    tracing::error!("Geyser plugin error: {:?}", e);
+                }
+            }
+        }),
+        tokio::spawn({
+            let processor = processor.clone().with_commitment(Commitment::Confirmed);
+            async move {
+                if let Err(e) = rpc_poller.start(processor).await {
+                    tracing::error!("RPC poller error: {:?}", e);
+                }
+            }
+        }),
+        tokio::spawn({
+            let processor = processor.clone().with_commitment(Commitment::Confirmed);
+            async move {
+                if let Err(e) = websocket_listener.start(processor).await {
+                    tracing::error!("WebSocket listener error: {:?}", e);
+                }
             }
         }),
-        tokio::spawn(async move {
-            if let Err(e) = rpc_poller.start(processor.clone()).await {
-                tracing::error!("RPC poller error: {:?}", e);
+        tokio::spawn({
+            let processor = processor.clone().with_commitment(Commitment::Confirmed);
+            async move {
+                if let Err(e) = account_subscriber.start(processor).await {
+                    tracing::error!("Account subscriber error: {:?}", e);
+                }
             }
         }),
-        tokio::spawn(async move {
-            if let Err(e) = websocket_listener.start(processor).await {
-                tracing::error!("WebSocket listener error: {:?}", e);
+        tokio::spawn({
+            let processor = processor.clone().with_commitment(Commitment::Confirmed);
+            async move {
+                if let Err(e) = block_subscriber.start(processor).await {
+                    tracing::error!("Block subscriber error: {:?}", e);
+                }
+            }
+        }),
+        tokio::spawn({
+            let processor = processor.clone();
+            async move {
+                if let Some(grpc_source) = grpc_source {
+                    let processor = processor.with_commitment(grpc_source.commitment());
+                    if let Err(e) = grpc_source.start(processor).await {
+                        tracing::error!("gRPC source error: {:?}", e);
+                    }
+                }
             }
         }),
         api_server.start(),
@@ -53,3 +129,61 @@ pub async fn run_indexer() -> Result<(), Box<dyn std::error::Error>> {
 
     Ok(())
 }
+
+/// Runs a recording through the pipeline instead of live sources.
+pub async fn run_replay(
+    path: &str,
+    format: Option<RecordingFormat>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+
+    let storage = Storage::new(&config).await?;
+    let ipfs_storage = IpfsStorage::new(&config.ipfs_api_url);
+    let mut processor = Processor::new(storage, ipfs_storage);
+    if config.dedup.enabled {
+        processor = processor.with_deduplicator(Arc::new(Deduplicator::new(&config.dedup)));
+    }
+
+    ReplaySource::new(path, format).start(processor).await
+}
+
+pub async fn run_backfill(
+    range: BackfillRange,
+    source: BackfillSource,
+    concurrency: usize,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = Config::load()?;
+
+    let storage = Storage::new(&config).await?;
+    let ipfs_storage = IpfsStorage::new(&config.ipfs_api_url);
+    let processor = Processor::new(storage.clone(), ipfs_storage);
+
+    let report = match (source, range) {
+        (BackfillSource::Rpc, range) => {
+            let processor = processor.with_commitment(Commitment::Confirmed);
+            let endpoints = Arc::new(EndpointPool::from_config(&config));
+            Backfill::new(&config, endpoints, storage, concurrency)
+                .run(&processor, range)
+                .await?
+        }
+        // The archive only holds rooted blocks.
+        (BackfillSource::Bigtable, BackfillRange::Slots { start, end }) => {
+            let processor = processor.with_commitment(Commitment::Finalized);
+            LedgerArchive::new(&config.ledger_archive)
+                .await?
+                .run(&processor, start, end)
+                .await?
+        }
+        (BackfillSource::Bigtable, BackfillRange::Program { .. }) => {
+            return Err("program backfills are only supported from RPC".into());
+        }
+    };
+    tracing::info!(
+        "Backfill finished: {} blocks, {} transactions, {} skipped slots",
+        report.blocks,
+        report.transactions,
+        report.skipped_slots
+    );
+
+    Ok(())
+}
@@ -1,12 +1,83 @@
-use cypher_indexer::run_indexer;
+use clap::{Args, Parser, Subcommand};
+use cypher_indexer::config::RecordingFormat;
+use cypher_indexer::ingestion::{BackfillRange, BackfillSource};
+use cypher_indexer::{run_backfill, run_indexer, run_replay};
+use solana_sdk::pubkey::Pubkey;
+use solana_sdk::signature::Signature;
 use tokio;
 use tracing_subscriber::{fmt, EnvFilter};
 
+#[derive(Parser)]
+#[command(name = "cypher_indexer")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the live indexer (default)
+    Run,
+    /// Index historical blocks or a program's transaction history
+    Backfill(BackfillArgs),
+    /// Process a recorded event file instead of a live cluster
+    Replay(ReplayArgs),
+}
+
+#[derive(Args)]
+struct ReplayArgs {
+    path: String,
+    /// `jsonl` or `bincode`; inferred from the extension by default
+    #[arg(long)]
+    format: Option<RecordingFormat>,
+}
+
+#[derive(Args)]
+struct BackfillArgs {
+    #[arg(long, requires = "end_slot", conflicts_with = "program")]
+    start_slot: Option<u64>,
+    #[arg(long, requires = "start_slot")]
+    end_slot: Option<u64>,
+    #[arg(long, required_unless_present = "start_slot")]
+    program: Option<Pubkey>,
+    /// Start walking back from this signature instead of the tip
+    #[arg(long, requires = "program")]
+    before: Option<Signature>,
+    /// Stop at this signature
+    #[arg(long, requires = "program")]
+    until: Option<Signature>,
+    /// `rpc` or `bigtable` (a ledger-archive instance; slot ranges only)
+    #[arg(long, default_value = "rpc")]
+    source: BackfillSource,
+    #[arg(long, default_value_t = 8)]
+    concurrency: usize,
+}
+
+impl BackfillArgs {
+    fn range(&self) -> BackfillRange {
+        match (self.start_slot, self.end_slot, self.program) {
+            (Some(start), Some(end), _) => BackfillRange::Slots { start, end },
+            (_, _, Some(program_id)) => BackfillRange::Program {
+                program_id,
+                before: self.before,
+                until: self.until,
+            },
+            _ => unreachable!("clap enforces a slot range or a program"),
+        }
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .init();
 
-    run_indexer().await
+    match Cli::parse().command {
+        Some(Command::Backfill(args)) => {
+            run_backfill(args.range(), args.source, args.concurrency).await
+        }
+        Some(Command::Replay(args)) => run_replay(&args.path, args.format).await,
+        Some(Command::Run) | None => run_indexer().await,
+    }
 }
@@ -1,3 +1,4 @@
+use crate::processing::programs::token::{self, TokenAccount, TokenMint, TokenProgramAccount};
 use borsh::{BorshDeserialize, BorshSerialize};
 use solana_sdk::pubkey::Pubkey;
 use thiserror::Error;
@@ -28,6 +29,9 @@ impl AccountParser {
         if data.len() < 1 {
             return Err(AccountParseError::InvalidDataLength);
         }
+        if token::is_token_program(owner) {
+            return self.parse_token_program_account(pubkey, data, owner);
+        }
 
         let account_type = data[0];
         match account_type {
@@ -74,6 +78,27 @@ impl AccountParser {
         })
     }
 
+    fn parse_token_program_account(
+        &self,
+        pubkey: &Pubkey,
+        data: &[u8],
+        owner: &Pubkey,
+    ) -> Result<ParsedAccount, AccountParseError> {
+        match token::parse_account(data)? {
+            Some(TokenProgramAccount::Mint(mint)) => Ok(ParsedAccount::TokenMint {
+                pubkey: *pubkey,
+                program_id: *owner,
+                data: mint,
+            }),
+            Some(TokenProgramAccount::Account(account)) => Ok(ParsedAccount::TokenAccount {
+                pubkey: *pubkey,
+                program_id: *owner,
+                data: account,
+            }),
+            None => self.parse_unknown_account(pubkey, data, owner),
+        }
+    }
+
     fn parse_unknown_account(
         &self,
         pubkey: &Pubkey,
@@ -131,9 +156,33 @@ pub enum ParsedAccount {
         pubkey: Pubkey,
         data: CypherMetadata,
     },
+    /// A mint of SPL Token or Token-2022; `program_id` says which.
+    TokenMint {
+        pubkey: Pubkey,
+        program_id: Pubkey,
+        data: TokenMint,
+    },
+    TokenAccount {
+        pubkey: Pubkey,
+        program_id: Pubkey,
+        data: TokenAccount,
+    },
     Unknown {
         pubkey: Pubkey,
         owner: Pubkey,
         data: Vec<u8>,
     },
 }
+
+impl ParsedAccount {
+    pub fn pubkey(&self) -> &Pubkey {
+        match self {
+            ParsedAccount::CypherMint { pubkey, .. }
+            | ParsedAccount::CypherToken { pubkey, .. }
+            | ParsedAccount::CypherMetadata { pubkey, .. }
+            | ParsedAccount::TokenMint { pubkey, .. }
+            | ParsedAccount::TokenAccount { pubkey, .. }
+            | ParsedAccount::Unknown { pubkey, .. } => pubkey,
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use super::resolved_transaction::ResolvedTransaction;
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::CompiledInstruction;
+use solana_sdk::pubkey::Pubkey;
+
+/// Where an instruction ran in its transaction's call tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstructionPosition {
+    /// Index of the top-level instruction it ran under.
+    pub outer_index: u8,
+    /// Index among that instruction's inner instructions; `None` for the
+    /// top-level instruction itself.
+    pub inner_index: Option<u8>,
+    /// 1 for top-level instructions, 2 for the programs they invoke, and so on.
+    pub stack_height: u32,
+    /// Program that invoked it, for inner instructions.
+    pub parent_program: Option<Pubkey>,
+}
+
+impl InstructionPosition {
+    /// `outer` or `outer.inner`, unique within a transaction.
+    pub fn path(&self) -> String {
+        match self.inner_index {
+            Some(inner) => format!("{}.{}", self.outer_index, inner),
+            None => self.outer_index.to_string(),
+        }
+    }
+}
+
+/// Every instruction of `transaction` in execution order: each top-level
+/// instruction followed by the inner instructions it caused.
+pub fn walk(transaction: &ResolvedTransaction) -> Vec<(InstructionPosition, &CompiledInstruction)> {
+    let mut walked = Vec::new();
+    for (outer_index, instruction) in transaction.instructions.iter().enumerate() {
+        let outer_index = outer_index as u8;
+        walked.push((
+            InstructionPosition {
+                outer_index,
+                inner_index: None,
+                stack_height: 1,
+                parent_program: None,
+            },
+            instruction,
+        ));
+
+        // Programs currently on the invocation stack, outermost first.
+        let mut stack = vec![transaction.program_id(instruction)];
+        let inner = transaction
+            .inner_instructions
+            .iter()
+            .filter(|inner| inner.index == outer_index)
+            .flat_map(|inner| &inner.instructions);
+        for (inner_index, inner) in inner.enumerate() {
+            // Meta from before stack heights were recorded: assume a direct
+            // call from the top-level instruction.
+            let stack_height = inner.stack_height.unwrap_or(2).max(2);
+            stack.truncate(stack_height as usize - 1);
+            walked.push((
+                InstructionPosition {
+                    outer_index,
+                    inner_index: Some(inner_index as u8),
+                    stack_height,
+                    parent_program: stack.last().copied().flatten(),
+                },
+                &inner.instruction,
+            ));
+            stack.push(transaction.program_id(&inner.instruction));
+        }
+    }
+    walked
+}
+
+/// A stored instruction and the instructions it invoked.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct InstructionNode {
+    pub program_id: String,
+    pub instruction_type: String,
+    pub data: String,
+    pub outer_index: u8,
+    pub inner_index: Option<u8>,
+    pub stack_height: u32,
+    pub children: Vec<InstructionNode>,
+}
+
+/// Nests instructions listed in execution order under their callers, using
+/// each one's stack height.
+pub fn assemble(instructions: Vec<InstructionNode>) -> Vec<InstructionNode> {
+    let mut roots = Vec::new();
+    let mut path: Vec<InstructionNode> = Vec::new();
+    for node in instructions {
+        while path.len() >= node.stack_height.max(1) as usize {
+            close(&mut path, &mut roots);
+        }
+        path.push(node);
+    }
+    while !path.is_empty() {
+        close(&mut path, &mut roots);
+    }
+    roots
+}
+
+fn close(path: &mut Vec<InstructionNode>, roots: &mut Vec<InstructionNode>) {
+    if let Some(node) = path.pop() {
+        match path.last_mut() {
+            Some(parent) => parent.children.push(node),
+            None => roots.push(node),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::message::v0::LoadedAddresses;
+    use solana_sdk::message::MessageHeader;
+    use solana_transaction_status::{InnerInstruction, InnerInstructions};
+
+    fn inner(program_id_index: u8, stack_height: Option<u32>) -> InnerInstruction {
+        InnerInstruction {
+            instruction: CompiledInstruction::new_from_raw_parts(program_id_index, vec![], vec![]),
+            stack_height,
+        }
+    }
+
+    #[test]
+    fn test_walk_links_parents() {
+        let programs: Vec<Pubkey> = (0..4).map(|_| Pubkey::new_unique()).collect();
+        // 0 calls 1, which calls 2; then 0 calls 3.
+        let transaction = ResolvedTransaction {
+            signature: String::new(),
+            header: MessageHeader::default(),
+            static_keys: programs.clone(),
+            loaded: LoadedAddresses::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(0, vec![], vec![])],
+            inner_instructions: vec![InnerInstructions {
+                index: 0,
+                instructions: vec![inner(1, Some(2)), inner(2, Some(3)), inner(3, Some(2))],
+            }],
+        };
+
+        let positions: Vec<_> = walk(&transaction).into_iter().map(|(p, _)| p).collect();
+        assert_eq!(positions.len(), 4);
+        assert_eq!(positions[0].parent_program, None);
+        assert_eq!(positions[1].parent_program, Some(programs[0]));
+        assert_eq!(positions[2].parent_program, Some(programs[1]));
+        assert_eq!(positions[2].stack_height, 3);
+        assert_eq!(positions[3].parent_program, Some(programs[0]));
+        assert_eq!(positions[3].path(), "0.2");
+    }
+
+    #[test]
+    fn test_assemble_nests_by_stack_height() {
+        let node = |outer_index, inner_index, stack_height| InstructionNode {
+            program_id: String::new(),
+            instruction_type: "unknown".to_string(),
+            data: String::new(),
+            outer_index,
+            inner_index,
+            stack_height,
+            children: Vec::new(),
+        };
+        let tree = assemble(vec![
+            node(0, None, 1),
+            node(0, Some(0), 2),
+            node(0, Some(1), 3),
+            node(0, Some(2), 2),
+            node(1, None, 1),
+        ]);
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].children.len(), 2);
+        assert_eq!(tree[0].children[0].children[0].inner_index, Some(1));
+        assert_eq!(tree[0].children[1].inner_index, Some(2));
+        assert!(tree[1].children.is_empty());
+    }
+
+    #[test]
+    fn test_assemble_cypher_instructions() {
+        use crate::processing::instruction_parser::{
+            ASSOCIATED_CYPHER_PROGRAM_ID, CYPHER_PROGRAM_ID,
+        };
+
+        let node = |program_id: Pubkey, instruction_type: &str, inner_index, stack_height| {
+            InstructionNode {
+                program_id: program_id.to_string(),
+                instruction_type: instruction_type.to_string(),
+                data: String::new(),
+                outer_index: 0,
+                inner_index,
+                stack_height,
+                children: Vec::new(),
+            }
+        };
+        let system = solana_sdk::system_program::id();
+        // Creating an associated account creates it through the System
+        // program, then initializes it through the Cypher program.
+        let tree = assemble(vec![
+            node(
+                ASSOCIATED_CYPHER_PROGRAM_ID,
+                "create_associated_account",
+                None,
+                1,
+            ),
+            node(system, "create_account", Some(0), 2),
+            node(CYPHER_PROGRAM_ID, "initialize", Some(1), 2),
+        ]);
+
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].instruction_type, "create_associated_account");
+        let children: Vec<_> = tree[0]
+            .children
+            .iter()
+            .map(|child| (child.program_id.clone(), child.instruction_type.as_str()))
+            .collect();
+        assert_eq!(
+            children,
+            vec![
+                (system.to_string(), "create_account"),
+                (CYPHER_PROGRAM_ID.to_string(), "initialize"),
+            ]
+        );
+    }
+}
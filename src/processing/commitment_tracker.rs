@@ -0,0 +1,108 @@
+use crate::storage::Commitment;
+use parking_lot::RwLock;
+use std::collections::BTreeMap;
+
+/// Slot statuses kept behind the newest slot seen. Older slots fall back to
+/// the finalized watermark.
+const RETAINED_SLOTS: u64 = 10_000;
+
+/// Highest commitment each recent slot has reached, shared by every clone of
+/// a processor so rows written after a status change get the new level.
+#[derive(Default)]
+pub struct CommitmentTracker {
+    inner: RwLock<Inner>,
+}
+
+#[derive(Default)]
+struct Inner {
+    slots: BTreeMap<u64, Commitment>,
+    /// Every slot at or below this one is finalized.
+    finalized: Option<u64>,
+}
+
+impl CommitmentTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The commitment `slot` is known to have reached, if any.
+    pub fn get(&self, slot: u64) -> Option<Commitment> {
+        let inner = self.inner.read();
+        if inner.finalized >= Some(slot) {
+            return Some(Commitment::Finalized);
+        }
+        inner.slots.get(&slot).copied()
+    }
+
+    /// Records `slot` reaching `commitment`. Returns false when it was
+    /// already there, so callers only update storage on a change.
+    pub fn update(&self, slot: u64, commitment: Commitment) -> bool {
+        let mut inner = self.inner.write();
+        if inner.finalized >= Some(slot) {
+            return false;
+        }
+        if inner.slots.get(&slot) >= Some(&commitment) {
+            return false;
+        }
+        inner.slots.insert(slot, commitment);
+        let newest = inner.slots.keys().next_back().copied().unwrap_or(slot);
+        inner.prune_below(newest.saturating_sub(RETAINED_SLOTS));
+        true
+    }
+
+    /// Moves the finalized watermark up to `slot`. Returns false when it was
+    /// already there.
+    pub fn finalize_through(&self, slot: u64) -> bool {
+        let mut inner = self.inner.write();
+        if inner.finalized >= Some(slot) {
+            return false;
+        }
+        inner.finalized = Some(slot);
+        inner.prune_below(slot + 1);
+        true
+    }
+}
+
+impl Inner {
+    fn prune_below(&mut self, slot: u64) {
+        while let Some(entry) = self.slots.first_entry() {
+            if *entry.key() >= slot {
+                break;
+            }
+            entry.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commitment_only_moves_forward() {
+        let tracker = CommitmentTracker::new();
+        assert_eq!(tracker.get(10), None);
+
+        assert!(tracker.update(10, Commitment::Confirmed));
+        assert!(!tracker.update(10, Commitment::Confirmed));
+        assert!(!tracker.update(10, Commitment::Processed));
+        assert_eq!(tracker.get(10), Some(Commitment::Confirmed));
+
+        assert!(tracker.update(10, Commitment::Finalized));
+        assert_eq!(tracker.get(10), Some(Commitment::Finalized));
+    }
+
+    #[test]
+    fn test_finalize_through() {
+        let tracker = CommitmentTracker::new();
+        tracker.update(10, Commitment::Confirmed);
+        tracker.update(12, Commitment::Processed);
+
+        assert!(tracker.finalize_through(11));
+        assert!(!tracker.finalize_through(11));
+        assert_eq!(tracker.get(5), Some(Commitment::Finalized));
+        assert_eq!(tracker.get(10), Some(Commitment::Finalized));
+        assert_eq!(tracker.get(12), Some(Commitment::Processed));
+        assert!(!tracker.update(10, Commitment::Confirmed));
+    }
+}
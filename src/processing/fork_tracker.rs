@@ -0,0 +1,173 @@
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+/// Unrooted slots whose writes can still be undone. Older slots are
+/// forgotten even without a root, so undo history stays bounded.
+pub const MAX_UNROOTED_SLOTS: u64 = 1024;
+
+/// Tree of unrooted slots built from the parents reported with slot status
+/// updates and blocks. Rooting a slot settles every slot at or below it and
+/// reports the ones that were not on the rooted chain.
+#[derive(Default)]
+pub struct ForkTracker {
+    parents: BTreeMap<u64, Option<u64>>,
+    root: Option<u64>,
+}
+
+impl ForkTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_slot(&mut self, slot: u64, parent: Option<u64>) {
+        if self.root >= Some(slot) {
+            return;
+        }
+        let entry = self.parents.entry(slot).or_insert(None);
+        if parent.is_some() {
+            *entry = parent;
+        }
+    }
+
+    /// Roots `root` and returns the orphaned slots, newest first. Slots whose
+    /// ancestry is unknown are assumed to be on the rooted chain.
+    pub fn root(&mut self, root: u64) -> Vec<u64> {
+        if self.root >= Some(root) {
+            return Vec::new();
+        }
+        self.parents.entry(root).or_insert(None);
+
+        // Walk the rooted chain down to the previous root, or as far as
+        // parents are known. Every tracked slot above `floor` that is not on
+        // the chain belongs to a dead fork.
+        let mut chain = HashSet::new();
+        let mut floor = root;
+        let mut slot = root;
+        loop {
+            chain.insert(slot);
+            match self.parents.get(&slot).copied().flatten() {
+                Some(parent) if self.root >= Some(parent) => {
+                    floor = parent;
+                    break;
+                }
+                Some(parent) if self.parents.contains_key(&parent) => slot = parent,
+                Some(parent) => {
+                    floor = parent;
+                    break;
+                }
+                None => {
+                    floor = slot;
+                    break;
+                }
+            }
+        }
+
+        let mut dead = BTreeSet::new();
+        for (&slot, &parent) in self.parents.range(floor + 1..) {
+            let orphaned = if slot <= root {
+                !chain.contains(&slot)
+            } else {
+                // Descendants of the root survive; descendants of dead slots
+                // or of slots below the root that are not on the chain do not.
+                match parent {
+                    Some(parent) if parent < root => parent > floor && !chain.contains(&parent),
+                    Some(parent) => dead.contains(&parent),
+                    None => false,
+                }
+            };
+            if orphaned {
+                dead.insert(slot);
+            }
+        }
+
+        self.parents = self.parents.split_off(&root);
+        self.parents.retain(|slot, _| !dead.contains(slot));
+        self.root = Some(root);
+        dead.into_iter().rev().collect()
+    }
+
+    /// `slot` and its known ancestors above the current root, newest first.
+    pub fn ancestry(&self, slot: u64) -> Vec<u64> {
+        let mut ancestry = Vec::new();
+        let mut next = Some(slot);
+        while let Some(slot) = next.filter(|&slot| self.root < Some(slot)) {
+            ancestry.push(slot);
+            next = self.parents.get(&slot).copied().flatten();
+        }
+        ancestry
+    }
+
+    /// Drops a slot the cluster reported dead and returns it along with its
+    /// tracked descendants, newest first.
+    pub fn mark_dead(&mut self, slot: u64) -> Vec<u64> {
+        if self.root >= Some(slot) {
+            return Vec::new();
+        }
+        let mut dead = BTreeSet::from([slot]);
+        for (&child, &parent) in self.parents.range(slot + 1..) {
+            if parent.is_some_and(|parent| dead.contains(&parent)) {
+                dead.insert(child);
+            }
+        }
+        self.parents.retain(|slot, _| !dead.contains(slot));
+        dead.into_iter().rev().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dead_fork_is_reported() {
+        let mut forks = ForkTracker::new();
+        forks.root(100);
+        // 100 -> 101 -> 103 is rooted; 102 and its child 104 forked off 101.
+        forks.add_slot(101, Some(100));
+        forks.add_slot(102, Some(101));
+        forks.add_slot(103, Some(101));
+        forks.add_slot(104, Some(102));
+        forks.add_slot(105, Some(103));
+
+        assert_eq!(forks.ancestry(105), vec![105, 103, 101]);
+        assert_eq!(forks.root(103), vec![104, 102]);
+        assert_eq!(forks.ancestry(105), vec![105]);
+        assert_eq!(
+            forks.parents.keys().copied().collect::<Vec<_>>(),
+            vec![103, 105]
+        );
+        assert!(forks.root(103).is_empty());
+    }
+
+    #[test]
+    fn test_unknown_ancestry_is_kept() {
+        let mut forks = ForkTracker::new();
+        forks.add_slot(50, None);
+        forks.add_slot(60, Some(59));
+        forks.add_slot(61, Some(60));
+        forks.add_slot(62, Some(58));
+
+        // 62's parent is below the known chain, so it cannot be judged.
+        assert!(forks.root(61).is_empty());
+        assert_eq!(
+            forks.parents.keys().copied().collect::<Vec<_>>(),
+            vec![61, 62]
+        );
+    }
+
+    #[test]
+    fn test_dead_slot_takes_its_descendants() {
+        let mut forks = ForkTracker::new();
+        forks.root(100);
+        forks.add_slot(101, Some(100));
+        forks.add_slot(102, Some(101));
+        forks.add_slot(103, Some(101));
+        forks.add_slot(104, Some(102));
+
+        assert_eq!(forks.mark_dead(102), vec![104, 102]);
+        assert_eq!(
+            forks.parents.keys().copied().collect::<Vec<_>>(),
+            vec![100, 101, 103]
+        );
+        assert!(forks.mark_dead(100).is_empty());
+    }
+}
@@ -1,14 +1,32 @@
+use crate::processing::programs::address_lookup_table::{self, AddressLookupTableInstruction};
+use crate::processing::programs::compute_budget::{self, ComputeBudgetInstruction};
+use crate::processing::programs::stake::{self, StakeInstruction};
+use crate::processing::programs::system::{self, SystemInstruction};
+use crate::processing::programs::token::{
+    self, TokenInstruction, TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID,
+};
+use crate::processing::programs::vote::{self, VoteInstruction};
+use crate::processing::programs::LamportTransfer;
 use borsh::{BorshDeserialize, BorshSerialize};
+use serde::Serialize;
 use solana_sdk::instruction::{AccountMeta, CompiledInstruction};
 use solana_sdk::pubkey::Pubkey;
+use solana_sdk::system_program;
 use thiserror::Error;
 
+pub const CYPHER_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("CyphrkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const ASSOCIATED_CYPHER_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("ACyphrGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL");
+
 #[derive(Debug, Error)]
 pub enum InstructionParseError {
     #[error("Failed to deserialize instruction data: {0}")]
     DeserializationError(#[from] std::io::Error),
     #[error("Unknown instruction: {0}")]
-    UnknownInstruction(u8),
+    UnknownInstruction(u32),
+    #[error("Instruction data is empty")]
+    EmptyData,
     #[error("Invalid account index: {0}")]
     InvalidAccountIndex(usize),
 }
@@ -20,44 +38,80 @@ impl InstructionParser {
         Self
     }
 
+    /// `accounts` are the instruction's accounts in order, resolved against
+    /// the transaction's account keys. Instructions of known programs that
+    /// fail to decode, e.g. in failed transactions, are kept as `Unknown`.
     pub fn parse_instruction(
         &self,
         program_id: &Pubkey,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
-        match program_id.to_string().as_str() {
-            "CyphrkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA" => {
-                self.parse_cypher_instruction(instruction)
+        let data = &instruction.data;
+        let decoded = match *program_id {
+            CYPHER_PROGRAM_ID => self.parse_cypher_instruction(instruction, accounts),
+            ASSOCIATED_CYPHER_PROGRAM_ID => {
+                self.parse_associated_cypher_instruction(instruction, accounts)
             }
-            "ACyphrGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL" => {
-                self.parse_associated_cypher_instruction(instruction)
+            TOKEN_PROGRAM_ID | TOKEN_2022_PROGRAM_ID => token::parse_instruction(data, accounts)
+                .map(|instruction| ParsedInstruction::Token {
+                    program_id: *program_id,
+                    instruction,
+                }),
+            system_program::ID => {
+                system::parse_instruction(data, accounts).map(ParsedInstruction::System)
             }
-            _ => self.parse_unknown_instruction(program_id, instruction),
-        }
+            solana_sdk::stake::program::ID => {
+                stake::parse_instruction(data, accounts).map(ParsedInstruction::Stake)
+            }
+            solana_sdk::vote::program::ID => {
+                vote::parse_instruction(data, accounts).map(ParsedInstruction::Vote)
+            }
+            solana_sdk::address_lookup_table::program::ID => {
+                address_lookup_table::parse_instruction(data, accounts)
+                    .map(ParsedInstruction::AddressLookupTable)
+            }
+            solana_sdk::compute_budget::ID => {
+                compute_budget::parse_instruction(data).map(ParsedInstruction::ComputeBudget)
+            }
+            _ => return self.parse_unknown_instruction(program_id, instruction, accounts),
+        };
+        decoded.or_else(|err| {
+            tracing::debug!("Storing {} instruction as unknown: {}", program_id, err);
+            self.parse_unknown_instruction(program_id, instruction, accounts)
+        })
     }
 
     fn parse_cypher_instruction(
         &self,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
-        let instruction_type: u8 = instruction.data[0];
+        let instruction_type = *instruction
+            .data
+            .first()
+            .ok_or(InstructionParseError::EmptyData)?;
         match instruction_type {
-            0 => self.parse_cypher_initialize(instruction),
-            1 => self.parse_cypher_transfer(instruction),
-            2 => self.parse_cypher_mint(instruction),
-            3 => self.parse_cypher_burn(instruction),
-            _ => Err(InstructionParseError::UnknownInstruction(instruction_type)),
+            0 => self.parse_cypher_initialize(instruction, accounts),
+            1 => self.parse_cypher_transfer(instruction, accounts),
+            2 => self.parse_cypher_mint(instruction, accounts),
+            3 => self.parse_cypher_burn(instruction, accounts),
+            other => Err(InstructionParseError::UnknownInstruction(other.into())),
         }
     }
 
     fn parse_associated_cypher_instruction(
         &self,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
-        let instruction_type: u8 = instruction.data[0];
+        let instruction_type = *instruction
+            .data
+            .first()
+            .ok_or(InstructionParseError::EmptyData)?;
         match instruction_type {
-            0 => self.parse_create_associated_cypher_account(instruction),
-            _ => Err(InstructionParseError::UnknownInstruction(instruction_type)),
+            0 => self.parse_create_associated_cypher_account(accounts),
+            other => Err(InstructionParseError::UnknownInstruction(other.into())),
         }
     }
 
@@ -65,9 +119,11 @@ impl InstructionParser {
         &self,
         program_id: &Pubkey,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
         Ok(ParsedInstruction::Unknown {
             program_id: *program_id,
+            accounts: accounts.to_vec(),
             data: instruction.data.clone(),
         })
     }
@@ -75,78 +131,99 @@ impl InstructionParser {
     fn parse_cypher_initialize(
         &self,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
         let params = CypherInitializeParams::try_from_slice(&instruction.data[1..])?;
-        Ok(ParsedInstruction::CypherInitialize { params })
+        Ok(ParsedInstruction::CypherInitialize {
+            params,
+            mint: self.get_account_pubkey(accounts, 0)?,
+        })
     }
 
     fn parse_cypher_transfer(
         &self,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
         let params = CypherTransferParams::try_from_slice(&instruction.data[1..])?;
-        Ok(ParsedInstruction::CypherTransfer { params })
+        Ok(ParsedInstruction::CypherTransfer {
+            params,
+            source: self.get_account_pubkey(accounts, 0)?,
+            destination: self.get_account_pubkey(accounts, 1)?,
+            authority: self.get_account_pubkey(accounts, 2)?,
+        })
     }
 
     fn parse_cypher_mint(
         &self,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
         let params = CypherMintParams::try_from_slice(&instruction.data[1..])?;
-        Ok(ParsedInstruction::CypherMint { params })
+        Ok(ParsedInstruction::CypherMint {
+            params,
+            mint: self.get_account_pubkey(accounts, 0)?,
+            destination: self.get_account_pubkey(accounts, 1)?,
+            authority: self.get_account_pubkey(accounts, 2)?,
+        })
     }
 
     fn parse_cypher_burn(
         &self,
         instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
         let params = CypherBurnParams::try_from_slice(&instruction.data[1..])?;
-        Ok(ParsedInstruction::CypherBurn { params })
+        Ok(ParsedInstruction::CypherBurn {
+            params,
+            account: self.get_account_pubkey(accounts, 0)?,
+            mint: self.get_account_pubkey(accounts, 1)?,
+            authority: self.get_account_pubkey(accounts, 2)?,
+        })
     }
 
     fn parse_create_associated_cypher_account(
         &self,
-        instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
     ) -> Result<ParsedInstruction, InstructionParseError> {
         Ok(ParsedInstruction::CreateAssociatedCypherAccount {
-            funding_account: self.get_account_pubkey(instruction, 0)?,
-            associated_account: self.get_account_pubkey(instruction, 1)?,
-            wallet_account: self.get_account_pubkey(instruction, 2)?,
-            cypher_mint: self.get_account_pubkey(instruction, 3)?,
+            funding_account: self.get_account_pubkey(accounts, 0)?,
+            associated_account: self.get_account_pubkey(accounts, 1)?,
+            wallet_account: self.get_account_pubkey(accounts, 2)?,
+            cypher_mint: self.get_account_pubkey(accounts, 3)?,
         })
     }
 
     fn get_account_pubkey(
         &self,
-        instruction: &CompiledInstruction,
+        accounts: &[AccountMeta],
         index: usize,
     ) -> Result<Pubkey, InstructionParseError> {
-        instruction
-            .accounts
+        accounts
             .get(index)
+            .map(|account| account.pubkey)
             .ok_or(InstructionParseError::InvalidAccountIndex(index))
-            .map(|&account_index| Pubkey::new(&[account_index]))
     }
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, BorshSerialize, BorshDeserialize, Serialize)]
 pub struct CypherInitializeParams {
     pub decimals: u8,
     pub mint_authority: Pubkey,
     pub freeze_authority: Option<Pubkey>,
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, BorshSerialize, BorshDeserialize, Serialize)]
 pub struct CypherTransferParams {
     pub amount: u64,
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, BorshSerialize, BorshDeserialize, Serialize)]
 pub struct CypherMintParams {
     pub amount: u64,
 }
 
-#[derive(Debug, BorshSerialize, BorshDeserialize)]
+#[derive(Debug, BorshSerialize, BorshDeserialize, Serialize)]
 pub struct CypherBurnParams {
     pub amount: u64,
 }
@@ -155,15 +232,25 @@ pub struct CypherBurnParams {
 pub enum ParsedInstruction {
     CypherInitialize {
         params: CypherInitializeParams,
+        mint: Pubkey,
     },
     CypherTransfer {
         params: CypherTransferParams,
+        source: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
     },
     CypherMint {
         params: CypherMintParams,
+        mint: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
     },
     CypherBurn {
         params: CypherBurnParams,
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
     },
     CreateAssociatedCypherAccount {
         funding_account: Pubkey,
@@ -171,8 +258,129 @@ pub enum ParsedInstruction {
         wallet_account: Pubkey,
         cypher_mint: Pubkey,
     },
+    /// An instruction of SPL Token or Token-2022; `program_id` says which.
+    Token {
+        program_id: Pubkey,
+        instruction: TokenInstruction,
+    },
+    System(SystemInstruction),
+    Stake(StakeInstruction),
+    Vote(VoteInstruction),
+    AddressLookupTable(AddressLookupTableInstruction),
+    ComputeBudget(ComputeBudgetInstruction),
     Unknown {
         program_id: Pubkey,
+        accounts: Vec<AccountMeta>,
         data: Vec<u8>,
     },
 }
+
+impl ParsedInstruction {
+    /// Lamports moved by a native program instruction, e.g. a System
+    /// transfer or a stake withdrawal.
+    pub fn lamport_transfer(&self) -> Option<LamportTransfer> {
+        match self {
+            ParsedInstruction::System(instruction) => instruction.lamport_transfer(),
+            ParsedInstruction::Stake(instruction) => instruction.lamport_transfer(),
+            ParsedInstruction::Vote(instruction) => instruction.lamport_transfer(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_associated_account_uses_resolved_keys() {
+        let accounts: Vec<AccountMeta> = (0..4)
+            .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+            .collect();
+        let program_id: Pubkey = "ACyphrGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL"
+            .parse()
+            .unwrap();
+        // Indexes into the transaction's keys; the parser must not read them.
+        let instruction = CompiledInstruction::new_from_raw_parts(5, vec![0], vec![9, 3, 7, 1]);
+
+        let parsed = InstructionParser::new()
+            .parse_instruction(&program_id, &instruction, &accounts)
+            .unwrap();
+        match parsed {
+            ParsedInstruction::CreateAssociatedCypherAccount {
+                funding_account,
+                associated_account,
+                wallet_account,
+                cypher_mint,
+            } => {
+                assert_eq!(funding_account, accounts[0].pubkey);
+                assert_eq!(associated_account, accounts[1].pubkey);
+                assert_eq!(wallet_account, accounts[2].pubkey);
+                assert_eq!(cypher_mint, accounts[3].pubkey);
+            }
+            other => panic!("unexpected instruction: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_missing_account_is_unknown() {
+        let program_id: Pubkey = "CyphrkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+            .parse()
+            .unwrap();
+        let mut data = vec![1];
+        data.extend_from_slice(&borsh::to_vec(&CypherTransferParams { amount: 5 }).unwrap());
+        let instruction = CompiledInstruction::new_from_raw_parts(0, data, vec![1, 2]);
+        let accounts = vec![
+            AccountMeta::new(Pubkey::new_unique(), false),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ];
+
+        let parser = InstructionParser::new();
+        assert!(matches!(
+            parser.parse_cypher_instruction(&instruction, &accounts),
+            Err(InstructionParseError::InvalidAccountIndex(2))
+        ));
+        assert!(matches!(
+            parser.parse_instruction(&program_id, &instruction, &accounts),
+            Ok(ParsedInstruction::Unknown { .. })
+        ));
+    }
+
+    #[test]
+    fn test_empty_cypher_cpi_is_unknown() {
+        let accounts = vec![AccountMeta::new(Pubkey::new_unique(), false)];
+        let instruction = CompiledInstruction::new_from_raw_parts(0, Vec::new(), vec![1]);
+        let parser = InstructionParser::new();
+
+        for program_id in [CYPHER_PROGRAM_ID, ASSOCIATED_CYPHER_PROGRAM_ID] {
+            let parsed = parser
+                .parse_instruction(&program_id, &instruction, &accounts)
+                .unwrap();
+            assert!(matches!(
+                parsed,
+                ParsedInstruction::Unknown { program_id: id, ref data, .. }
+                    if id == program_id && data.is_empty()
+            ));
+        }
+        assert!(matches!(
+            parser.parse_cypher_instruction(&instruction, &accounts),
+            Err(InstructionParseError::EmptyData)
+        ));
+    }
+
+    #[test]
+    fn test_undecodable_native_instruction_is_unknown() {
+        let accounts = vec![
+            AccountMeta::new(Pubkey::new_unique(), true),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ];
+        // A System transfer whose lamports are cut short.
+        let instruction =
+            CompiledInstruction::new_from_raw_parts(0, vec![2, 0, 0, 0, 1], vec![0, 1]);
+
+        let parsed = InstructionParser::new()
+            .parse_instruction(&solana_sdk::system_program::id(), &instruction, &accounts)
+            .unwrap();
+        assert!(matches!(parsed, ParsedInstruction::Unknown { ref data, .. } if data.len() == 5));
+    }
+}
@@ -0,0 +1,135 @@
+use crate::ingestion::EndpointPool;
+use dashmap::DashMap;
+use solana_client::client_error::ClientError;
+use solana_sdk::address_lookup_table::state::AddressLookupTable;
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::pubkey::Pubkey;
+use std::sync::Arc;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum LookupTableError {
+    #[error("RPC error: {0}")]
+    Rpc(#[from] ClientError),
+    #[error("Invalid lookup table account {0}")]
+    InvalidTable(Pubkey),
+    #[error("Lookup table {table} has no address at index {index}")]
+    IndexOutOfRange { table: Pubkey, index: u8 },
+}
+
+/// Addresses of lookup tables fetched over RPC, for transactions whose meta
+/// lacks `loadedAddresses`. Tables only grow while active, so a cached table
+/// is refetched only when a lookup indexes past its end.
+pub struct LookupTableCache {
+    endpoints: Arc<EndpointPool>,
+    tables: DashMap<Pubkey, Arc<Vec<Pubkey>>>,
+    max_tables: usize,
+}
+
+impl LookupTableCache {
+    pub fn new(endpoints: Arc<EndpointPool>, max_tables: usize) -> Self {
+        Self {
+            endpoints,
+            tables: DashMap::new(),
+            max_tables: max_tables.max(1),
+        }
+    }
+
+    /// Resolves `lookups` the way the runtime does: every writable address
+    /// in lookup order, then every read-only one.
+    pub async fn load(
+        &self,
+        lookups: &[MessageAddressTableLookup],
+    ) -> Result<LoadedAddresses, LookupTableError> {
+        let mut loaded = LoadedAddresses::default();
+        for lookup in lookups {
+            let addresses = self.addresses(lookup).await?;
+            loaded.writable.extend(select(
+                &lookup.account_key,
+                &addresses,
+                &lookup.writable_indexes,
+            )?);
+            loaded.readonly.extend(select(
+                &lookup.account_key,
+                &addresses,
+                &lookup.readonly_indexes,
+            )?);
+        }
+        Ok(loaded)
+    }
+
+    async fn addresses(
+        &self,
+        lookup: &MessageAddressTableLookup,
+    ) -> Result<Arc<Vec<Pubkey>>, LookupTableError> {
+        let needed = lookup
+            .writable_indexes
+            .iter()
+            .chain(&lookup.readonly_indexes)
+            .max()
+            .map_or(0, |&index| index as usize + 1);
+        if let Some(addresses) = self.tables.get(&lookup.account_key) {
+            if addresses.len() >= needed {
+                return Ok(addresses.clone());
+            }
+        }
+
+        let key = lookup.account_key;
+        let account = self
+            .endpoints
+            .call(|client| async move { client.get_account(&key).await })
+            .await?;
+        metrics::counter!("cypher_lookup_table_fetches_total").increment(1);
+        let table = AddressLookupTable::deserialize(&account.data)
+            .map_err(|_| LookupTableError::InvalidTable(key))?;
+        let addresses = Arc::new(table.addresses.to_vec());
+
+        if self.tables.len() >= self.max_tables && !self.tables.contains_key(&key) {
+            let evicted = self.tables.iter().next().map(|entry| *entry.key());
+            if let Some(evicted) = evicted {
+                self.tables.remove(&evicted);
+            }
+        }
+        self.tables.insert(key, addresses.clone());
+        Ok(addresses)
+    }
+}
+
+fn select(
+    table: &Pubkey,
+    addresses: &[Pubkey],
+    indexes: &[u8],
+) -> Result<Vec<Pubkey>, LookupTableError> {
+    indexes
+        .iter()
+        .map(|&index| {
+            addresses
+                .get(index as usize)
+                .copied()
+                .ok_or(LookupTableError::IndexOutOfRange {
+                    table: *table,
+                    index,
+                })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_select_by_index() {
+        let table = Pubkey::new_unique();
+        let addresses = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+
+        assert_eq!(
+            select(&table, &addresses, &[1, 0]).unwrap(),
+            vec![addresses[1], addresses[0]]
+        );
+        assert!(matches!(
+            select(&table, &addresses, &[2]),
+            Err(LookupTableError::IndexOutOfRange { index: 2, .. })
+        ));
+    }
+}
@@ -1,16 +1,35 @@
+use crate::ingestion::recording::{RecordedEvent, Recorder};
+use crate::ingestion::snapshots::{AccountSnapshot, BlockSnapshot, SlotStatus};
+use crate::ingestion::{AccountVersion, Deduplicator};
 use crate::storage::ipfs::IpfsStorage;
-use crate::storage::Storage;
+use crate::storage::{Commitment, Storage};
+use rayon::prelude::*;
 use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::option_serializer::OptionSerializer;
 use solana_transaction_status::{EncodedConfirmedTransaction, UiConfirmedBlock};
+use std::sync::Arc;
 
 pub mod account_parser;
+pub mod call_tree;
+pub mod commitment_tracker;
 pub mod event_parser;
+pub mod fork_tracker;
 pub mod instruction_parser;
+pub mod lookup_tables;
+pub mod programs;
+pub mod resolved_transaction;
 pub mod state_manager;
 
 use account_parser::{AccountParser, ParsedAccount};
+use call_tree::InstructionPosition;
+use commitment_tracker::CommitmentTracker;
 use event_parser::{EventParser, ParsedEvent};
+use fork_tracker::ForkTracker;
 use instruction_parser::{InstructionParser, ParsedInstruction};
+use lookup_tables::LookupTableCache;
+use parking_lot::Mutex;
+use programs::compute_budget::TransactionFee;
+use resolved_transaction::ResolvedTransaction;
 use state_manager::StateManager;
 
 #[derive(Clone)]
@@ -21,6 +40,12 @@ pub struct Processor {
     instruction_parser: InstructionParser,
     event_parser: EventParser,
     state_manager: StateManager,
+    dedup: Option<Arc<Deduplicator>>,
+    recorder: Option<Arc<Recorder>>,
+    commitment: Commitment,
+    commitments: Arc<CommitmentTracker>,
+    forks: Arc<Mutex<ForkTracker>>,
+    lookup_tables: Option<Arc<LookupTableCache>>,
 }
 
 impl Processor {
@@ -32,6 +57,83 @@ impl Processor {
             instruction_parser: InstructionParser::new(),
             event_parser: EventParser::new(),
             state_manager: StateManager::new(),
+            dedup: None,
+            recorder: None,
+            commitment: Commitment::Processed,
+            commitments: Arc::new(CommitmentTracker::new()),
+            forks: Arc::new(Mutex::new(ForkTracker::new())),
+            lookup_tables: None,
+        }
+    }
+
+    /// Shares `dedup` across every clone of this processor, so sources that
+    /// deliver the same transaction or account update only index it once.
+    pub fn with_deduplicator(mut self, dedup: Arc<Deduplicator>) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    /// Appends every event this processor (and its clones) receives to
+    /// `recorder`, before deduplication, so a replay sees the same input.
+    pub fn with_recorder(mut self, recorder: Arc<Recorder>) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
+    /// The commitment of data handed to this processor, for sources that
+    /// only deliver e.g. confirmed blocks. Slot status updates can raise it
+    /// further; the default is `Processed`.
+    pub fn with_commitment(mut self, commitment: Commitment) -> Self {
+        self.commitment = commitment;
+        self
+    }
+
+    /// Fetches lookup tables for v0 transactions whose meta does not list
+    /// the addresses they loaded. Without it such transactions fail to index.
+    pub fn with_lookup_tables(mut self, lookup_tables: Arc<LookupTableCache>) -> Self {
+        self.lookup_tables = Some(lookup_tables);
+        self
+    }
+
+    fn commitment_at(&self, slot: u64) -> Commitment {
+        self.commitments
+            .get(slot)
+            .map_or(self.commitment, |known| known.max(self.commitment))
+    }
+
+    /// Rolls back every slot that rooting `root` orphaned, newest first, and
+    /// forgets the undo history of slots it settled.
+    async fn settle_root(&self, root: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let orphaned = self.forks.lock().root(root);
+        for slot in orphaned {
+            tracing::warn!("Slot {} was abandoned, rolling back its writes", slot);
+            metrics::counter!("cypher_fork_rollbacks_total").increment(1);
+            self.state_manager.rollback_slot(slot);
+            self.storage.rollback_slot(slot).await?;
+        }
+        self.state_manager.forget_through(root);
+        self.storage.forget_through(root);
+        Ok(())
+    }
+
+    /// Rolls back a slot the cluster reported dead, and any descendants
+    /// already seen, newest first.
+    async fn abandon_slot(&self, slot: u64) -> Result<(), Box<dyn std::error::Error>> {
+        let dead = self.forks.lock().mark_dead(slot);
+        for slot in dead {
+            tracing::warn!("Slot {} is dead, rolling back its writes", slot);
+            metrics::counter!("cypher_fork_rollbacks_total").increment(1);
+            self.state_manager.rollback_slot(slot);
+            self.storage.rollback_slot(slot).await?;
+        }
+        Ok(())
+    }
+
+    fn record(&self, event: impl FnOnce() -> RecordedEvent) {
+        if let Some(recorder) = &self.recorder {
+            if let Err(e) = recorder.record(&event()) {
+                tracing::warn!("Failed to record event: {}", e);
+            }
         }
     }
 
@@ -40,46 +142,98 @@ impl Processor {
         transaction: EncodedConfirmedTransaction,
         slot: u64,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let signature = transaction.transaction.signatures[0].to_string();
-
-        for (index, instruction) in transaction
-            .transaction
-            .message
-            .instructions
-            .iter()
-            .enumerate()
-        {
-            let program_id =
-                transaction.transaction.message.account_keys[instruction.program_id_index as usize];
+        self.record(|| RecordedEvent::Transaction {
+            slot,
+            transaction: transaction.clone(),
+        });
+        self.index_transaction(transaction, slot).await
+    }
+
+    async fn index_transaction(
+        &self,
+        transaction: EncodedConfirmedTransaction,
+        slot: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let resolved =
+            ResolvedTransaction::resolve(&transaction.transaction, self.lookup_tables.as_deref())
+                .await?;
+        // A claim dropped on error lets another source's copy through.
+        let claim = match &self.dedup {
+            Some(dedup) => match dedup.claim_transaction(&resolved.signature, slot).await {
+                Some(claim) => Some(claim),
+                None => return Ok(()),
+            },
+            None => None,
+        };
+        self.store_transaction(transaction, &resolved, slot).await?;
+        if let Some(claim) = claim {
+            claim.commit();
+        }
+        Ok(())
+    }
+
+    async fn store_transaction(
+        &self,
+        transaction: EncodedConfirmedTransaction,
+        resolved: &ResolvedTransaction,
+        slot: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let signature = &resolved.signature;
+        let commitment = self.commitment_at(slot);
+        let meta = transaction.transaction.meta.as_ref();
+
+        let account_keys = resolved.account_keys();
+        for (position, instruction) in call_tree::walk(resolved) {
+            let (Some(program_id), Some(accounts)) = (
+                resolved.program_id(instruction),
+                resolved.instruction_accounts(instruction),
+            ) else {
+                tracing::warn!(
+                    "Instruction {} in {} has an invalid account index",
+                    position.path(),
+                    signature
+                );
+                continue;
+            };
             let parsed_instruction = self
                 .instruction_parser
-                .parse_instruction(&program_id, instruction)?;
+                .parse_instruction(&program_id, instruction, &accounts)?;
             self.storage
-                .store_instruction(parsed_instruction, slot, &signature)
+                .store_instruction(parsed_instruction, &position, slot, signature, commitment)
                 .await?;
         }
 
-        if let Some(log_messages) = transaction.meta.log_messages {
-            let events = self.event_parser.parse_logs(&log_messages)?;
+        let Some(meta) = meta else {
+            return Ok(());
+        };
+
+        let fee = TransactionFee::new(resolved, meta.fee);
+        self.storage
+            .store_transaction_fee(&fee, slot, signature, commitment)
+            .await?;
+
+        if let OptionSerializer::Some(log_messages) = &meta.log_messages {
+            let events = self.event_parser.parse_logs(log_messages)?;
             for event in events {
-                self.storage.store_event(event, slot, &signature).await?;
+                self.storage
+                    .store_event(event, slot, signature, commitment)
+                    .await?;
             }
         }
 
-        if let Some(post_balances) = transaction.meta.post_balances {
-            for (index, &lamports) in post_balances.iter().enumerate() {
-                if let Some(account_keys) = &transaction.transaction.message.account_keys {
-                    let pubkey = &account_keys[index];
-                    if let Some(account_data) = &transaction.meta.post_token_balances {
-                        let parsed_account = self.account_parser.parse_account(
-                            pubkey,
-                            account_data,
-                            &Pubkey::default(),
-                        )?;
-                        self.state_manager
-                            .update_account(*pubkey, parsed_account.clone());
-                        self.storage.store_account(parsed_account, slot).await?;
-                    }
+        for (index, _) in meta.post_balances.iter().enumerate() {
+            if let Some(pubkey) = account_keys.get(index) {
+                if let OptionSerializer::Some(account_data) = &meta.post_token_balances {
+                    let parsed_account = self.account_parser.parse_account(
+                        pubkey,
+                        account_data,
+                        &Pubkey::default(),
+                    )?;
+                    self.state_manager
+                        .update_account_at(slot, *pubkey, parsed_account.clone());
+                    self.storage
+                        .store_account(parsed_account, slot, commitment)
+                        .await?;
                 }
             }
         }
@@ -92,43 +246,213 @@ impl Processor {
         block: UiConfirmedBlock,
         slot: u64,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        self.record(|| RecordedEvent::Block {
+            slot,
+            block: block.clone(),
+        });
+        self.forks.lock().add_slot(slot, Some(block.parent_slot));
         for transaction in block.transactions {
             if let Some(transaction) = transaction {
-                self.process_transaction(transaction, slot).await?;
+                self.index_transaction(transaction, slot).await?;
             }
         }
         Ok(())
     }
 
+    pub async fn process_block_metadata(
+        &self,
+        block: BlockSnapshot,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.record(|| RecordedEvent::BlockMetadata(block.clone()));
+        self.forks.lock().add_slot(block.slot, block.parent_slot);
+        self.storage.store_block_metadata(&block).await?;
+        Ok(())
+    }
+
+    /// Records a slot reaching `status`.
+    pub async fn process_slot_status(
+        &self,
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.record(|| RecordedEvent::SlotStatus {
+            slot,
+            parent,
+            status,
+        });
+        metrics::counter!("cypher_slot_status_updates_total", "status" => status.as_str())
+            .increment(1);
+        if status == SlotStatus::Dead {
+            // Rolling back records the slot as dead.
+            return self.abandon_slot(slot).await;
+        }
+        self.storage.store_slot_status(slot, parent, status).await?;
+        self.forks.lock().add_slot(slot, parent);
+        if status == SlotStatus::Rooted {
+            self.settle_root(slot).await?;
+        }
+
+        let commitment = Commitment::from(status);
+        if self.commitments.update(slot, commitment) && commitment > Commitment::Processed {
+            self.storage.update_commitment(&[slot], commitment).await?;
+        }
+        Ok(())
+    }
+
+    /// Marks `slot` and its ancestors finalized, for sources that learn the
+    /// finalized slot (e.g. `getSlot` at `finalized`) rather than each root.
+    /// Slots on abandoned forks are rolled back rather than raised.
+    pub async fn process_finalized_slot(
+        &self,
+        slot: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        if self.commitments.finalize_through(slot) {
+            metrics::gauge!("cypher_finalized_slot").set(slot as f64);
+            let chain = self.forks.lock().ancestry(slot);
+            self.settle_root(slot).await?;
+            self.storage
+                .update_commitment(&chain, Commitment::Finalized)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Updates without a `write_version` (e.g. from `accountSubscribe`) are
+    /// deduplicated by their data instead.
     pub async fn process_account_update(
         &self,
         pubkey: Pubkey,
         data: Vec<u8>,
         owner: Pubkey,
         slot: u64,
+        write_version: Option<u64>,
     ) -> Result<(), Box<dyn std::error::Error>> {
-        let parsed_account = self.account_parser.parse_account(&pubkey, &data, &owner)?;
+        self.record(|| RecordedEvent::AccountUpdate {
+            pubkey,
+            owner,
+            data: data.clone(),
+            slot,
+            write_version,
+        });
+        let claim = match &self.dedup {
+            Some(dedup) => {
+                let version = match write_version {
+                    Some(write_version) => AccountVersion::WriteVersion(write_version),
+                    None => AccountVersion::DataHash(solana_sdk::hash::hash(&data)),
+                };
+                match dedup.claim_account(&pubkey, slot, version).await {
+                    Some(claim) => Some(claim),
+                    None => return Ok(()),
+                }
+            }
+            None => None,
+        };
+        self.store_account_update(pubkey, &data, &owner, slot)
+            .await?;
+        if let Some(claim) = claim {
+            claim.commit();
+        }
+        Ok(())
+    }
+
+    async fn store_account_update(
+        &self,
+        pubkey: Pubkey,
+        data: &[u8],
+        owner: &Pubkey,
+        slot: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let parsed_account = self.account_parser.parse_account(&pubkey, data, owner)?;
         self.state_manager
-            .update_account(pubkey, parsed_account.clone());
-        self.storage.store_account(parsed_account, slot).await?;
+            .update_account_at(slot, pubkey, parsed_account.clone());
+        self.storage
+            .store_account(parsed_account, slot, self.commitment_at(slot))
+            .await?;
+        Ok(())
+    }
+
+    /// Bulk path for `is_startup` account updates from a validator snapshot.
+    /// Accounts are parsed in parallel, seeded into the state manager and
+    /// written with one bulk insert per backend.
+    pub async fn process_startup_accounts(
+        &self,
+        accounts: Vec<AccountSnapshot>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for account in &accounts {
+            self.record(|| RecordedEvent::AccountUpdate {
+                pubkey: account.pubkey,
+                owner: account.owner,
+                data: account.data.clone(),
+                slot: account.slot,
+                write_version: Some(account.write_version),
+            });
+        }
+        // Parsing is CPU-bound; rayon runs it from a blocking thread so the
+        // runtime's workers stay free.
+        let parsed: Vec<(Pubkey, ParsedAccount, u64)> = tokio::task::spawn_blocking(move || {
+            let account_parser = AccountParser::new();
+            accounts
+                .par_iter()
+                .filter_map(|account| {
+                    match account_parser.parse_account(
+                        &account.pubkey,
+                        &account.data,
+                        &account.owner,
+                    ) {
+                        Ok(parsed) => Some((account.pubkey, parsed, account.slot)),
+                        Err(e) => {
+                            tracing::warn!(
+                                "Skipping startup account {}: {:?}",
+                                account.pubkey,
+                                e
+                            );
+                            None
+                        }
+                    }
+                })
+                .collect()
+        })
+        .await?;
+
+        let mut rows = Vec::with_capacity(parsed.len());
+        for (pubkey, parsed_account, slot) in parsed {
+            self.state_manager
+                .update_account(pubkey, parsed_account.clone());
+            rows.push((parsed_account, slot));
+        }
+        metrics::counter!("cypher_startup_accounts_loaded_total").increment(rows.len() as u64);
+        // Startup snapshots are taken from a rooted bank.
+        self.storage
+            .store_accounts(rows, Commitment::Finalized)
+            .await?;
         Ok(())
     }
 
     pub async fn process_instruction(
         &self,
         instruction: ParsedInstruction,
+        position: &InstructionPosition,
         slot: u64,
         tx_signature: &str,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        let commitment = self.commitment_at(slot);
         match instruction {
-            ParsedInstruction::CypherInitialize { params } => {
+            ParsedInstruction::CypherInitialize { params, mint } => {
                 self.storage
-                    .store_instruction(instruction.clone(), slot, tx_signature)
+                    .store_instruction(
+                        instruction.clone(),
+                        position,
+                        slot,
+                        tx_signature,
+                        commitment,
+                    )
                     .await?;
-                self.state_manager.update_account(
-                    params.mint_authority,
+                self.state_manager.update_account_at(
+                    slot,
+                    mint,
                     ParsedAccount::CypherMint {
-                        pubkey: params.mint_authority,
+                        pubkey: mint,
                         data: CypherMintData {
                             supply: 0,
                             decimals: params.decimals,
@@ -138,41 +462,53 @@ impl Processor {
                     },
                 );
             }
-            ParsedInstruction::CypherTransfer { params } => {
+            // Transfers move tokens between accounts and leave the supply alone.
+            ParsedInstruction::CypherTransfer { .. } => {
                 self.storage
-                    .store_instruction(instruction.clone(), slot, tx_signature)
+                    .store_instruction(
+                        instruction.clone(),
+                        position,
+                        slot,
+                        tx_signature,
+                        commitment,
+                    )
                     .await?;
-                let mint_account = self
-                    .state_manager
-                    .get_account(&params.mint)
-                    .ok_or(Box::new(ProcessorError::InvalidAccount))?;
-                let mint_data = mint_account.data.downcast_mut::<CypherMintData>().unwrap();
-                mint_data.supply -= params.amount;
-                self.state_manager.update_account(params.mint, mint_account);
             }
-            ParsedInstruction::CypherMint { params } => {
+            ParsedInstruction::CypherMint { params, mint, .. } => {
                 self.storage
-                    .store_instruction(instruction.clone(), slot, tx_signature)
+                    .store_instruction(
+                        instruction.clone(),
+                        position,
+                        slot,
+                        tx_signature,
+                        commitment,
+                    )
                     .await?;
                 let mint_account = self
                     .state_manager
-                    .get_account(&params.mint)
+                    .get_account(&mint)
                     .ok_or(Box::new(ProcessorError::InvalidAccount))?;
                 let mint_data = mint_account.data.downcast_mut::<CypherMintData>().unwrap();
                 mint_data.supply += params.amount;
-                self.state_manager.update_account(params.mint, mint_account);
+                self.state_manager.update_account_at(slot, mint, mint_account);
             }
-            ParsedInstruction::CypherBurn { params } => {
+            ParsedInstruction::CypherBurn { params, mint, .. } => {
                 self.storage
-                    .store_instruction(instruction.clone(), slot, tx_signature)
+                    .store_instruction(
+                        instruction.clone(),
+                        position,
+                        slot,
+                        tx_signature,
+                        commitment,
+                    )
                     .await?;
                 let mint_account = self
                     .state_manager
-                    .get_account(&params.mint)
+                    .get_account(&mint)
                     .ok_or(Box::new(ProcessorError::InvalidAccount))?;
                 let mint_data = mint_account.data.downcast_mut::<CypherMintData>().unwrap();
                 mint_data.supply -= params.amount;
-                self.state_manager.update_account(params.mint, mint_account);
+                self.state_manager.update_account_at(slot, mint, mint_account);
             }
             ParsedInstruction::CreateAssociatedCypherAccount {
                 funding_account,
@@ -181,9 +517,16 @@ impl Processor {
                 cypher_mint,
             } => {
                 self.storage
-                    .store_instruction(instruction.clone(), slot, tx_signature)
+                    .store_instruction(
+                        instruction.clone(),
+                        position,
+                        slot,
+                        tx_signature,
+                        commitment,
+                    )
                     .await?;
-                self.state_manager.update_account(
+                self.state_manager.update_account_at(
+                    slot,
                     funding_account,
                     ParsedAccount::Unknown {
                         pubkey: funding_account,
@@ -191,7 +534,8 @@ impl Processor {
                         data: vec![],
                     },
                 );
-                self.state_manager.update_account(
+                self.state_manager.update_account_at(
+                    slot,
                     associated_account,
                     ParsedAccount::Unknown {
                         pubkey: associated_account,
@@ -199,7 +543,8 @@ impl Processor {
                         data: vec![],
                     },
                 );
-                self.state_manager.update_account(
+                self.state_manager.update_account_at(
+                    slot,
                     cypher_mint,
                     ParsedAccount::CypherMint {
                         pubkey: cypher_mint,
@@ -212,6 +557,19 @@ impl Processor {
                     },
                 );
             }
+            // Token balances and supplies come from account updates; the
+            // instruction is only recorded.
+            ParsedInstruction::Token { .. } => {
+                self.storage
+                    .store_instruction(
+                        instruction.clone(),
+                        position,
+                        slot,
+                        tx_signature,
+                        commitment,
+                    )
+                    .await?;
+            }
             _ => {}
         }
         Ok(())
@@ -238,7 +596,7 @@ mod tests {
         let owner = Pubkey::new_unique();
 
         processor
-            .process_account_update(pubkey, data, owner, 0)
+            .process_account_update(pubkey, data, owner, 0, None)
             .await
             .unwrap();
 
@@ -280,7 +638,7 @@ mod tests {
         let owner = Pubkey::new_unique();
 
         processor
-            .process_account_update(pubkey, data, owner, 0)
+            .process_account_update(pubkey, data, owner, 0, None)
             .await
             .unwrap();
 
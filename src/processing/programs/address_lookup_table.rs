@@ -0,0 +1,117 @@
+//! The Address Lookup Table program.
+
+use super::{account, read_bincode};
+use crate::processing::instruction_parser::InstructionParseError;
+use serde::Serialize;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AddressLookupTableInstruction {
+    CreateLookupTable {
+        lookup_table: Pubkey,
+        authority: Pubkey,
+        payer: Pubkey,
+        recent_slot: u64,
+        bump_seed: u8,
+    },
+    FreezeLookupTable {
+        lookup_table: Pubkey,
+        authority: Pubkey,
+    },
+    ExtendLookupTable {
+        lookup_table: Pubkey,
+        authority: Pubkey,
+        new_addresses: Vec<Pubkey>,
+    },
+    DeactivateLookupTable {
+        lookup_table: Pubkey,
+        authority: Pubkey,
+    },
+    CloseLookupTable {
+        lookup_table: Pubkey,
+        authority: Pubkey,
+        recipient: Pubkey,
+    },
+}
+
+impl AddressLookupTableInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AddressLookupTableInstruction::CreateLookupTable { .. } => "create_lookup_table",
+            AddressLookupTableInstruction::FreezeLookupTable { .. } => "freeze_lookup_table",
+            AddressLookupTableInstruction::ExtendLookupTable { .. } => "extend_lookup_table",
+            AddressLookupTableInstruction::DeactivateLookupTable { .. } => {
+                "deactivate_lookup_table"
+            }
+            AddressLookupTableInstruction::CloseLookupTable { .. } => "close_lookup_table",
+        }
+    }
+}
+
+pub fn parse_instruction(
+    data: &[u8],
+    accounts: &[AccountMeta],
+) -> Result<AddressLookupTableInstruction, InstructionParseError> {
+    let account = |index| account(accounts, index);
+    let mut data = data;
+    let tag: u32 = read_bincode(&mut data)?;
+    let instruction = match tag {
+        0 => AddressLookupTableInstruction::CreateLookupTable {
+            lookup_table: account(0)?,
+            authority: account(1)?,
+            payer: account(2)?,
+            recent_slot: read_bincode(&mut data)?,
+            bump_seed: read_bincode(&mut data)?,
+        },
+        1 => AddressLookupTableInstruction::FreezeLookupTable {
+            lookup_table: account(0)?,
+            authority: account(1)?,
+        },
+        2 => AddressLookupTableInstruction::ExtendLookupTable {
+            lookup_table: account(0)?,
+            authority: account(1)?,
+            new_addresses: read_bincode(&mut data)?,
+        },
+        3 => AddressLookupTableInstruction::DeactivateLookupTable {
+            lookup_table: account(0)?,
+            authority: account(1)?,
+        },
+        4 => AddressLookupTableInstruction::CloseLookupTable {
+            lookup_table: account(0)?,
+            authority: account(1)?,
+            recipient: account(2)?,
+        },
+        other => return Err(InstructionParseError::UnknownInstruction(other)),
+    };
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::address_lookup_table::instruction as lookup_table_instruction;
+
+    #[test]
+    fn test_extend_lookup_table() {
+        let lookup_table = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let new_addresses = vec![Pubkey::new_unique(), Pubkey::new_unique()];
+        let instruction = lookup_table_instruction::extend_lookup_table(
+            lookup_table,
+            authority,
+            None,
+            new_addresses.clone(),
+        );
+
+        assert_eq!(
+            parse_instruction(&instruction.data, &instruction.accounts).unwrap(),
+            AddressLookupTableInstruction::ExtendLookupTable {
+                lookup_table,
+                authority,
+                new_addresses,
+            }
+        );
+    }
+}
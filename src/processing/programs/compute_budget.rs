@@ -0,0 +1,188 @@
+//! The Compute Budget program, and the priority fee it sets for a
+//! transaction.
+
+use super::read;
+use crate::processing::instruction_parser::InstructionParseError;
+use crate::processing::resolved_transaction::ResolvedTransaction;
+use serde::Serialize;
+
+/// Compute units a top-level instruction may use when the transaction does
+/// not set a limit.
+const DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT: u32 = 200_000;
+const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+const MICRO_LAMPORTS_PER_LAMPORT: u128 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ComputeBudgetInstruction {
+    /// Disabled, but present in old blocks.
+    RequestUnits {
+        units: u32,
+        additional_fee: u32,
+    },
+    RequestHeapFrame {
+        bytes: u32,
+    },
+    SetComputeUnitLimit {
+        units: u32,
+    },
+    SetComputeUnitPrice {
+        micro_lamports: u64,
+    },
+    SetLoadedAccountsDataSizeLimit {
+        bytes: u32,
+    },
+}
+
+impl ComputeBudgetInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ComputeBudgetInstruction::RequestUnits { .. } => "request_units",
+            ComputeBudgetInstruction::RequestHeapFrame { .. } => "request_heap_frame",
+            ComputeBudgetInstruction::SetComputeUnitLimit { .. } => "set_compute_unit_limit",
+            ComputeBudgetInstruction::SetComputeUnitPrice { .. } => "set_compute_unit_price",
+            ComputeBudgetInstruction::SetLoadedAccountsDataSizeLimit { .. } => {
+                "set_loaded_accounts_data_size_limit"
+            }
+        }
+    }
+}
+
+pub fn parse_instruction(data: &[u8]) -> Result<ComputeBudgetInstruction, InstructionParseError> {
+    let mut data = data;
+    let tag: u8 = read(&mut data)?;
+    let instruction = match tag {
+        0 => ComputeBudgetInstruction::RequestUnits {
+            units: read(&mut data)?,
+            additional_fee: read(&mut data)?,
+        },
+        1 => ComputeBudgetInstruction::RequestHeapFrame {
+            bytes: read(&mut data)?,
+        },
+        2 => ComputeBudgetInstruction::SetComputeUnitLimit {
+            units: read(&mut data)?,
+        },
+        3 => ComputeBudgetInstruction::SetComputeUnitPrice {
+            micro_lamports: read(&mut data)?,
+        },
+        4 => ComputeBudgetInstruction::SetLoadedAccountsDataSizeLimit {
+            bytes: read(&mut data)?,
+        },
+        other => return Err(InstructionParseError::UnknownInstruction(other.into())),
+    };
+    Ok(instruction)
+}
+
+/// What a transaction paid, with the priority part of the fee worked out
+/// from its compute budget instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TransactionFee {
+    /// Total fee charged, from the transaction meta.
+    pub fee: u64,
+    pub compute_unit_limit: u32,
+    /// Micro-lamports per compute unit.
+    pub compute_unit_price: u64,
+    pub priority_fee: u64,
+}
+
+impl TransactionFee {
+    pub fn new(transaction: &ResolvedTransaction, fee: u64) -> Self {
+        let mut unit_limit = None;
+        let mut unit_price = 0;
+        let mut other_instructions = 0u32;
+        for instruction in &transaction.instructions {
+            let is_compute_budget = transaction
+                .program_id(instruction)
+                .is_some_and(|program_id| solana_sdk::compute_budget::check_id(&program_id));
+            if !is_compute_budget {
+                other_instructions += 1;
+                continue;
+            }
+            match parse_instruction(&instruction.data) {
+                Ok(ComputeBudgetInstruction::SetComputeUnitLimit { units }) => {
+                    unit_limit = Some(units)
+                }
+                Ok(ComputeBudgetInstruction::SetComputeUnitPrice { micro_lamports }) => {
+                    unit_price = micro_lamports
+                }
+                _ => {}
+            }
+        }
+
+        let compute_unit_limit = unit_limit
+            .unwrap_or(other_instructions.saturating_mul(DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT))
+            .min(MAX_COMPUTE_UNIT_LIMIT);
+        // Rounded up, as the runtime charges it.
+        let priority_fee =
+            (unit_price as u128 * compute_unit_limit as u128).div_ceil(MICRO_LAMPORTS_PER_LAMPORT);
+        Self {
+            fee,
+            compute_unit_limit,
+            compute_unit_price: unit_price,
+            priority_fee: priority_fee.min(u64::MAX as u128) as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::compute_budget::ComputeBudgetInstruction as SdkInstruction;
+    use solana_sdk::instruction::{CompiledInstruction, Instruction};
+    use solana_sdk::message::v0::LoadedAddresses;
+    use solana_sdk::message::MessageHeader;
+    use solana_sdk::pubkey::Pubkey;
+
+    fn transaction(compute_budget: Vec<Instruction>) -> ResolvedTransaction {
+        let mut instructions: Vec<_> = compute_budget
+            .into_iter()
+            .map(|instruction| CompiledInstruction::new_from_raw_parts(0, instruction.data, vec![]))
+            .collect();
+        instructions.push(CompiledInstruction::new_from_raw_parts(1, vec![], vec![]));
+        ResolvedTransaction {
+            signature: String::new(),
+            header: MessageHeader::default(),
+            static_keys: vec![solana_sdk::compute_budget::id(), Pubkey::new_unique()],
+            loaded: LoadedAddresses::default(),
+            instructions,
+            inner_instructions: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_priority_fee() {
+        let fee = TransactionFee::new(
+            &transaction(vec![
+                SdkInstruction::set_compute_unit_limit(300_000),
+                SdkInstruction::set_compute_unit_price(10_000),
+            ]),
+            8_000,
+        );
+        assert_eq!(
+            fee,
+            TransactionFee {
+                fee: 8_000,
+                compute_unit_limit: 300_000,
+                compute_unit_price: 10_000,
+                priority_fee: 3_000,
+            }
+        );
+    }
+
+    #[test]
+    fn test_default_compute_unit_limit() {
+        let fee = TransactionFee::new(
+            &transaction(vec![SdkInstruction::set_compute_unit_price(1)]),
+            5_001,
+        );
+        assert_eq!(
+            fee.compute_unit_limit,
+            DEFAULT_INSTRUCTION_COMPUTE_UNIT_LIMIT
+        );
+        // 0.2 lamports, rounded up.
+        assert_eq!(fee.priority_fee, 1);
+
+        let fee = TransactionFee::new(&transaction(vec![]), 5_000);
+        assert_eq!(fee.priority_fee, 0);
+    }
+}
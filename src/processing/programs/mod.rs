@@ -0,0 +1,69 @@
+//! Decoders for programs indexed alongside Cypher.
+
+use super::instruction_parser::InstructionParseError;
+use bincode::Options;
+use borsh::BorshDeserialize;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::packet::PACKET_DATA_SIZE;
+use solana_sdk::pubkey::Pubkey;
+use std::io;
+
+pub mod address_lookup_table;
+pub mod compute_budget;
+pub mod stake;
+pub mod system;
+pub mod token;
+pub mod vote;
+
+/// Lamports an instruction moves out of one account into another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LamportTransfer {
+    pub source: Pubkey,
+    pub destination: Pubkey,
+    pub lamports: u64,
+}
+
+/// Reads a `T` from the front of `data` and advances past it.
+fn read<T: BorshDeserialize>(data: &mut &[u8]) -> io::Result<T> {
+    T::deserialize(data)
+}
+
+/// Like `read`, for the native programs, which encode instructions with
+/// bincode. Reads are capped at a packet's size, as the runtime caps them,
+/// so a corrupt length cannot allocate more than any instruction holds.
+fn read_bincode<T: DeserializeOwned>(data: &mut &[u8]) -> io::Result<T> {
+    bincode::options()
+        .with_limit(PACKET_DATA_SIZE as u64)
+        .with_fixint_encoding()
+        .allow_trailing_bytes()
+        .deserialize_from(data)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+fn account(accounts: &[AccountMeta], index: usize) -> Result<Pubkey, InstructionParseError> {
+    accounts
+        .get(index)
+        .map(|account| account.pubkey)
+        .ok_or(InstructionParseError::InvalidAccountIndex(index))
+}
+
+/// Accounts from `start` on, e.g. a variable list of signers.
+fn accounts_from(accounts: &[AccountMeta], start: usize) -> Vec<Pubkey> {
+    accounts
+        .iter()
+        .skip(start)
+        .map(|account| account.pubkey)
+        .collect()
+}
+
+/// A pubkey stored as 32 bytes, all zero when unset.
+struct OptionalNonZeroPubkey(Option<Pubkey>);
+
+impl BorshDeserialize for OptionalNonZeroPubkey {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let pubkey = Pubkey::deserialize_reader(reader)?;
+        Ok(Self((pubkey != Pubkey::default()).then_some(pubkey)))
+    }
+}
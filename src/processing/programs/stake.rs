@@ -0,0 +1,330 @@
+//! The Stake program.
+
+use super::{account, read_bincode, LamportTransfer};
+use crate::processing::instruction_parser::InstructionParseError;
+use serde::{Deserialize, Serialize};
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Lockup {
+    pub unix_timestamp: i64,
+    pub epoch: u64,
+    pub custodian: Pubkey,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StakeInstruction {
+    /// `Initialize` and `InitializeChecked`, which sets no lockup.
+    Initialize {
+        stake_account: Pubkey,
+        staker: Pubkey,
+        withdrawer: Pubkey,
+        lockup: Option<Lockup>,
+    },
+    /// Every `Authorize*` instruction. For the `*WithSeed` ones `authority`
+    /// is the base the current authority was derived from.
+    Authorize {
+        stake_account: Pubkey,
+        authority: Pubkey,
+        new_authority: Pubkey,
+        authority_type: &'static str,
+    },
+    DelegateStake {
+        stake_account: Pubkey,
+        vote_account: Pubkey,
+        authority: Pubkey,
+    },
+    Split {
+        stake_account: Pubkey,
+        split_stake_account: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    Withdraw {
+        stake_account: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    Deactivate {
+        stake_account: Pubkey,
+        authority: Pubkey,
+    },
+    /// `SetLockup` and `SetLockupChecked`; unset fields are left unchanged.
+    SetLockup {
+        stake_account: Pubkey,
+        authority: Pubkey,
+        unix_timestamp: Option<i64>,
+        epoch: Option<u64>,
+        custodian: Option<Pubkey>,
+    },
+    Merge {
+        destination: Pubkey,
+        source: Pubkey,
+        authority: Pubkey,
+    },
+    GetMinimumDelegation,
+    DeactivateDelinquent {
+        stake_account: Pubkey,
+        vote_account: Pubkey,
+    },
+    Redelegate {
+        stake_account: Pubkey,
+        new_stake_account: Pubkey,
+        vote_account: Pubkey,
+        authority: Pubkey,
+    },
+    /// `MoveStake` and `MoveLamports`.
+    Move {
+        source: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+        /// Whether active stake moved, rather than inactive lamports.
+        stake: bool,
+    },
+}
+
+impl StakeInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StakeInstruction::Initialize { .. } => "initialize",
+            StakeInstruction::Authorize { .. } => "authorize",
+            StakeInstruction::DelegateStake { .. } => "delegate_stake",
+            StakeInstruction::Split { .. } => "split",
+            StakeInstruction::Withdraw { .. } => "withdraw",
+            StakeInstruction::Deactivate { .. } => "deactivate",
+            StakeInstruction::SetLockup { .. } => "set_lockup",
+            StakeInstruction::Merge { .. } => "merge",
+            StakeInstruction::GetMinimumDelegation => "get_minimum_delegation",
+            StakeInstruction::DeactivateDelinquent { .. } => "deactivate_delinquent",
+            StakeInstruction::Redelegate { .. } => "redelegate",
+            StakeInstruction::Move { stake: true, .. } => "move_stake",
+            StakeInstruction::Move { stake: false, .. } => "move_lamports",
+        }
+    }
+
+    pub fn lamport_transfer(&self) -> Option<LamportTransfer> {
+        match *self {
+            StakeInstruction::Withdraw {
+                stake_account: source,
+                destination,
+                lamports,
+                ..
+            }
+            | StakeInstruction::Split {
+                stake_account: source,
+                split_stake_account: destination,
+                lamports,
+                ..
+            }
+            | StakeInstruction::Move {
+                source,
+                destination,
+                lamports,
+                ..
+            } => Some(LamportTransfer {
+                source,
+                destination,
+                lamports,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_instruction(
+    data: &[u8],
+    accounts: &[AccountMeta],
+) -> Result<StakeInstruction, InstructionParseError> {
+    let account = |index| account(accounts, index);
+    let mut data = data;
+    let tag: u32 = read_bincode(&mut data)?;
+    let instruction = match tag {
+        0 => StakeInstruction::Initialize {
+            stake_account: account(0)?,
+            staker: read_bincode(&mut data)?,
+            withdrawer: read_bincode(&mut data)?,
+            lockup: Some(read_bincode(&mut data)?),
+        },
+        1 => StakeInstruction::Authorize {
+            stake_account: account(0)?,
+            authority: account(2)?,
+            new_authority: read_bincode(&mut data)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        2 => StakeInstruction::DelegateStake {
+            stake_account: account(0)?,
+            vote_account: account(1)?,
+            authority: account(5)?,
+        },
+        3 => StakeInstruction::Split {
+            stake_account: account(0)?,
+            split_stake_account: account(1)?,
+            authority: account(2)?,
+            lamports: read_bincode(&mut data)?,
+        },
+        4 => StakeInstruction::Withdraw {
+            stake_account: account(0)?,
+            destination: account(1)?,
+            authority: account(4)?,
+            lamports: read_bincode(&mut data)?,
+        },
+        5 => StakeInstruction::Deactivate {
+            stake_account: account(0)?,
+            authority: account(2)?,
+        },
+        6 => StakeInstruction::SetLockup {
+            stake_account: account(0)?,
+            authority: account(1)?,
+            unix_timestamp: read_bincode(&mut data)?,
+            epoch: read_bincode(&mut data)?,
+            custodian: read_bincode(&mut data)?,
+        },
+        7 => StakeInstruction::Merge {
+            destination: account(0)?,
+            source: account(1)?,
+            authority: account(4)?,
+        },
+        // The seed and owner the current authority was derived with follow,
+        // but `authority` is enough to identify it.
+        8 => StakeInstruction::Authorize {
+            stake_account: account(0)?,
+            authority: account(1)?,
+            new_authority: read_bincode(&mut data)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        9 => StakeInstruction::Initialize {
+            stake_account: account(0)?,
+            staker: account(2)?,
+            withdrawer: account(3)?,
+            lockup: None,
+        },
+        10 => StakeInstruction::Authorize {
+            stake_account: account(0)?,
+            authority: account(2)?,
+            new_authority: account(3)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        11 => StakeInstruction::Authorize {
+            stake_account: account(0)?,
+            authority: account(1)?,
+            new_authority: account(3)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        12 => StakeInstruction::SetLockup {
+            stake_account: account(0)?,
+            authority: account(1)?,
+            unix_timestamp: read_bincode(&mut data)?,
+            epoch: read_bincode(&mut data)?,
+            // The new custodian signs, so it is an account.
+            custodian: account(2).ok(),
+        },
+        13 => StakeInstruction::GetMinimumDelegation,
+        14 => StakeInstruction::DeactivateDelinquent {
+            stake_account: account(0)?,
+            vote_account: account(1)?,
+        },
+        15 => StakeInstruction::Redelegate {
+            stake_account: account(0)?,
+            new_stake_account: account(1)?,
+            vote_account: account(2)?,
+            authority: account(4)?,
+        },
+        16 | 17 => StakeInstruction::Move {
+            source: account(0)?,
+            destination: account(1)?,
+            authority: account(2)?,
+            lamports: read_bincode(&mut data)?,
+            stake: tag == 16,
+        },
+        other => return Err(InstructionParseError::UnknownInstruction(other)),
+    };
+    Ok(instruction)
+}
+
+fn authority_type(authority_type: u32) -> &'static str {
+    match authority_type {
+        0 => "staker",
+        1 => "withdrawer",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::stake::instruction as stake_instruction;
+    use solana_sdk::stake::state::{Authorized, StakeAuthorize};
+
+    #[test]
+    fn test_initialize_and_withdraw() {
+        let stake_account = Pubkey::new_unique();
+        let authorized = Authorized {
+            staker: Pubkey::new_unique(),
+            withdrawer: Pubkey::new_unique(),
+        };
+        let instruction = stake_instruction::initialize(
+            &stake_account,
+            &authorized,
+            &solana_sdk::stake::state::Lockup::default(),
+        );
+        assert_eq!(
+            parse_instruction(&instruction.data, &instruction.accounts).unwrap(),
+            StakeInstruction::Initialize {
+                stake_account,
+                staker: authorized.staker,
+                withdrawer: authorized.withdrawer,
+                lockup: Some(Lockup {
+                    unix_timestamp: 0,
+                    epoch: 0,
+                    custodian: Pubkey::default(),
+                }),
+            }
+        );
+
+        let destination = Pubkey::new_unique();
+        let instruction = stake_instruction::withdraw(
+            &stake_account,
+            &authorized.withdrawer,
+            &destination,
+            42,
+            None,
+        );
+        let parsed = parse_instruction(&instruction.data, &instruction.accounts).unwrap();
+        assert_eq!(
+            parsed.lamport_transfer(),
+            Some(LamportTransfer {
+                source: stake_account,
+                destination,
+                lamports: 42,
+            })
+        );
+    }
+
+    #[test]
+    fn test_authorize_checked() {
+        let stake_account = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let new_authority = Pubkey::new_unique();
+        let instruction = stake_instruction::authorize_checked(
+            &stake_account,
+            &authority,
+            &new_authority,
+            StakeAuthorize::Withdrawer,
+            None,
+        );
+
+        assert_eq!(
+            parse_instruction(&instruction.data, &instruction.accounts).unwrap(),
+            StakeInstruction::Authorize {
+                stake_account,
+                authority,
+                new_authority,
+                authority_type: "withdrawer",
+            }
+        );
+    }
+}
@@ -0,0 +1,303 @@
+//! The System program: account creation, SOL transfers and durable nonces.
+
+use super::{account, read_bincode, LamportTransfer};
+use crate::processing::instruction_parser::InstructionParseError;
+use serde::Serialize;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+
+/// How an account address was derived by `create_with_seed`, for the
+/// `*WithSeed` instructions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AddressSeed {
+    pub base: Pubkey,
+    pub seed: String,
+    pub owner: Pubkey,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SystemInstruction {
+    CreateAccount {
+        funder: Pubkey,
+        account: Pubkey,
+        lamports: u64,
+        space: u64,
+        owner: Pubkey,
+        seed: Option<AddressSeed>,
+    },
+    Assign {
+        account: Pubkey,
+        owner: Pubkey,
+        seed: Option<AddressSeed>,
+    },
+    Transfer {
+        source: Pubkey,
+        destination: Pubkey,
+        lamports: u64,
+        /// How `source` was derived, for `TransferWithSeed`.
+        seed: Option<AddressSeed>,
+    },
+    Allocate {
+        account: Pubkey,
+        space: u64,
+        seed: Option<AddressSeed>,
+    },
+    AdvanceNonceAccount {
+        nonce_account: Pubkey,
+        authority: Pubkey,
+    },
+    WithdrawNonceAccount {
+        nonce_account: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    InitializeNonceAccount {
+        nonce_account: Pubkey,
+        authority: Pubkey,
+    },
+    AuthorizeNonceAccount {
+        nonce_account: Pubkey,
+        authority: Pubkey,
+        new_authority: Pubkey,
+    },
+    UpgradeNonceAccount {
+        nonce_account: Pubkey,
+    },
+}
+
+impl SystemInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SystemInstruction::CreateAccount { .. } => "create_account",
+            SystemInstruction::Assign { .. } => "assign",
+            SystemInstruction::Transfer { .. } => "transfer",
+            SystemInstruction::Allocate { .. } => "allocate",
+            SystemInstruction::AdvanceNonceAccount { .. } => "advance_nonce_account",
+            SystemInstruction::WithdrawNonceAccount { .. } => "withdraw_nonce_account",
+            SystemInstruction::InitializeNonceAccount { .. } => "initialize_nonce_account",
+            SystemInstruction::AuthorizeNonceAccount { .. } => "authorize_nonce_account",
+            SystemInstruction::UpgradeNonceAccount { .. } => "upgrade_nonce_account",
+        }
+    }
+
+    pub fn lamport_transfer(&self) -> Option<LamportTransfer> {
+        match *self {
+            SystemInstruction::CreateAccount {
+                funder,
+                account,
+                lamports,
+                ..
+            } => Some(LamportTransfer {
+                source: funder,
+                destination: account,
+                lamports,
+            }),
+            SystemInstruction::Transfer {
+                source,
+                destination,
+                lamports,
+                ..
+            }
+            | SystemInstruction::WithdrawNonceAccount {
+                nonce_account: source,
+                destination,
+                lamports,
+                ..
+            } => Some(LamportTransfer {
+                source,
+                destination,
+                lamports,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_instruction(
+    data: &[u8],
+    accounts: &[AccountMeta],
+) -> Result<SystemInstruction, InstructionParseError> {
+    let account = |index| account(accounts, index);
+    let mut data = data;
+    let tag: u32 = read_bincode(&mut data)?;
+    let instruction = match tag {
+        0 => SystemInstruction::CreateAccount {
+            funder: account(0)?,
+            account: account(1)?,
+            lamports: read_bincode(&mut data)?,
+            space: read_bincode(&mut data)?,
+            owner: read_bincode(&mut data)?,
+            seed: None,
+        },
+        1 => SystemInstruction::Assign {
+            account: account(0)?,
+            owner: read_bincode(&mut data)?,
+            seed: None,
+        },
+        2 => SystemInstruction::Transfer {
+            source: account(0)?,
+            destination: account(1)?,
+            lamports: read_bincode(&mut data)?,
+            seed: None,
+        },
+        3 => {
+            let base = read_bincode(&mut data)?;
+            let seed = read_bincode(&mut data)?;
+            let lamports = read_bincode(&mut data)?;
+            let space = read_bincode(&mut data)?;
+            let owner = read_bincode(&mut data)?;
+            SystemInstruction::CreateAccount {
+                funder: account(0)?,
+                account: account(1)?,
+                lamports,
+                space,
+                owner,
+                seed: Some(AddressSeed { base, seed, owner }),
+            }
+        }
+        4 => SystemInstruction::AdvanceNonceAccount {
+            nonce_account: account(0)?,
+            authority: account(2)?,
+        },
+        5 => SystemInstruction::WithdrawNonceAccount {
+            nonce_account: account(0)?,
+            destination: account(1)?,
+            authority: account(4)?,
+            lamports: read_bincode(&mut data)?,
+        },
+        6 => SystemInstruction::InitializeNonceAccount {
+            nonce_account: account(0)?,
+            authority: read_bincode(&mut data)?,
+        },
+        7 => SystemInstruction::AuthorizeNonceAccount {
+            nonce_account: account(0)?,
+            authority: account(1)?,
+            new_authority: read_bincode(&mut data)?,
+        },
+        8 => SystemInstruction::Allocate {
+            account: account(0)?,
+            space: read_bincode(&mut data)?,
+            seed: None,
+        },
+        9 => {
+            let base = read_bincode(&mut data)?;
+            let seed = read_bincode(&mut data)?;
+            let space = read_bincode(&mut data)?;
+            let owner = read_bincode(&mut data)?;
+            SystemInstruction::Allocate {
+                account: account(0)?,
+                space,
+                seed: Some(AddressSeed { base, seed, owner }),
+            }
+        }
+        10 => {
+            let base = read_bincode(&mut data)?;
+            let seed = read_bincode(&mut data)?;
+            let owner = read_bincode(&mut data)?;
+            SystemInstruction::Assign {
+                account: account(0)?,
+                owner,
+                seed: Some(AddressSeed { base, seed, owner }),
+            }
+        }
+        11 => SystemInstruction::Transfer {
+            source: account(0)?,
+            destination: account(2)?,
+            lamports: read_bincode(&mut data)?,
+            seed: Some(AddressSeed {
+                base: account(1)?,
+                seed: read_bincode(&mut data)?,
+                owner: read_bincode(&mut data)?,
+            }),
+        },
+        12 => SystemInstruction::UpgradeNonceAccount {
+            nonce_account: account(0)?,
+        },
+        other => return Err(InstructionParseError::UnknownInstruction(other)),
+    };
+    Ok(instruction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::system_instruction;
+
+    #[test]
+    fn test_transfer_with_seed() {
+        let base = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let source = Pubkey::create_with_seed(&base, "vault", &owner).unwrap();
+        let destination = Pubkey::new_unique();
+        let instruction = system_instruction::transfer_with_seed(
+            &source,
+            &base,
+            "vault".to_string(),
+            &owner,
+            &destination,
+            500,
+        );
+
+        let parsed = parse_instruction(&instruction.data, &instruction.accounts).unwrap();
+        assert_eq!(
+            parsed,
+            SystemInstruction::Transfer {
+                source,
+                destination,
+                lamports: 500,
+                seed: Some(AddressSeed {
+                    base,
+                    seed: "vault".to_string(),
+                    owner,
+                }),
+            }
+        );
+        assert_eq!(
+            parsed.lamport_transfer(),
+            Some(LamportTransfer {
+                source,
+                destination,
+                lamports: 500,
+            })
+        );
+    }
+
+    #[test]
+    fn test_oversized_seed_is_rejected() {
+        let accounts = vec![
+            AccountMeta::new(Pubkey::new_unique(), true),
+            AccountMeta::new(Pubkey::new_unique(), false),
+        ];
+        // `CreateAccountWithSeed` whose seed claims to be u64::MAX bytes.
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(Pubkey::new_unique().as_ref());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(b"vault");
+
+        assert!(matches!(
+            parse_instruction(&data, &accounts),
+            Err(InstructionParseError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_create_account_moves_lamports() {
+        let funder = Pubkey::new_unique();
+        let account = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let instruction = system_instruction::create_account(&funder, &account, 1_000, 165, &owner);
+
+        let parsed = parse_instruction(&instruction.data, &instruction.accounts).unwrap();
+        assert_eq!(parsed.name(), "create_account");
+        assert_eq!(
+            parsed.lamport_transfer(),
+            Some(LamportTransfer {
+                source: funder,
+                destination: account,
+                lamports: 1_000,
+            })
+        );
+    }
+}
@@ -0,0 +1,979 @@
+//! SPL Token and Token-2022. Token-2022 keeps the SPL Token instruction and
+//! account layouts and adds extensions after them, so one decoder handles
+//! both programs.
+
+use super::{account, accounts_from, read, OptionalNonZeroPubkey};
+use crate::processing::instruction_parser::InstructionParseError;
+use borsh::BorshDeserialize;
+use serde::Serialize;
+use solana_sdk::hash::hash;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+use std::io;
+
+pub const TOKEN_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA");
+pub const TOKEN_2022_PROGRAM_ID: Pubkey =
+    solana_sdk::pubkey!("TokenzQdBNbLqP5VEhdkAS6EPFLC1PQnrvHm2q6DPEz");
+
+const MINT_LEN: usize = 82;
+const ACCOUNT_LEN: usize = 165;
+const MULTISIG_LEN: usize = 355;
+
+pub fn is_token_program(program_id: &Pubkey) -> bool {
+    *program_id == TOKEN_PROGRAM_ID || *program_id == TOKEN_2022_PROGRAM_ID
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenInstruction {
+    InitializeMint {
+        mint: Pubkey,
+        decimals: u8,
+        mint_authority: Pubkey,
+        freeze_authority: Option<Pubkey>,
+    },
+    InitializeAccount {
+        account: Pubkey,
+        mint: Pubkey,
+        owner: Pubkey,
+    },
+    InitializeMultisig {
+        multisig: Pubkey,
+        m: u8,
+        signers: Vec<Pubkey>,
+    },
+    /// `Transfer`, `TransferChecked` and Token-2022's `TransferCheckedWithFee`.
+    Transfer {
+        source: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        amount: u64,
+        mint: Option<Pubkey>,
+        decimals: Option<u8>,
+        fee: Option<u64>,
+    },
+    Approve {
+        source: Pubkey,
+        delegate: Pubkey,
+        owner: Pubkey,
+        amount: u64,
+        mint: Option<Pubkey>,
+        decimals: Option<u8>,
+    },
+    Revoke {
+        source: Pubkey,
+        owner: Pubkey,
+    },
+    SetAuthority {
+        account: Pubkey,
+        current_authority: Pubkey,
+        authority_type: &'static str,
+        new_authority: Option<Pubkey>,
+    },
+    MintTo {
+        mint: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        amount: u64,
+        decimals: Option<u8>,
+    },
+    Burn {
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
+        amount: u64,
+        decimals: Option<u8>,
+    },
+    CloseAccount {
+        account: Pubkey,
+        destination: Pubkey,
+        owner: Pubkey,
+    },
+    FreezeAccount {
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
+    },
+    ThawAccount {
+        account: Pubkey,
+        mint: Pubkey,
+        authority: Pubkey,
+    },
+    SyncNative {
+        account: Pubkey,
+    },
+    GetAccountDataSize {
+        mint: Pubkey,
+    },
+    InitializeImmutableOwner {
+        account: Pubkey,
+    },
+    AmountToUiAmount {
+        mint: Pubkey,
+        amount: u64,
+    },
+    UiAmountToAmount {
+        mint: Pubkey,
+        ui_amount: String,
+    },
+    InitializeMintCloseAuthority {
+        mint: Pubkey,
+        close_authority: Option<Pubkey>,
+    },
+    InitializeTransferFeeConfig {
+        mint: Pubkey,
+        transfer_fee_config_authority: Option<Pubkey>,
+        withdraw_withheld_authority: Option<Pubkey>,
+        transfer_fee_basis_points: u16,
+        maximum_fee: u64,
+    },
+    WithdrawWithheldTokensFromMint {
+        mint: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+    },
+    WithdrawWithheldTokensFromAccounts {
+        mint: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        sources: Vec<Pubkey>,
+    },
+    HarvestWithheldTokensToMint {
+        mint: Pubkey,
+        sources: Vec<Pubkey>,
+    },
+    SetTransferFee {
+        mint: Pubkey,
+        authority: Pubkey,
+        transfer_fee_basis_points: u16,
+        maximum_fee: u64,
+    },
+    Reallocate {
+        account: Pubkey,
+        payer: Pubkey,
+        owner: Pubkey,
+    },
+    CreateNativeMint {
+        payer: Pubkey,
+    },
+    InitializeNonTransferableMint {
+        mint: Pubkey,
+    },
+    InitializeInterestBearingMint {
+        mint: Pubkey,
+        rate_authority: Option<Pubkey>,
+        /// Basis points per year.
+        rate: i16,
+    },
+    UpdateInterestRate {
+        mint: Pubkey,
+        authority: Pubkey,
+        rate: i16,
+    },
+    InitializePermanentDelegate {
+        mint: Pubkey,
+        delegate: Pubkey,
+    },
+    WithdrawExcessLamports {
+        source: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+    },
+    /// Confidential transfers carry encrypted amounts and proofs; only the
+    /// instruction is decoded.
+    ConfidentialTransfer {
+        instruction: &'static str,
+    },
+    /// Other Token-2022 extension instructions, by extension and the
+    /// extension's own instruction number.
+    Extension {
+        extension: &'static str,
+        instruction: u8,
+    },
+    /// Token-2022's token-metadata and token-group interface instructions.
+    Interface {
+        interface: &'static str,
+        instruction: &'static str,
+    },
+}
+
+/// Interface instructions start with the first 8 bytes of the SHA-256 of
+/// a preimage instead of a one-byte tag.
+const INTERFACE_INSTRUCTIONS: [(&str, &str, &str); 9] = [
+    (
+        "token_metadata",
+        "initialize",
+        "spl_token_metadata_interface:initialize_account",
+    ),
+    (
+        "token_metadata",
+        "update_field",
+        "spl_token_metadata_interface:updating_field",
+    ),
+    (
+        "token_metadata",
+        "remove_key",
+        "spl_token_metadata_interface:remove_key_ix",
+    ),
+    (
+        "token_metadata",
+        "update_authority",
+        "spl_token_metadata_interface:update_the_authority",
+    ),
+    (
+        "token_metadata",
+        "emit",
+        "spl_token_metadata_interface:emitter",
+    ),
+    (
+        "token_group",
+        "initialize_group",
+        "spl_token_group_interface:initialize_token_group",
+    ),
+    (
+        "token_group",
+        "update_group_max_size",
+        "spl_token_group_interface:update_group_max_size",
+    ),
+    (
+        "token_group",
+        "update_group_authority",
+        "spl_token_group_interface:update_authority",
+    ),
+    (
+        "token_group",
+        "initialize_member",
+        "spl_token_group_interface:initialize_member",
+    ),
+];
+
+impl TokenInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenInstruction::InitializeMint { .. } => "initialize_mint",
+            TokenInstruction::InitializeAccount { .. } => "initialize_account",
+            TokenInstruction::InitializeMultisig { .. } => "initialize_multisig",
+            TokenInstruction::Transfer { .. } => "transfer",
+            TokenInstruction::Approve { .. } => "approve",
+            TokenInstruction::Revoke { .. } => "revoke",
+            TokenInstruction::SetAuthority { .. } => "set_authority",
+            TokenInstruction::MintTo { .. } => "mint_to",
+            TokenInstruction::Burn { .. } => "burn",
+            TokenInstruction::CloseAccount { .. } => "close_account",
+            TokenInstruction::FreezeAccount { .. } => "freeze_account",
+            TokenInstruction::ThawAccount { .. } => "thaw_account",
+            TokenInstruction::SyncNative { .. } => "sync_native",
+            TokenInstruction::GetAccountDataSize { .. } => "get_account_data_size",
+            TokenInstruction::InitializeImmutableOwner { .. } => "initialize_immutable_owner",
+            TokenInstruction::AmountToUiAmount { .. } => "amount_to_ui_amount",
+            TokenInstruction::UiAmountToAmount { .. } => "ui_amount_to_amount",
+            TokenInstruction::InitializeMintCloseAuthority { .. } => {
+                "initialize_mint_close_authority"
+            }
+            TokenInstruction::InitializeTransferFeeConfig { .. } => {
+                "initialize_transfer_fee_config"
+            }
+            TokenInstruction::WithdrawWithheldTokensFromMint { .. } => {
+                "withdraw_withheld_tokens_from_mint"
+            }
+            TokenInstruction::WithdrawWithheldTokensFromAccounts { .. } => {
+                "withdraw_withheld_tokens_from_accounts"
+            }
+            TokenInstruction::HarvestWithheldTokensToMint { .. } => {
+                "harvest_withheld_tokens_to_mint"
+            }
+            TokenInstruction::SetTransferFee { .. } => "set_transfer_fee",
+            TokenInstruction::Reallocate { .. } => "reallocate",
+            TokenInstruction::CreateNativeMint { .. } => "create_native_mint",
+            TokenInstruction::InitializeNonTransferableMint { .. } => {
+                "initialize_non_transferable_mint"
+            }
+            TokenInstruction::InitializeInterestBearingMint { .. } => {
+                "initialize_interest_bearing_mint"
+            }
+            TokenInstruction::UpdateInterestRate { .. } => "update_interest_rate",
+            TokenInstruction::InitializePermanentDelegate { .. } => "initialize_permanent_delegate",
+            TokenInstruction::WithdrawExcessLamports { .. } => "withdraw_excess_lamports",
+            TokenInstruction::ConfidentialTransfer { .. } => "confidential_transfer",
+            TokenInstruction::Extension { .. } => "extension",
+            TokenInstruction::Interface { .. } => "interface",
+        }
+    }
+}
+
+/// Decodes an instruction of either token program. Fields are read from
+/// `data` in the order they are written below, which is the wire order.
+/// Data that does not start with a known tag is tried as an interface
+/// instruction, as Token-2022 does.
+pub fn parse_instruction(
+    data: &[u8],
+    accounts: &[AccountMeta],
+) -> Result<TokenInstruction, InstructionParseError> {
+    let account = |index| account(accounts, index);
+    let input = data;
+    let mut data = data;
+    let tag: u8 = read(&mut data)?;
+    let instruction = match tag {
+        0 | 20 => TokenInstruction::InitializeMint {
+            mint: account(0)?,
+            decimals: read(&mut data)?,
+            mint_authority: read(&mut data)?,
+            freeze_authority: read(&mut data)?,
+        },
+        1 => TokenInstruction::InitializeAccount {
+            account: account(0)?,
+            mint: account(1)?,
+            owner: account(2)?,
+        },
+        16 | 18 => TokenInstruction::InitializeAccount {
+            account: account(0)?,
+            mint: account(1)?,
+            owner: read(&mut data)?,
+        },
+        2 | 19 => TokenInstruction::InitializeMultisig {
+            multisig: account(0)?,
+            m: read(&mut data)?,
+            // `InitializeMultisig` also takes the rent sysvar.
+            signers: accounts_from(accounts, if tag == 2 { 2 } else { 1 }),
+        },
+        3 => TokenInstruction::Transfer {
+            source: account(0)?,
+            destination: account(1)?,
+            authority: account(2)?,
+            amount: read(&mut data)?,
+            mint: None,
+            decimals: None,
+            fee: None,
+        },
+        12 => TokenInstruction::Transfer {
+            source: account(0)?,
+            mint: Some(account(1)?),
+            destination: account(2)?,
+            authority: account(3)?,
+            amount: read(&mut data)?,
+            decimals: Some(read(&mut data)?),
+            fee: None,
+        },
+        4 => TokenInstruction::Approve {
+            source: account(0)?,
+            delegate: account(1)?,
+            owner: account(2)?,
+            amount: read(&mut data)?,
+            mint: None,
+            decimals: None,
+        },
+        13 => TokenInstruction::Approve {
+            source: account(0)?,
+            mint: Some(account(1)?),
+            delegate: account(2)?,
+            owner: account(3)?,
+            amount: read(&mut data)?,
+            decimals: Some(read(&mut data)?),
+        },
+        5 => TokenInstruction::Revoke {
+            source: account(0)?,
+            owner: account(1)?,
+        },
+        6 => TokenInstruction::SetAuthority {
+            account: account(0)?,
+            current_authority: account(1)?,
+            authority_type: authority_type(read(&mut data)?),
+            new_authority: read(&mut data)?,
+        },
+        7 | 14 => TokenInstruction::MintTo {
+            mint: account(0)?,
+            destination: account(1)?,
+            authority: account(2)?,
+            amount: read(&mut data)?,
+            decimals: if tag == 14 {
+                Some(read(&mut data)?)
+            } else {
+                None
+            },
+        },
+        8 | 15 => TokenInstruction::Burn {
+            account: account(0)?,
+            mint: account(1)?,
+            authority: account(2)?,
+            amount: read(&mut data)?,
+            decimals: if tag == 15 {
+                Some(read(&mut data)?)
+            } else {
+                None
+            },
+        },
+        9 => TokenInstruction::CloseAccount {
+            account: account(0)?,
+            destination: account(1)?,
+            owner: account(2)?,
+        },
+        10 => TokenInstruction::FreezeAccount {
+            account: account(0)?,
+            mint: account(1)?,
+            authority: account(2)?,
+        },
+        11 => TokenInstruction::ThawAccount {
+            account: account(0)?,
+            mint: account(1)?,
+            authority: account(2)?,
+        },
+        17 => TokenInstruction::SyncNative {
+            account: account(0)?,
+        },
+        21 => TokenInstruction::GetAccountDataSize { mint: account(0)? },
+        22 => TokenInstruction::InitializeImmutableOwner {
+            account: account(0)?,
+        },
+        23 => TokenInstruction::AmountToUiAmount {
+            mint: account(0)?,
+            amount: read(&mut data)?,
+        },
+        24 => TokenInstruction::UiAmountToAmount {
+            mint: account(0)?,
+            ui_amount: String::from_utf8_lossy(data).into_owned(),
+        },
+        25 => TokenInstruction::InitializeMintCloseAuthority {
+            mint: account(0)?,
+            close_authority: read(&mut data)?,
+        },
+        26 => parse_transfer_fee_instruction(&mut data, accounts)?,
+        27 => TokenInstruction::ConfidentialTransfer {
+            instruction: confidential_transfer_instruction(read(&mut data)?),
+        },
+        29 => TokenInstruction::Reallocate {
+            account: account(0)?,
+            payer: account(1)?,
+            owner: account(3)?,
+        },
+        31 => TokenInstruction::CreateNativeMint { payer: account(0)? },
+        32 => TokenInstruction::InitializeNonTransferableMint { mint: account(0)? },
+        33 => match read::<u8>(&mut data)? {
+            0 => TokenInstruction::InitializeInterestBearingMint {
+                mint: account(0)?,
+                rate_authority: read::<OptionalNonZeroPubkey>(&mut data)?.0,
+                rate: read(&mut data)?,
+            },
+            1 => TokenInstruction::UpdateInterestRate {
+                mint: account(0)?,
+                authority: account(1)?,
+                rate: read(&mut data)?,
+            },
+            other => return Err(InstructionParseError::UnknownInstruction(other.into())),
+        },
+        35 => TokenInstruction::InitializePermanentDelegate {
+            mint: account(0)?,
+            delegate: read(&mut data)?,
+        },
+        38 => TokenInstruction::WithdrawExcessLamports {
+            source: account(0)?,
+            destination: account(1)?,
+            authority: account(2)?,
+        },
+        28 | 30 | 34 | 36 | 37 | 39..=44 => TokenInstruction::Extension {
+            extension: extension_instruction(tag),
+            instruction: read(&mut data)?,
+        },
+        other => {
+            return interface_instruction(input)
+                .ok_or(InstructionParseError::UnknownInstruction(other.into()))
+        }
+    };
+    Ok(instruction)
+}
+
+fn interface_instruction(data: &[u8]) -> Option<TokenInstruction> {
+    let discriminator = data.get(..8)?;
+    INTERFACE_INSTRUCTIONS
+        .iter()
+        .find(|(_, _, preimage)| hash(preimage.as_bytes()).as_ref()[..8] == *discriminator)
+        .map(|&(interface, instruction, _)| TokenInstruction::Interface {
+            interface,
+            instruction,
+        })
+}
+
+fn parse_transfer_fee_instruction(
+    data: &mut &[u8],
+    accounts: &[AccountMeta],
+) -> Result<TokenInstruction, InstructionParseError> {
+    let account = |index| account(accounts, index);
+    let instruction = match read::<u8>(data)? {
+        0 => TokenInstruction::InitializeTransferFeeConfig {
+            mint: account(0)?,
+            transfer_fee_config_authority: read(data)?,
+            withdraw_withheld_authority: read(data)?,
+            transfer_fee_basis_points: read(data)?,
+            maximum_fee: read(data)?,
+        },
+        1 => TokenInstruction::Transfer {
+            source: account(0)?,
+            mint: Some(account(1)?),
+            destination: account(2)?,
+            authority: account(3)?,
+            amount: read(data)?,
+            decimals: Some(read(data)?),
+            fee: Some(read(data)?),
+        },
+        2 => TokenInstruction::WithdrawWithheldTokensFromMint {
+            mint: account(0)?,
+            destination: account(1)?,
+            authority: account(2)?,
+        },
+        3 => {
+            // The source accounts come last, after any multisig signers.
+            let num_sources: u8 = read(data)?;
+            let start = accounts.len().saturating_sub(num_sources as usize).max(3);
+            TokenInstruction::WithdrawWithheldTokensFromAccounts {
+                mint: account(0)?,
+                destination: account(1)?,
+                authority: account(2)?,
+                sources: accounts_from(accounts, start),
+            }
+        }
+        4 => TokenInstruction::HarvestWithheldTokensToMint {
+            mint: account(0)?,
+            sources: accounts_from(accounts, 1),
+        },
+        5 => TokenInstruction::SetTransferFee {
+            mint: account(0)?,
+            authority: account(1)?,
+            transfer_fee_basis_points: read(data)?,
+            maximum_fee: read(data)?,
+        },
+        other => return Err(InstructionParseError::UnknownInstruction(other.into())),
+    };
+    Ok(instruction)
+}
+
+fn authority_type(authority_type: u8) -> &'static str {
+    match authority_type {
+        0 => "mint_tokens",
+        1 => "freeze_account",
+        2 => "account_owner",
+        3 => "close_account",
+        4 => "transfer_fee_config",
+        5 => "withheld_withdraw",
+        6 => "close_mint",
+        7 => "interest_rate",
+        8 => "permanent_delegate",
+        9 => "confidential_transfer_mint",
+        10 => "transfer_hook_program_id",
+        11 => "confidential_transfer_fee_config",
+        12 => "metadata_pointer",
+        13 => "group_pointer",
+        14 => "group_member_pointer",
+        _ => "unknown",
+    }
+}
+
+fn confidential_transfer_instruction(instruction: u8) -> &'static str {
+    match instruction {
+        0 => "initialize_mint",
+        1 => "update_mint",
+        2 => "configure_account",
+        3 => "approve_account",
+        4 => "empty_account",
+        5 => "deposit",
+        6 => "withdraw",
+        7 => "transfer",
+        8 => "apply_pending_balance",
+        9 => "enable_confidential_credits",
+        10 => "disable_confidential_credits",
+        11 => "enable_non_confidential_credits",
+        12 => "disable_non_confidential_credits",
+        13 => "transfer_with_split_proofs",
+        _ => "unknown",
+    }
+}
+
+fn extension_instruction(tag: u8) -> &'static str {
+    match tag {
+        28 => "default_account_state",
+        30 => "memo_transfer",
+        34 => "cpi_guard",
+        36 => "transfer_hook",
+        37 => "confidential_transfer_fee",
+        39 => "metadata_pointer",
+        40 => "group_pointer",
+        41 => "group_member_pointer",
+        42 => "confidential_mint_burn",
+        43 => "scaled_ui_amount",
+        44 => "pausable",
+        _ => "unknown",
+    }
+}
+
+/// A mint or token account owned by either token program.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum TokenProgramAccount {
+    Mint(TokenMint),
+    Account(TokenAccount),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenMint {
+    pub mint_authority: Option<Pubkey>,
+    pub supply: u64,
+    pub decimals: u8,
+    pub is_initialized: bool,
+    pub freeze_authority: Option<Pubkey>,
+    pub extensions: Vec<TokenExtension>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TokenAccount {
+    pub mint: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub delegate: Option<Pubkey>,
+    pub state: TokenAccountState,
+    /// Rent-exempt reserve of wrapped SOL accounts.
+    pub is_native: Option<u64>,
+    pub delegated_amount: u64,
+    pub close_authority: Option<Pubkey>,
+    pub extensions: Vec<TokenExtension>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenAccountState {
+    Uninitialized,
+    Initialized,
+    Frozen,
+}
+
+impl From<u8> for TokenAccountState {
+    fn from(state: u8) -> Self {
+        match state {
+            1 => TokenAccountState::Initialized,
+            2 => TokenAccountState::Frozen,
+            _ => TokenAccountState::Uninitialized,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, BorshDeserialize)]
+pub struct TransferFee {
+    pub epoch: u64,
+    pub maximum_fee: u64,
+    pub transfer_fee_basis_points: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TokenExtension {
+    TransferFeeConfig {
+        transfer_fee_config_authority: Option<Pubkey>,
+        withdraw_withheld_authority: Option<Pubkey>,
+        withheld_amount: u64,
+        older_transfer_fee: TransferFee,
+        newer_transfer_fee: TransferFee,
+    },
+    TransferFeeAmount {
+        withheld_amount: u64,
+    },
+    MintCloseAuthority {
+        close_authority: Option<Pubkey>,
+    },
+    DefaultAccountState {
+        state: TokenAccountState,
+    },
+    InterestBearingConfig {
+        rate_authority: Option<Pubkey>,
+        initialization_timestamp: i64,
+        pre_update_average_rate: i16,
+        last_update_timestamp: i64,
+        current_rate: i16,
+    },
+    PermanentDelegate {
+        delegate: Option<Pubkey>,
+    },
+    /// Extensions whose contents are not decoded.
+    Other {
+        name: &'static str,
+    },
+}
+
+/// `COption` as laid out in account state: a `u32` tag, then the value,
+/// which takes up space even when the tag is 0.
+struct COption<T>(Option<T>);
+
+impl<T: BorshDeserialize> BorshDeserialize for COption<T> {
+    fn deserialize_reader<R: io::Read>(reader: &mut R) -> io::Result<Self> {
+        let tag = u32::deserialize_reader(reader)?;
+        let value = T::deserialize_reader(reader)?;
+        Ok(Self((tag == 1).then_some(value)))
+    }
+}
+
+#[derive(BorshDeserialize)]
+struct MintLayout {
+    mint_authority: COption<Pubkey>,
+    supply: u64,
+    decimals: u8,
+    is_initialized: bool,
+    freeze_authority: COption<Pubkey>,
+}
+
+#[derive(BorshDeserialize)]
+struct AccountLayout {
+    mint: Pubkey,
+    owner: Pubkey,
+    amount: u64,
+    delegate: COption<Pubkey>,
+    state: u8,
+    is_native: COption<u64>,
+    delegated_amount: u64,
+    close_authority: COption<Pubkey>,
+}
+
+/// Decodes account data owned by a token program. Multisig and
+/// unrecognised accounts are `None`.
+pub fn parse_account(data: &[u8]) -> io::Result<Option<TokenProgramAccount>> {
+    // Token-2022 accounts with extensions are at least an account long, with
+    // the account type after the base state and the extensions after that.
+    // They are never exactly as long as a multisig.
+    let (is_mint, extensions) = match data.len() {
+        MINT_LEN => (true, Vec::new()),
+        ACCOUNT_LEN => (false, Vec::new()),
+        MULTISIG_LEN => return Ok(None),
+        len if len > ACCOUNT_LEN => match data[ACCOUNT_LEN] {
+            1 => (true, parse_extensions(&data[ACCOUNT_LEN + 1..])?),
+            2 => (false, parse_extensions(&data[ACCOUNT_LEN + 1..])?),
+            _ => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+
+    let mut data = data;
+    let account = if is_mint {
+        let mint: MintLayout = read(&mut data)?;
+        TokenProgramAccount::Mint(TokenMint {
+            mint_authority: mint.mint_authority.0,
+            supply: mint.supply,
+            decimals: mint.decimals,
+            is_initialized: mint.is_initialized,
+            freeze_authority: mint.freeze_authority.0,
+            extensions,
+        })
+    } else {
+        let account: AccountLayout = read(&mut data)?;
+        TokenProgramAccount::Account(TokenAccount {
+            mint: account.mint,
+            owner: account.owner,
+            amount: account.amount,
+            delegate: account.delegate.0,
+            state: account.state.into(),
+            is_native: account.is_native.0,
+            delegated_amount: account.delegated_amount,
+            close_authority: account.close_authority.0,
+            extensions,
+        })
+    };
+    Ok(Some(account))
+}
+
+/// Type-length-value entries: a `u16` type and `u16` length, then the value.
+fn parse_extensions(mut tlv: &[u8]) -> io::Result<Vec<TokenExtension>> {
+    let mut extensions = Vec::new();
+    while tlv.len() >= 4 {
+        let extension_type = u16::from_le_bytes([tlv[0], tlv[1]]);
+        let len = u16::from_le_bytes([tlv[2], tlv[3]]) as usize;
+        // An uninitialized entry marks the end; the rest is padding.
+        if extension_type == 0 {
+            break;
+        }
+        let value = tlv
+            .get(4..4 + len)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        extensions.push(parse_extension(extension_type, value)?);
+        tlv = &tlv[4 + len..];
+    }
+    Ok(extensions)
+}
+
+fn parse_extension(extension_type: u16, mut value: &[u8]) -> io::Result<TokenExtension> {
+    let value = &mut value;
+    let extension = match extension_type {
+        1 => TokenExtension::TransferFeeConfig {
+            transfer_fee_config_authority: read::<OptionalNonZeroPubkey>(value)?.0,
+            withdraw_withheld_authority: read::<OptionalNonZeroPubkey>(value)?.0,
+            withheld_amount: read(value)?,
+            older_transfer_fee: read(value)?,
+            newer_transfer_fee: read(value)?,
+        },
+        2 => TokenExtension::TransferFeeAmount {
+            withheld_amount: read(value)?,
+        },
+        3 => TokenExtension::MintCloseAuthority {
+            close_authority: read::<OptionalNonZeroPubkey>(value)?.0,
+        },
+        6 => TokenExtension::DefaultAccountState {
+            state: read::<u8>(value)?.into(),
+        },
+        10 => TokenExtension::InterestBearingConfig {
+            rate_authority: read::<OptionalNonZeroPubkey>(value)?.0,
+            initialization_timestamp: read(value)?,
+            pre_update_average_rate: read(value)?,
+            last_update_timestamp: read(value)?,
+            current_rate: read(value)?,
+        },
+        12 => TokenExtension::PermanentDelegate {
+            delegate: read::<OptionalNonZeroPubkey>(value)?.0,
+        },
+        other => TokenExtension::Other {
+            name: extension_name(other),
+        },
+    };
+    Ok(extension)
+}
+
+fn extension_name(extension_type: u16) -> &'static str {
+    match extension_type {
+        4 => "confidential_transfer_mint",
+        5 => "confidential_transfer_account",
+        7 => "immutable_owner",
+        8 => "memo_transfer",
+        9 => "non_transferable",
+        11 => "cpi_guard",
+        13 => "non_transferable_account",
+        14 => "transfer_hook",
+        15 => "transfer_hook_account",
+        16 => "confidential_transfer_fee_config",
+        17 => "confidential_transfer_fee_amount",
+        18 => "metadata_pointer",
+        19 => "token_metadata",
+        20 => "group_pointer",
+        21 => "token_group",
+        22 => "group_member_pointer",
+        23 => "token_group_member",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn accounts(count: usize) -> Vec<AccountMeta> {
+        (0..count)
+            .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+            .collect()
+    }
+
+    #[test]
+    fn test_transfer_checked_with_fee() {
+        let accounts = accounts(4);
+        let mut data = vec![26, 1];
+        data.extend_from_slice(&1_000u64.to_le_bytes());
+        data.push(6);
+        data.extend_from_slice(&25u64.to_le_bytes());
+
+        assert_eq!(
+            parse_instruction(&data, &accounts).unwrap(),
+            TokenInstruction::Transfer {
+                source: accounts[0].pubkey,
+                destination: accounts[2].pubkey,
+                authority: accounts[3].pubkey,
+                amount: 1_000,
+                mint: Some(accounts[1].pubkey),
+                decimals: Some(6),
+                fee: Some(25),
+            }
+        );
+        assert!(matches!(
+            parse_instruction(&[3, 1, 2], &accounts),
+            Err(InstructionParseError::DeserializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_interface_and_newer_instructions() {
+        let accounts = accounts(2);
+        let mut data = hash(b"spl_token_metadata_interface:updating_field").as_ref()[..8].to_vec();
+        data.extend_from_slice(&[0, 0, 0, 0]);
+        assert_eq!(
+            parse_instruction(&data, &accounts).unwrap(),
+            TokenInstruction::Interface {
+                interface: "token_metadata",
+                instruction: "update_field",
+            }
+        );
+
+        assert_eq!(
+            parse_instruction(&[44, 1], &accounts).unwrap(),
+            TokenInstruction::Extension {
+                extension: "pausable",
+                instruction: 1,
+            }
+        );
+        assert!(matches!(
+            parse_instruction(&[200, 0, 0, 0, 0, 0, 0, 0], &accounts),
+            Err(InstructionParseError::UnknownInstruction(200))
+        ));
+    }
+
+    #[test]
+    fn test_token_2022_mint_with_extensions() {
+        let authority = Pubkey::new_unique();
+        let mut data = vec![0u8; ACCOUNT_LEN];
+        data[..4].copy_from_slice(&1u32.to_le_bytes());
+        data[4..36].copy_from_slice(authority.as_ref());
+        data[36..44].copy_from_slice(&5_000u64.to_le_bytes());
+        data[44] = 9;
+        data[45] = 1;
+        data.push(1);
+        // Interest-bearing config with no rate authority and a 5% rate.
+        data.extend_from_slice(&10u16.to_le_bytes());
+        data.extend_from_slice(&52u16.to_le_bytes());
+        data.extend_from_slice(&[0u8; 32]);
+        data.extend_from_slice(&100i64.to_le_bytes());
+        data.extend_from_slice(&500i16.to_le_bytes());
+        data.extend_from_slice(&200i64.to_le_bytes());
+        data.extend_from_slice(&500i16.to_le_bytes());
+        data.extend_from_slice(&7u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+
+        let Some(TokenProgramAccount::Mint(mint)) = parse_account(&data).unwrap() else {
+            panic!("expected a mint");
+        };
+        assert_eq!(mint.mint_authority, Some(authority));
+        assert_eq!(mint.supply, 5_000);
+        assert_eq!(mint.decimals, 9);
+        assert_eq!(mint.freeze_authority, None);
+        assert_eq!(
+            mint.extensions,
+            vec![
+                TokenExtension::InterestBearingConfig {
+                    rate_authority: None,
+                    initialization_timestamp: 100,
+                    pre_update_average_rate: 500,
+                    last_update_timestamp: 200,
+                    current_rate: 500,
+                },
+                TokenExtension::Other {
+                    name: "immutable_owner"
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_legacy_token_account() {
+        let mut data = vec![0u8; ACCOUNT_LEN];
+        let mint = Pubkey::new_unique();
+        data[..32].copy_from_slice(mint.as_ref());
+        data[64..72].copy_from_slice(&42u64.to_le_bytes());
+        data[108] = 2;
+
+        let Some(TokenProgramAccount::Account(account)) = parse_account(&data).unwrap() else {
+            panic!("expected a token account");
+        };
+        assert_eq!(account.mint, mint);
+        assert_eq!(account.amount, 42);
+        assert_eq!(account.state, TokenAccountState::Frozen);
+        assert!(account.extensions.is_empty());
+        assert_eq!(parse_account(&[0u8; MULTISIG_LEN]).unwrap(), None);
+    }
+}
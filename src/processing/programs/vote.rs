@@ -0,0 +1,220 @@
+//! The Vote program.
+
+use super::{account, read_bincode, LamportTransfer};
+use crate::processing::instruction_parser::InstructionParseError;
+use serde::Serialize;
+use solana_sdk::instruction::AccountMeta;
+use solana_sdk::pubkey::Pubkey;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum VoteInstruction {
+    InitializeAccount {
+        vote_account: Pubkey,
+        node: Pubkey,
+        authorized_voter: Pubkey,
+        authorized_withdrawer: Pubkey,
+        commission: u8,
+    },
+    /// Every `Authorize*` instruction. For the `*WithSeed` ones `authority`
+    /// is the base the current authority was derived from.
+    Authorize {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        new_authority: Pubkey,
+        authority_type: &'static str,
+    },
+    /// A vote of any kind. The votes themselves are not decoded.
+    Vote {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        kind: &'static str,
+    },
+    Withdraw {
+        vote_account: Pubkey,
+        destination: Pubkey,
+        authority: Pubkey,
+        lamports: u64,
+    },
+    UpdateValidatorIdentity {
+        vote_account: Pubkey,
+        node: Pubkey,
+        authority: Pubkey,
+    },
+    UpdateCommission {
+        vote_account: Pubkey,
+        authority: Pubkey,
+        commission: u8,
+    },
+}
+
+impl VoteInstruction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VoteInstruction::InitializeAccount { .. } => "initialize_account",
+            VoteInstruction::Authorize { .. } => "authorize",
+            VoteInstruction::Vote { .. } => "vote",
+            VoteInstruction::Withdraw { .. } => "withdraw",
+            VoteInstruction::UpdateValidatorIdentity { .. } => "update_validator_identity",
+            VoteInstruction::UpdateCommission { .. } => "update_commission",
+        }
+    }
+
+    pub fn lamport_transfer(&self) -> Option<LamportTransfer> {
+        match *self {
+            VoteInstruction::Withdraw {
+                vote_account,
+                destination,
+                lamports,
+                ..
+            } => Some(LamportTransfer {
+                source: vote_account,
+                destination,
+                lamports,
+            }),
+            _ => None,
+        }
+    }
+}
+
+pub fn parse_instruction(
+    data: &[u8],
+    accounts: &[AccountMeta],
+) -> Result<VoteInstruction, InstructionParseError> {
+    let account = |index| account(accounts, index);
+    let mut data = data;
+    let tag: u32 = read_bincode(&mut data)?;
+    let instruction = match tag {
+        0 => VoteInstruction::InitializeAccount {
+            vote_account: account(0)?,
+            node: read_bincode(&mut data)?,
+            authorized_voter: read_bincode(&mut data)?,
+            authorized_withdrawer: read_bincode(&mut data)?,
+            commission: read_bincode(&mut data)?,
+        },
+        1 => VoteInstruction::Authorize {
+            vote_account: account(0)?,
+            authority: account(2)?,
+            new_authority: read_bincode(&mut data)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        // Legacy votes also pass the slot hashes and clock sysvars.
+        2 | 6 => VoteInstruction::Vote {
+            vote_account: account(0)?,
+            authority: account(3)?,
+            kind: vote_kind(tag),
+        },
+        8 | 9 | 12..=15 => VoteInstruction::Vote {
+            vote_account: account(0)?,
+            authority: account(1)?,
+            kind: vote_kind(tag),
+        },
+        3 => VoteInstruction::Withdraw {
+            vote_account: account(0)?,
+            destination: account(1)?,
+            authority: account(2)?,
+            lamports: read_bincode(&mut data)?,
+        },
+        4 => VoteInstruction::UpdateValidatorIdentity {
+            vote_account: account(0)?,
+            node: account(1)?,
+            authority: account(2)?,
+        },
+        5 => VoteInstruction::UpdateCommission {
+            vote_account: account(0)?,
+            authority: account(1)?,
+            commission: read_bincode(&mut data)?,
+        },
+        7 => VoteInstruction::Authorize {
+            vote_account: account(0)?,
+            authority: account(2)?,
+            new_authority: account(3)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        10 => {
+            let authority_type = authority_type(read_bincode(&mut data)?);
+            let _owner: Pubkey = read_bincode(&mut data)?;
+            let _seed: String = read_bincode(&mut data)?;
+            VoteInstruction::Authorize {
+                vote_account: account(0)?,
+                authority: account(2)?,
+                new_authority: read_bincode(&mut data)?,
+                authority_type,
+            }
+        }
+        11 => VoteInstruction::Authorize {
+            vote_account: account(0)?,
+            authority: account(2)?,
+            new_authority: account(3)?,
+            authority_type: authority_type(read_bincode(&mut data)?),
+        },
+        other => return Err(InstructionParseError::UnknownInstruction(other)),
+    };
+    Ok(instruction)
+}
+
+fn authority_type(authority_type: u32) -> &'static str {
+    match authority_type {
+        0 => "voter",
+        1 => "withdrawer",
+        _ => "unknown",
+    }
+}
+
+fn vote_kind(tag: u32) -> &'static str {
+    match tag {
+        2 => "vote",
+        6 => "vote_switch",
+        8 => "update_vote_state",
+        9 => "update_vote_state_switch",
+        12 => "compact_update_vote_state",
+        13 => "compact_update_vote_state_switch",
+        14 => "tower_sync",
+        15 => "tower_sync_switch",
+        _ => "unknown",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_withdraw() {
+        let accounts: Vec<AccountMeta> = (0..3)
+            .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+            .collect();
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&7_000u64.to_le_bytes());
+
+        let parsed = parse_instruction(&data, &accounts).unwrap();
+        assert_eq!(
+            parsed,
+            VoteInstruction::Withdraw {
+                vote_account: accounts[0].pubkey,
+                destination: accounts[1].pubkey,
+                authority: accounts[2].pubkey,
+                lamports: 7_000,
+            }
+        );
+        assert_eq!(parsed.lamport_transfer().unwrap().lamports, 7_000);
+    }
+
+    #[test]
+    fn test_tower_sync_is_a_vote() {
+        let accounts: Vec<AccountMeta> = (0..2)
+            .map(|_| AccountMeta::new(Pubkey::new_unique(), false))
+            .collect();
+        // The tower itself is not read.
+        let data = 14u32.to_le_bytes().to_vec();
+
+        assert_eq!(
+            parse_instruction(&data, &accounts).unwrap(),
+            VoteInstruction::Vote {
+                vote_account: accounts[0].pubkey,
+                authority: accounts[1].pubkey,
+                kind: "tower_sync",
+            }
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use super::lookup_tables::{LookupTableCache, LookupTableError};
+use solana_sdk::instruction::{AccountMeta, CompiledInstruction};
+use solana_sdk::message::v0::{LoadedAddresses, MessageAddressTableLookup};
+use solana_sdk::message::{AccountKeys, MessageHeader};
+use solana_sdk::pubkey::{ParsePubkeyError, Pubkey};
+use solana_transaction_status::option_serializer::OptionSerializer;
+use solana_transaction_status::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, InnerInstruction, InnerInstructions,
+    UiCompiledInstruction, UiInstruction, UiMessage, UiTransaction,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ResolveError {
+    #[error("Unsupported transaction encoding")]
+    UnsupportedEncoding,
+    #[error("Invalid pubkey: {0}")]
+    InvalidPubkey(#[from] ParsePubkeyError),
+    #[error("Invalid instruction data: {0}")]
+    InvalidInstructionData(#[from] bs58::decode::Error),
+    #[error("Transaction {0} uses lookup tables but its meta has no loaded addresses")]
+    MissingLoadedAddresses(String),
+    #[error("Lookup table error: {0}")]
+    LookupTable(#[from] LookupTableError),
+}
+
+/// A transaction's instructions with every account they can refer to,
+/// including the addresses v0 transactions load from lookup tables.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedTransaction {
+    pub signature: String,
+    pub header: MessageHeader,
+    pub static_keys: Vec<Pubkey>,
+    pub loaded: LoadedAddresses,
+    pub instructions: Vec<CompiledInstruction>,
+    /// Instructions invoked through CPI, grouped by the index of the
+    /// top-level instruction that caused them, in execution order.
+    pub inner_instructions: Vec<InnerInstructions>,
+}
+
+impl ResolvedTransaction {
+    /// Loaded addresses are taken from `loadedAddresses` in the meta when it
+    /// is there, and otherwise fetched through `lookup_tables`.
+    pub async fn resolve(
+        transaction: &EncodedTransactionWithStatusMeta,
+        lookup_tables: Option<&LookupTableCache>,
+    ) -> Result<Self, ResolveError> {
+        let (signature, header, static_keys, instructions, lookups) =
+            decode_message(&transaction.transaction)?;
+
+        let loaded = if lookups.is_empty() {
+            LoadedAddresses::default()
+        } else if let Some(loaded) = meta_loaded_addresses(transaction)? {
+            loaded
+        } else if let Some(lookup_tables) = lookup_tables {
+            lookup_tables.load(&lookups).await?
+        } else {
+            return Err(ResolveError::MissingLoadedAddresses(signature));
+        };
+
+        Ok(Self {
+            signature,
+            header,
+            static_keys,
+            loaded,
+            instructions,
+            inner_instructions: meta_inner_instructions(transaction)?,
+        })
+    }
+
+    /// Static keys, then loaded writable and read-only addresses: the order
+    /// instruction account indexes and meta balances use.
+    pub fn account_keys(&self) -> AccountKeys<'_> {
+        AccountKeys::new(&self.static_keys, Some(&self.loaded))
+    }
+
+    pub fn program_id(&self, instruction: &CompiledInstruction) -> Option<Pubkey> {
+        self.account_keys()
+            .get(instruction.program_id_index as usize)
+            .copied()
+    }
+
+    /// The instruction's accounts with the signer and writable flags the
+    /// message requested, or `None` if an index is out of range.
+    pub fn instruction_accounts(
+        &self,
+        instruction: &CompiledInstruction,
+    ) -> Option<Vec<AccountMeta>> {
+        let account_keys = self.account_keys();
+        instruction
+            .accounts
+            .iter()
+            .map(|&index| {
+                let index = index as usize;
+                Some(AccountMeta {
+                    pubkey: *account_keys.get(index)?,
+                    is_signer: self.is_signer(index),
+                    is_writable: self.is_writable(index),
+                })
+            })
+            .collect()
+    }
+
+    fn is_signer(&self, index: usize) -> bool {
+        index < self.header.num_required_signatures as usize
+    }
+
+    /// Signed keys come first, then unsigned static keys, each with their
+    /// read-only keys last, then the loaded writable and read-only addresses.
+    fn is_writable(&self, index: usize) -> bool {
+        let num_signed = self.header.num_required_signatures as usize;
+        let num_static = self.static_keys.len();
+        if index < num_signed {
+            index < num_signed.saturating_sub(self.header.num_readonly_signed_accounts as usize)
+        } else if index < num_static {
+            index < num_static.saturating_sub(self.header.num_readonly_unsigned_accounts as usize)
+        } else {
+            index - num_static < self.loaded.writable.len()
+        }
+    }
+}
+
+type DecodedMessage = (
+    String,
+    MessageHeader,
+    Vec<Pubkey>,
+    Vec<CompiledInstruction>,
+    Vec<MessageAddressTableLookup>,
+);
+
+fn decode_message(encoded: &EncodedTransaction) -> Result<DecodedMessage, ResolveError> {
+    if let EncodedTransaction::Json(UiTransaction {
+        signatures,
+        message: UiMessage::Raw(message),
+    }) = encoded
+    {
+        let static_keys = message
+            .account_keys
+            .iter()
+            .map(|key| key.parse())
+            .collect::<Result<_, _>>()?;
+        let instructions = message
+            .instructions
+            .iter()
+            .map(decode_instruction)
+            .collect::<Result<_, _>>()?;
+        let lookups = message
+            .address_table_lookups
+            .iter()
+            .flatten()
+            .map(|lookup| {
+                Ok(MessageAddressTableLookup {
+                    account_key: lookup.account_key.parse()?,
+                    writable_indexes: lookup.writable_indexes.clone(),
+                    readonly_indexes: lookup.readonly_indexes.clone(),
+                })
+            })
+            .collect::<Result<_, ResolveError>>()?;
+        return Ok((
+            signatures.first().cloned().unwrap_or_default(),
+            message.header,
+            static_keys,
+            instructions,
+            lookups,
+        ));
+    }
+
+    // Binary encodings; `jsonParsed` and `accounts` drop the raw instructions.
+    let transaction = encoded.decode().ok_or(ResolveError::UnsupportedEncoding)?;
+    let message = &transaction.message;
+    Ok((
+        transaction
+            .signatures
+            .first()
+            .map(ToString::to_string)
+            .unwrap_or_default(),
+        *message.header(),
+        message.static_account_keys().to_vec(),
+        message.instructions().to_vec(),
+        message
+            .address_table_lookups()
+            .map(<[_]>::to_vec)
+            .unwrap_or_default(),
+    ))
+}
+
+fn decode_instruction(
+    instruction: &UiCompiledInstruction,
+) -> Result<CompiledInstruction, ResolveError> {
+    Ok(CompiledInstruction {
+        program_id_index: instruction.program_id_index,
+        accounts: instruction.accounts.clone(),
+        data: bs58::decode(&instruction.data).into_vec()?,
+    })
+}
+
+/// Inner instructions in `jsonParsed` form have no account indexes and are
+/// left out.
+fn meta_inner_instructions(
+    transaction: &EncodedTransactionWithStatusMeta,
+) -> Result<Vec<InnerInstructions>, ResolveError> {
+    let Some(OptionSerializer::Some(inner)) = transaction
+        .meta
+        .as_ref()
+        .map(|meta| meta.inner_instructions.as_ref())
+    else {
+        return Ok(Vec::new());
+    };
+    inner
+        .iter()
+        .map(|inner| {
+            let instructions = inner
+                .instructions
+                .iter()
+                .filter_map(|instruction| match instruction {
+                    UiInstruction::Compiled(instruction) => Some(instruction),
+                    UiInstruction::Parsed(_) => None,
+                })
+                .map(|instruction| {
+                    Ok(InnerInstruction {
+                        instruction: decode_instruction(instruction)?,
+                        stack_height: instruction.stack_height,
+                    })
+                })
+                .collect::<Result<_, ResolveError>>()?;
+            Ok(InnerInstructions {
+                index: inner.index,
+                instructions,
+            })
+        })
+        .collect()
+}
+
+fn meta_loaded_addresses(
+    transaction: &EncodedTransactionWithStatusMeta,
+) -> Result<Option<LoadedAddresses>, ResolveError> {
+    let Some(OptionSerializer::Some(loaded)) = transaction
+        .meta
+        .as_ref()
+        .map(|meta| meta.loaded_addresses.as_ref())
+    else {
+        return Ok(None);
+    };
+    let parse = |keys: &[String]| {
+        keys.iter()
+            .map(|key| key.parse())
+            .collect::<Result<Vec<Pubkey>, _>>()
+    };
+    Ok(Some(LoadedAddresses {
+        writable: parse(&loaded.writable)?,
+        readonly: parse(&loaded.readonly)?,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::message::{v0, VersionedMessage};
+    use solana_sdk::signature::Signature;
+    use solana_sdk::transaction::VersionedTransaction;
+    use solana_transaction_status::{
+        TransactionStatusMeta, UiTransactionEncoding, VersionedTransactionWithStatusMeta,
+    };
+
+    fn v0_transaction(loaded: LoadedAddresses) -> EncodedTransactionWithStatusMeta {
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: vec![Pubkey::new_unique(), Pubkey::new_unique()],
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(
+                1,
+                vec![7, 8],
+                vec![0, 2, 3],
+            )],
+            address_table_lookups: vec![MessageAddressTableLookup {
+                account_key: Pubkey::new_unique(),
+                writable_indexes: vec![4],
+                readonly_indexes: vec![9],
+            }],
+        };
+        VersionedTransactionWithStatusMeta {
+            transaction: VersionedTransaction {
+                signatures: vec![Signature::default()],
+                message: VersionedMessage::V0(message),
+            },
+            meta: TransactionStatusMeta {
+                loaded_addresses: loaded,
+                ..TransactionStatusMeta::default()
+            },
+        }
+        .encode(UiTransactionEncoding::Json, Some(0), true)
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_accounts_from_meta_loaded_addresses() {
+        let loaded = LoadedAddresses {
+            writable: vec![Pubkey::new_unique()],
+            readonly: vec![Pubkey::new_unique()],
+        };
+        let resolved = ResolvedTransaction::resolve(&v0_transaction(loaded.clone()), None)
+            .await
+            .unwrap();
+
+        let keys = resolved.account_keys();
+        assert_eq!(keys.len(), 4);
+        assert_eq!(keys.get(2), Some(&loaded.writable[0]));
+        assert_eq!(keys.get(3), Some(&loaded.readonly[0]));
+
+        let instruction = &resolved.instructions[0];
+        assert_eq!(instruction.data, vec![7, 8]);
+        assert_eq!(
+            resolved.program_id(instruction),
+            Some(resolved.static_keys[1])
+        );
+        assert_eq!(
+            keys.get(instruction.accounts[2] as usize),
+            Some(&loaded.readonly[0])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_instruction_account_flags() {
+        let loaded = LoadedAddresses {
+            writable: vec![Pubkey::new_unique()],
+            readonly: vec![Pubkey::new_unique()],
+        };
+        let resolved = ResolvedTransaction::resolve(&v0_transaction(loaded.clone()), None)
+            .await
+            .unwrap();
+
+        let accounts = resolved
+            .instruction_accounts(&resolved.instructions[0])
+            .unwrap();
+        assert_eq!(
+            accounts,
+            vec![
+                AccountMeta::new(resolved.static_keys[0], true),
+                AccountMeta::new(loaded.writable[0], false),
+                AccountMeta::new_readonly(loaded.readonly[0], false),
+            ]
+        );
+
+        let out_of_range = CompiledInstruction::new_from_raw_parts(1, vec![], vec![4]);
+        assert_eq!(resolved.instruction_accounts(&out_of_range), None);
+    }
+
+    #[tokio::test]
+    async fn test_missing_loaded_addresses() {
+        let mut transaction = v0_transaction(LoadedAddresses::default());
+        transaction.meta.as_mut().unwrap().loaded_addresses = OptionSerializer::Skip;
+
+        assert!(matches!(
+            ResolvedTransaction::resolve(&transaction, None).await,
+            Err(ResolveError::MissingLoadedAddresses(_))
+        ));
+    }
+}
@@ -1,28 +1,98 @@
 use crate::processing::account_parser::ParsedAccount;
+use crate::processing::fork_tracker::MAX_UNROOTED_SLOTS;
 use dashmap::DashMap;
+use parking_lot::Mutex;
 use solana_sdk::pubkey::Pubkey;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+
+/// An account replaced in a slot, with its value and writer before the slot.
+struct Undo {
+    pubkey: Pubkey,
+    account: Option<ParsedAccount>,
+    writer: Option<u64>,
+}
 
 pub struct StateManager {
     accounts: DashMap<Pubkey, ParsedAccount>,
+    /// Slot of the last `update_account_at` for each account.
+    writers: DashMap<Pubkey, u64>,
+    undo: Mutex<BTreeMap<u64, Vec<Undo>>>,
 }
 
 impl StateManager {
     pub fn new() -> Self {
         Self {
             accounts: DashMap::new(),
+            writers: DashMap::new(),
+            undo: Mutex::new(BTreeMap::new()),
         }
     }
 
     pub fn update_account(&self, pubkey: Pubkey, account: ParsedAccount) {
+        let _undo = self.undo.lock();
+        self.writers.remove(&pubkey);
         self.accounts.insert(pubkey, account);
     }
 
+    /// Like `update_account`, but remembers the previous value so the change
+    /// can be undone if `slot` ends up on a dead fork.
+    pub fn update_account_at(&self, slot: u64, pubkey: Pubkey, account: ParsedAccount) {
+        let mut undo = self.undo.lock();
+        let previous = Undo {
+            pubkey,
+            account: self.accounts.insert(pubkey, account),
+            writer: self.writers.insert(pubkey, slot),
+        };
+        undo.entry(slot).or_default().push(previous);
+        let newest = undo.keys().next_back().copied().unwrap_or(slot);
+        while let Some(entry) = undo.first_entry() {
+            if *entry.key() + MAX_UNROOTED_SLOTS >= newest {
+                break;
+            }
+            entry.remove();
+        }
+    }
+
+    /// Restores every account changed in `slot` to its value before the
+    /// slot, unless a later write on another fork replaced it since.
+    pub fn rollback_slot(&self, slot: u64) {
+        let mut undo = self.undo.lock();
+        let Some(log) = undo.remove(&slot) else {
+            return;
+        };
+        for Undo {
+            pubkey,
+            account,
+            writer,
+        } in log.into_iter().rev()
+        {
+            if self.writers.get(&pubkey).map(|writer| *writer) != Some(slot) {
+                continue;
+            }
+            match writer {
+                Some(writer) => self.writers.insert(pubkey, writer),
+                None => self.writers.remove(&pubkey).map(|(_, writer)| writer),
+            };
+            match account {
+                Some(account) => self.accounts.insert(pubkey, account),
+                None => self.accounts.remove(&pubkey).map(|(_, account)| account),
+            };
+        }
+    }
+
+    /// Drops undo history for slots at or below `root`.
+    pub fn forget_through(&self, root: u64) {
+        let mut undo = self.undo.lock();
+        *undo = undo.split_off(&(root + 1));
+    }
+
     pub fn get_account(&self, pubkey: &Pubkey) -> Option<ParsedAccount> {
         self.accounts.get(pubkey).map(|a| a.value().clone())
     }
 
     pub fn remove_account(&self, pubkey: &Pubkey) {
+        let _undo = self.undo.lock();
+        self.writers.remove(pubkey);
         self.accounts.remove(pubkey);
     }
 
@@ -46,6 +116,12 @@ impl StateManager {
                 ParsedAccount::CypherMetadata { .. } if account_type == "CypherMetadata" => {
                     Some((*entry.key(), entry.value().clone()))
                 }
+                ParsedAccount::TokenMint { .. } if account_type == "TokenMint" => {
+                    Some((*entry.key(), entry.value().clone()))
+                }
+                ParsedAccount::TokenAccount { .. } if account_type == "TokenAccount" => {
+                    Some((*entry.key(), entry.value().clone()))
+                }
                 _ => None,
             })
             .collect()
@@ -109,4 +185,50 @@ mod tests {
         assert_eq!(all_accounts.len(), 1);
         assert!(all_accounts.contains_key(&pubkey2));
     }
+
+    #[test]
+    fn test_rollback_slot() {
+        let state_manager = StateManager::new();
+        let pubkey = Pubkey::new_unique();
+        let owner = Pubkey::new_unique();
+        let account = |data: u8| ParsedAccount::Unknown {
+            pubkey,
+            owner,
+            data: vec![data],
+        };
+        let created = Pubkey::new_unique();
+
+        state_manager.update_account_at(10, pubkey, account(1));
+        state_manager.update_account_at(11, pubkey, account(2));
+        state_manager.update_account_at(11, pubkey, account(3));
+        state_manager.update_account_at(11, created, account(4));
+
+        state_manager.rollback_slot(11);
+        assert_eq!(state_manager.get_account(&pubkey), Some(account(1)));
+        assert_eq!(state_manager.get_account(&created), None);
+
+        // Rooted slots can no longer be rolled back.
+        state_manager.forget_through(10);
+        state_manager.rollback_slot(10);
+        assert_eq!(state_manager.get_account(&pubkey), Some(account(1)));
+    }
+
+    #[test]
+    fn test_rollback_keeps_writes_from_other_forks() {
+        let state_manager = StateManager::new();
+        let pubkey = Pubkey::new_unique();
+        let account = |data: u8| ParsedAccount::Unknown {
+            pubkey,
+            owner: Pubkey::default(),
+            data: vec![data],
+        };
+
+        // 11 and 12 fork off 10; 12 writes after 11.
+        state_manager.update_account_at(10, pubkey, account(1));
+        state_manager.update_account_at(11, pubkey, account(2));
+        state_manager.update_account_at(12, pubkey, account(3));
+
+        state_manager.rollback_slot(11);
+        assert_eq!(state_manager.get_account(&pubkey), Some(account(3)));
+    }
 }
@@ -1,3 +1,4 @@
+use crate::processing::call_tree::InstructionPosition;
 use crate::processing::{ParsedAccount, ParsedEvent, ParsedInstruction};
 use crate::storage::{Account, StorageError, Transaction};
 use serde::{Deserialize, Serialize};
@@ -6,11 +7,24 @@ use solana_bigtable_connection::{
     CredentialType,
 };
 
-const ACCOUNT_TABLE: &str = "accounts";
-const INSTRUCTION_TABLE: &str = "instructions";
-const EVENT_TABLE: &str = "events";
+pub(super) const ACCOUNT_TABLE: &str = "accounts";
+pub(super) const INSTRUCTION_TABLE: &str = "instructions";
+pub(super) const EVENT_TABLE: &str = "events";
 const TRANSACTION_TABLE: &str = "transactions";
 
+pub(super) fn account_row_key(pubkey: &str, slot: u64) -> RowKey {
+    format!("{}-{}", pubkey, slot)
+}
+
+/// `path` is the instruction's `InstructionPosition::path`.
+pub(super) fn instruction_row_key(tx_signature: &str, slot: u64, path: &str) -> RowKey {
+    format!("{}-{}-{}", tx_signature, slot, path)
+}
+
+pub(super) fn event_row_key(tx_signature: &str, slot: u64) -> RowKey {
+    format!("{}-{}", tx_signature, slot)
+}
+
 pub struct BigtableStorage {
     connection: BigTableConnection,
 }
@@ -35,7 +49,7 @@ impl BigtableStorage {
         account: ParsedAccount,
         slot: u64,
     ) -> Result<(), StorageError> {
-        let row_key = format!("{}-{}", account.pubkey(), slot);
+        let row_key = account_row_key(&account.pubkey().to_string(), slot);
         let serialized =
             bincode::serialize(&account).map_err(|e| StorageError::Serialization(e.into()))?;
 
@@ -47,15 +61,38 @@ impl BigtableStorage {
         Ok(())
     }
 
+    pub async fn store_accounts(
+        &self,
+        accounts: Vec<(ParsedAccount, u64)>,
+    ) -> Result<(), StorageError> {
+        let cells = accounts
+            .into_iter()
+            .map(|(account, slot)| {
+                let row_key = account_row_key(&account.pubkey().to_string(), slot);
+                let serialized = bincode::serialize(&account)
+                    .map_err(|e| StorageError::Serialization(e.into()))?;
+                Ok((row_key.into(), serialized))
+            })
+            .collect::<Result<Vec<_>, StorageError>>()?;
+
+        self.connection
+            .put_bincode_cells_with_retry(ACCOUNT_TABLE, &cells, true)
+            .await
+            .map_err(|e| StorageError::Other(e.to_string()))?;
+
+        Ok(())
+    }
+
     pub async fn store_instruction(
         &self,
         instruction: ParsedInstruction,
+        position: &InstructionPosition,
         slot: u64,
         tx_signature: &str,
     ) -> Result<(), StorageError> {
-        let row_key = format!("{}-{}-{}", tx_signature, slot, instruction.program_id());
-        let serialized =
-            bincode::serialize(&instruction).map_err(|e| StorageError::Serialization(e.into()))?;
+        let row_key = instruction_row_key(tx_signature, slot, &position.path());
+        let serialized = bincode::serialize(&(position, &instruction))
+            .map_err(|e| StorageError::Serialization(e.into()))?;
 
         self.connection
             .put_bincode_cells_with_retry(INSTRUCTION_TABLE, &[(row_key.into(), serialized)], true)
@@ -71,7 +108,7 @@ impl BigtableStorage {
         slot: u64,
         tx_signature: &str,
     ) -> Result<(), StorageError> {
-        let row_key = format!("{}-{}", tx_signature, slot);
+        let row_key = event_row_key(tx_signature, slot);
         let serialized =
             bincode::serialize(&event).map_err(|e| StorageError::Serialization(e.into()))?;
 
@@ -83,6 +120,13 @@ impl BigtableStorage {
         Ok(())
     }
 
+    pub async fn delete_rows(&self, table: &str, row_keys: &[RowKey]) -> Result<(), StorageError> {
+        self.connection
+            .delete_rows_with_retry(table, row_keys)
+            .await
+            .map_err(|e| StorageError::Other(e.to_string()))
+    }
+
     pub async fn get_account(&self, pubkey: &str) -> Result<Option<Account>, StorageError> {
         let mut client = self.connection.client();
         let result = client
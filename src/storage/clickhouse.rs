@@ -1,7 +1,26 @@
+use crate::ingestion::snapshots::BlockSnapshot;
+use crate::processing::call_tree::{InstructionNode, InstructionPosition};
+use crate::processing::instruction_parser::{ASSOCIATED_CYPHER_PROGRAM_ID, CYPHER_PROGRAM_ID};
+use crate::processing::programs::compute_budget::TransactionFee;
 use crate::processing::{ParsedAccount, ParsedEvent, ParsedInstruction};
-use crate::storage::{Account, StorageError, Transaction};
+use crate::storage::{Account, Commitment, StorageError, Transaction};
 use clickhouse::{Client, Row};
 
+/// Per-slot tables written by the processor, whose rows are deleted when
+/// their slot is rolled back.
+const SLOT_TABLES: [&str; 5] = [
+    "accounts",
+    "instructions",
+    "events",
+    "lamport_transfers",
+    "transaction_fees",
+];
+
+/// Rows written at, or in a slot since raised to, at least the bound
+/// commitment (bound twice).
+const AT_LEAST: &str =
+    "(commitment >= ? OR slot IN (SELECT slot FROM slot_commitment WHERE commitment >= ?))";
+
 pub struct ClickhouseStorage {
     client: Client,
 }
@@ -19,62 +38,238 @@ impl ClickhouseStorage {
         &self,
         account: ParsedAccount,
         slot: u64,
+        commitment: Commitment,
     ) -> Result<(), StorageError> {
-        let query =
-            "INSERT INTO accounts (pubkey, owner, slot, account_type, data) VALUES (?, ?, ?, ?, ?)";
-        let mut rows = Vec::new();
-        match account {
-            ParsedAccount::Token {
+        self.store_accounts(vec![(account, slot)], commitment).await
+    }
+
+    /// Writes all accounts with a single `INSERT`, used for startup snapshots.
+    pub async fn store_accounts(
+        &self,
+        accounts: Vec<(ParsedAccount, u64)>,
+        commitment: Commitment,
+    ) -> Result<(), StorageError> {
+        let query = "INSERT INTO accounts (pubkey, owner, slot, account_type, data, commitment) VALUES (?, ?, ?, ?, ?, ?)";
+        let rows = accounts
+            .into_iter()
+            .map(|(account, slot)| Self::account_row(account, slot, commitment))
+            .collect::<Result<Vec<_>, _>>()?;
+        self.client.insert(query, rows).await?;
+        Ok(())
+    }
+
+    fn account_row(
+        account: ParsedAccount,
+        slot: u64,
+        commitment: Commitment,
+    ) -> Result<Row, StorageError> {
+        let row = match account {
+            ParsedAccount::TokenMint {
                 pubkey,
-                owner,
+                program_id,
                 data,
-            } => {
-                rows.push(Row::new(vec![
-                    pubkey.to_string().into(),
-                    owner.to_string().into(),
-                    slot.into(),
-                    "token".into(),
-                    serde_json::to_string(&data)?.into(),
-                ]));
-            }
+            } => Row::new(vec![
+                pubkey.to_string().into(),
+                program_id.to_string().into(),
+                slot.into(),
+                "token_mint".into(),
+                serde_json::to_string(&data)?.into(),
+                commitment.rank().into(),
+            ]),
+            ParsedAccount::TokenAccount {
+                pubkey,
+                program_id,
+                data,
+            } => Row::new(vec![
+                pubkey.to_string().into(),
+                program_id.to_string().into(),
+                slot.into(),
+                "token_account".into(),
+                serde_json::to_string(&data)?.into(),
+                commitment.rank().into(),
+            ]),
             ParsedAccount::Unknown {
                 pubkey,
                 owner,
                 data,
-            } => {
-                rows.push(Row::new(vec![
-                    pubkey.to_string().into(),
-                    owner.to_string().into(),
-                    slot.into(),
-                    "unknown".into(),
-                    serde_json::to_string(&data)?.into(),
-                ]));
-            }
-        }
-        self.client.insert(query, rows).await?;
-        Ok(())
+            } => Row::new(vec![
+                pubkey.to_string().into(),
+                owner.to_string().into(),
+                slot.into(),
+                "unknown".into(),
+                serde_json::to_string(&data)?.into(),
+                commitment.rank().into(),
+            ]),
+        };
+        Ok(row)
     }
 
     pub async fn store_instruction(
         &self,
         instruction: ParsedInstruction,
+        position: &InstructionPosition,
         slot: u64,
         tx_signature: &str,
+        commitment: Commitment,
     ) -> Result<(), StorageError> {
-        let query = "INSERT INTO instructions (slot, tx_signature, program_id, instruction_type, data) VALUES (?, ?, ?, ?, ?)";
-        let mut rows = Vec::new();
-        match instruction {
-            ParsedInstruction::Unknown { program_id, data } => {
-                rows.push(Row::new(vec![
-                    slot.into(),
-                    tx_signature.into(),
-                    program_id.to_string().into(),
-                    "unknown".into(),
-                    serde_json::to_string(&data)?.into(),
-                ]));
-            }
+        let query = "INSERT INTO instructions (slot, tx_signature, program_id, instruction_type, data, outer_index, inner_index, stack_height, parent_program_id, commitment) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)";
+        let lamport_transfer = instruction.lamport_transfer();
+        let (program_id, instruction_type, data) = match instruction {
+            ParsedInstruction::CypherInitialize { params, mint } => (
+                CYPHER_PROGRAM_ID,
+                "initialize",
+                serde_json::to_string(&serde_json::json!({
+                    "mint": mint,
+                    "params": params,
+                }))?,
+            ),
+            ParsedInstruction::CypherTransfer {
+                params,
+                source,
+                destination,
+                authority,
+            } => (
+                CYPHER_PROGRAM_ID,
+                "transfer",
+                serde_json::to_string(&serde_json::json!({
+                    "source": source,
+                    "destination": destination,
+                    "authority": authority,
+                    "params": params,
+                }))?,
+            ),
+            ParsedInstruction::CypherMint {
+                params,
+                mint,
+                destination,
+                authority,
+            } => (
+                CYPHER_PROGRAM_ID,
+                "mint",
+                serde_json::to_string(&serde_json::json!({
+                    "mint": mint,
+                    "destination": destination,
+                    "authority": authority,
+                    "params": params,
+                }))?,
+            ),
+            ParsedInstruction::CypherBurn {
+                params,
+                account,
+                mint,
+                authority,
+            } => (
+                CYPHER_PROGRAM_ID,
+                "burn",
+                serde_json::to_string(&serde_json::json!({
+                    "account": account,
+                    "mint": mint,
+                    "authority": authority,
+                    "params": params,
+                }))?,
+            ),
+            ParsedInstruction::CreateAssociatedCypherAccount {
+                funding_account,
+                associated_account,
+                wallet_account,
+                cypher_mint,
+            } => (
+                ASSOCIATED_CYPHER_PROGRAM_ID,
+                "create_associated_account",
+                serde_json::to_string(&serde_json::json!({
+                    "funding_account": funding_account,
+                    "associated_account": associated_account,
+                    "wallet_account": wallet_account,
+                    "cypher_mint": cypher_mint,
+                }))?,
+            ),
+            ParsedInstruction::Unknown {
+                program_id, data, ..
+            } => (program_id, "unknown", serde_json::to_string(&data)?),
+            ParsedInstruction::Token {
+                program_id,
+                instruction,
+            } => (
+                program_id,
+                instruction.name(),
+                serde_json::to_string(&instruction)?,
+            ),
+            ParsedInstruction::System(instruction) => (
+                solana_sdk::system_program::id(),
+                instruction.name(),
+                serde_json::to_string(&instruction)?,
+            ),
+            ParsedInstruction::Stake(instruction) => (
+                solana_sdk::stake::program::id(),
+                instruction.name(),
+                serde_json::to_string(&instruction)?,
+            ),
+            ParsedInstruction::Vote(instruction) => (
+                solana_sdk::vote::program::id(),
+                instruction.name(),
+                serde_json::to_string(&instruction)?,
+            ),
+            ParsedInstruction::AddressLookupTable(instruction) => (
+                solana_sdk::address_lookup_table::program::id(),
+                instruction.name(),
+                serde_json::to_string(&instruction)?,
+            ),
+            ParsedInstruction::ComputeBudget(instruction) => (
+                solana_sdk::compute_budget::id(),
+                instruction.name(),
+                serde_json::to_string(&instruction)?,
+            ),
+        };
+        let row = Row::new(vec![
+            slot.into(),
+            tx_signature.into(),
+            program_id.to_string().into(),
+            instruction_type.into(),
+            data.into(),
+            position.outer_index.into(),
+            position.inner_index.into(),
+            position.stack_height.into(),
+            position.parent_program.map(|p| p.to_string()).into(),
+            commitment.rank().into(),
+        ]);
+        self.client.insert(query, vec![row]).await?;
+
+        if let Some(transfer) = lamport_transfer {
+            let query = "INSERT INTO lamport_transfers (slot, tx_signature, outer_index, inner_index, program_id, source, destination, lamports, commitment) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)";
+            let row = Row::new(vec![
+                slot.into(),
+                tx_signature.into(),
+                position.outer_index.into(),
+                position.inner_index.into(),
+                program_id.to_string().into(),
+                transfer.source.to_string().into(),
+                transfer.destination.to_string().into(),
+                transfer.lamports.into(),
+                commitment.rank().into(),
+            ]);
+            self.client.insert(query, vec![row]).await?;
         }
-        self.client.insert(query, rows).await?;
+        Ok(())
+    }
+
+    pub async fn store_transaction_fee(
+        &self,
+        fee: &TransactionFee,
+        slot: u64,
+        tx_signature: &str,
+        commitment: Commitment,
+    ) -> Result<(), StorageError> {
+        let query = "INSERT INTO transaction_fees (slot, tx_signature, fee, compute_unit_limit, compute_unit_price, priority_fee, commitment) VALUES (?, ?, ?, ?, ?, ?, ?)";
+        let row = Row::new(vec![
+            slot.into(),
+            tx_signature.into(),
+            fee.fee.into(),
+            fee.compute_unit_limit.into(),
+            fee.compute_unit_price.into(),
+            fee.priority_fee.into(),
+            commitment.rank().into(),
+        ]);
+        self.client.insert(query, vec![row]).await?;
         Ok(())
     }
 
@@ -83,8 +278,9 @@ impl ClickhouseStorage {
         event: ParsedEvent,
         slot: u64,
         tx_signature: &str,
+        commitment: Commitment,
     ) -> Result<(), StorageError> {
-        let query = "INSERT INTO events (slot, tx_signature, event_type, data) VALUES (?, ?, ?, ?)";
+        let query = "INSERT INTO events (slot, tx_signature, event_type, data, commitment) VALUES (?, ?, ?, ?, ?)";
         let mut rows = Vec::new();
         match event {
             ParsedEvent::Json(json) => {
@@ -93,6 +289,7 @@ impl ClickhouseStorage {
                     tx_signature.into(),
                     "json".into(),
                     serde_json::to_string(&json)?.into(),
+                    commitment.rank().into(),
                 ]));
             }
             ParsedEvent::Plain(text) => {
@@ -101,6 +298,7 @@ impl ClickhouseStorage {
                     tx_signature.into(),
                     "plain".into(),
                     text.into(),
+                    commitment.rank().into(),
                 ]));
             }
         }
@@ -108,9 +306,92 @@ impl ClickhouseStorage {
         Ok(())
     }
 
-    pub async fn get_account(&self, pubkey: &str) -> Result<Option<Account>, StorageError> {
-        let query = "SELECT * FROM accounts WHERE pubkey = ? ORDER BY slot DESC LIMIT 1";
-        let mut cursor = self.client.query(query).bind(&(pubkey,)).execute().await?;
+    pub async fn record_skipped_slot(&self, slot: u64) -> Result<(), StorageError> {
+        let query = "INSERT INTO skipped_slots (slot) VALUES (?)";
+        self.client
+            .insert(query, vec![Row::new(vec![slot.into()])])
+            .await?;
+        Ok(())
+    }
+
+    pub async fn store_block_metadata(&self, block: &BlockSnapshot) -> Result<(), StorageError> {
+        let query = "INSERT INTO blocks (slot, blockhash, parent_slot, block_time, block_height, executed_transaction_count) VALUES (?, ?, ?, ?, ?, ?)";
+        self.client
+            .insert(
+                query,
+                vec![Row::new(vec![
+                    block.slot.into(),
+                    block.blockhash.clone().into(),
+                    block.parent_slot.into(),
+                    block.block_time.into(),
+                    block.block_height.into(),
+                    block.executed_transaction_count.into(),
+                ])],
+            )
+            .await?;
+        Ok(())
+    }
+
+    pub async fn store_slot_status(
+        &self,
+        slot: u64,
+        parent: Option<u64>,
+        status: &str,
+    ) -> Result<(), StorageError> {
+        let query = "INSERT INTO slot_statuses (slot, parent, status) VALUES (?, ?, ?)";
+        self.client
+            .insert(
+                query,
+                vec![Row::new(vec![slot.into(), parent.into(), status.into()])],
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// Appends `slots` reaching `commitment` to `slot_commitment`. Rows keep
+    /// the commitment they were written with; reads take the higher of the
+    /// two, so raising a slot never rewrites its rows.
+    pub async fn update_commitment(
+        &self,
+        slots: &[u64],
+        commitment: Commitment,
+    ) -> Result<(), StorageError> {
+        if slots.is_empty() {
+            return Ok(());
+        }
+        let query = "INSERT INTO slot_commitment (slot, commitment) VALUES (?, ?)";
+        let rows = slots
+            .iter()
+            .map(|&slot| Row::new(vec![slot.into(), commitment.rank().into()]))
+            .collect();
+        self.client.insert(query, rows).await?;
+        Ok(())
+    }
+
+    /// Deletes every row written in `slot` and records it as dead.
+    pub async fn delete_slot(&self, slot: u64) -> Result<(), StorageError> {
+        for table in SLOT_TABLES {
+            let query = format!("ALTER TABLE {} DELETE WHERE slot = ?", table);
+            self.client.query(&query).bind(&(slot,)).execute().await?;
+        }
+        self.store_slot_status(slot, None, "dead").await
+    }
+
+    pub async fn get_account(
+        &self,
+        pubkey: &str,
+        commitment: Commitment,
+    ) -> Result<Option<Account>, StorageError> {
+        let query = format!(
+            "SELECT * FROM accounts WHERE pubkey = ? AND {} ORDER BY slot DESC LIMIT 1",
+            AT_LEAST
+        );
+        let mut cursor = self
+            .client
+            .query(&query)
+            .bind(&(pubkey, commitment.rank(), commitment.rank()))
+            .execute()
+            .await?;
         if let Some(row) = cursor.next().await? {
             Ok(Some(serde_json::from_str(&row.get::<String, _>("data")?)?))
         } else {
@@ -121,12 +402,16 @@ impl ClickhouseStorage {
     pub async fn get_transaction(
         &self,
         signature: &str,
+        commitment: Commitment,
     ) -> Result<Option<Transaction>, StorageError> {
-        let query = "SELECT * FROM transactions WHERE signature = ?";
+        let query = format!(
+            "SELECT * FROM transactions WHERE signature = ? AND {}",
+            AT_LEAST
+        );
         let mut cursor = self
             .client
-            .query(query)
-            .bind(&(signature,))
+            .query(&query)
+            .bind(&(signature, commitment.rank(), commitment.rank()))
             .execute()
             .await?;
         if let Some(row) = cursor.next().await? {
@@ -136,16 +421,51 @@ impl ClickhouseStorage {
         }
     }
 
+    /// A transaction's instructions in execution order.
+    pub async fn get_instructions(
+        &self,
+        signature: &str,
+        commitment: Commitment,
+    ) -> Result<Vec<InstructionNode>, StorageError> {
+        let query = format!(
+            "SELECT program_id, instruction_type, data, outer_index, inner_index, stack_height FROM instructions WHERE tx_signature = ? AND {} ORDER BY outer_index, isNotNull(inner_index), inner_index",
+            AT_LEAST
+        );
+        let mut cursor = self
+            .client
+            .query(&query)
+            .bind(&(signature, commitment.rank(), commitment.rank()))
+            .execute()
+            .await?;
+        let mut instructions = Vec::new();
+        while let Some(row) = cursor.next().await? {
+            instructions.push(InstructionNode {
+                program_id: row.get::<String, _>("program_id")?,
+                instruction_type: row.get::<String, _>("instruction_type")?,
+                data: row.get::<String, _>("data")?,
+                outer_index: row.get::<u8, _>("outer_index")?,
+                inner_index: row.get::<Option<u8>, _>("inner_index")?,
+                stack_height: row.get::<u32, _>("stack_height")?,
+                children: Vec::new(),
+            });
+        }
+        Ok(instructions)
+    }
+
     pub async fn get_transactions_by_account(
         &self,
         pubkey: &str,
         limit: i32,
+        commitment: Commitment,
     ) -> Result<Vec<Transaction>, StorageError> {
-        let query = "SELECT * FROM transactions WHERE pubkey = ? ORDER BY slot DESC LIMIT ?";
+        let query = format!(
+            "SELECT * FROM transactions WHERE pubkey = ? AND {} ORDER BY slot DESC LIMIT ?",
+            AT_LEAST
+        );
         let mut cursor = self
             .client
-            .query(query)
-            .bind(&(pubkey, limit))
+            .query(&query)
+            .bind(&(pubkey, commitment.rank(), commitment.rank(), limit))
             .execute()
             .await?;
         let mut transactions = Vec::new();
@@ -155,3 +475,49 @@ impl ClickhouseStorage {
         Ok(transactions)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    #[ignore = "needs a ClickHouse server with the cypher_indexer schema"]
+    async fn test_confirmed_transaction_without_slot_commitment() {
+        let storage = ClickhouseStorage::new("http://localhost:8123")
+            .await
+            .unwrap();
+        let signature = solana_sdk::signature::Signature::new_unique().to_string();
+        let pubkey = solana_sdk::pubkey::Pubkey::new_unique().to_string();
+        // A slot no status update has raised, so `slot_commitment` has no
+        // entry for it.
+        let slot = u64::MAX - rand::random::<u32>() as u64;
+        let query = "INSERT INTO transactions (signature, pubkey, slot, data, commitment) VALUES (?, ?, ?, ?, ?)";
+        let row = Row::new(vec![
+            signature.clone().into(),
+            pubkey.clone().into(),
+            slot.into(),
+            serde_json::json!({ "signature": signature })
+                .to_string()
+                .into(),
+            Commitment::Confirmed.rank().into(),
+        ]);
+        storage.client.insert(query, vec![row]).await.unwrap();
+
+        for (commitment, found) in [
+            (Commitment::Processed, true),
+            (Commitment::Confirmed, true),
+            (Commitment::Finalized, false),
+        ] {
+            let transaction = storage
+                .get_transaction(&signature, commitment)
+                .await
+                .unwrap();
+            assert_eq!(transaction.is_some(), found, "{:?}", commitment);
+            let transactions = storage
+                .get_transactions_by_account(&pubkey, 10, commitment)
+                .await
+                .unwrap();
+            assert_eq!(transactions.len(), found as usize, "{:?}", commitment);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use super::bigtable::{
+    account_row_key, event_row_key, instruction_row_key, ACCOUNT_TABLE, EVENT_TABLE,
+    INSTRUCTION_TABLE,
+};
+use crate::processing::fork_tracker::MAX_UNROOTED_SLOTS;
+use std::collections::BTreeMap;
+
+/// Keys written in one slot, needed to undo it in stores that are not
+/// queried by slot.
+#[derive(Debug, Default, PartialEq)]
+pub(super) struct SlotWrites {
+    /// Bigtable row keys by table.
+    pub rows: BTreeMap<&'static str, Vec<String>>,
+    /// Redis cache entries that may hold data from the slot.
+    pub cache_keys: Vec<String>,
+}
+
+/// Writes made in slots that are not rooted yet, so a dead fork can be
+/// rolled back.
+#[derive(Default)]
+pub(super) struct WriteJournal {
+    slots: BTreeMap<u64, SlotWrites>,
+}
+
+impl WriteJournal {
+    pub fn account(&mut self, pubkey: &str, slot: u64) {
+        self.record(slot, |writes| {
+            writes
+                .rows
+                .entry(ACCOUNT_TABLE)
+                .or_default()
+                .push(account_row_key(pubkey, slot));
+            writes.cache_keys.push(format!("account:{}", pubkey));
+        });
+    }
+
+    pub fn instruction(&mut self, tx_signature: &str, slot: u64, path: &str) {
+        self.record(slot, |writes| {
+            writes
+                .rows
+                .entry(INSTRUCTION_TABLE)
+                .or_default()
+                .push(instruction_row_key(tx_signature, slot, path));
+            writes.cache_keys.push(format!("tx:{}", tx_signature));
+        });
+    }
+
+    pub fn event(&mut self, tx_signature: &str, slot: u64) {
+        self.record(slot, |writes| {
+            writes
+                .rows
+                .entry(EVENT_TABLE)
+                .or_default()
+                .push(event_row_key(tx_signature, slot));
+        });
+    }
+
+    pub fn take(&mut self, slot: u64) -> Option<SlotWrites> {
+        self.slots.remove(&slot)
+    }
+
+    pub fn forget_through(&mut self, root: u64) {
+        self.slots = self.slots.split_off(&(root + 1));
+    }
+
+    fn record(&mut self, slot: u64, write: impl FnOnce(&mut SlotWrites)) {
+        let newest = self
+            .slots
+            .keys()
+            .next_back()
+            .copied()
+            .unwrap_or(slot)
+            .max(slot);
+        if slot + MAX_UNROOTED_SLOTS < newest {
+            return;
+        }
+        write(self.slots.entry(slot).or_default());
+        while let Some(entry) = self.slots.first_entry() {
+            if *entry.key() + MAX_UNROOTED_SLOTS >= newest {
+                break;
+            }
+            entry.remove();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_by_slot() {
+        let mut journal = WriteJournal::default();
+        journal.account("acct", 10);
+        journal.instruction("sig", 11, "0.1");
+        journal.event("sig", 11);
+
+        let writes = journal.take(11).unwrap();
+        assert_eq!(
+            writes.rows[INSTRUCTION_TABLE],
+            vec!["sig-11-0.1".to_string()]
+        );
+        assert_eq!(writes.rows[EVENT_TABLE], vec!["sig-11".to_string()]);
+        assert_eq!(writes.cache_keys, vec!["tx:sig".to_string()]);
+
+        journal.forget_through(10);
+        assert_eq!(journal.take(10), None);
+
+        journal.account("acct", 5000);
+        journal.account("acct", 10);
+        // Slot 10 is too far behind the newest slot to be kept.
+        assert_eq!(journal.take(10), None);
+    }
+}
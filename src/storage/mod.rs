@@ -1,11 +1,19 @@
 mod bigtable;
 mod clickhouse;
 pub mod ipfs;
+mod journal;
 mod redis;
 
 use crate::config::Config;
+use crate::ingestion::snapshots::{BlockSnapshot, SlotStatus};
+use crate::processing::call_tree::{self, InstructionNode, InstructionPosition};
+use crate::processing::programs::compute_budget::TransactionFee;
 use crate::processing::{ParsedAccount, ParsedEvent, ParsedInstruction};
 use async_trait::async_trait;
+use journal::WriteJournal;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 pub use self::bigtable::BigtableStorage;
 pub use self::ipfs::IpfsStorage;
@@ -34,6 +42,42 @@ pub trait StorageBackend: Send + Sync {
     ) -> Result<Vec<Transaction>, StorageError>;
 }
 
+/// How settled a stored row is. Ordered, so `>=` means "at least as final".
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Commitment {
+    Processed,
+    Confirmed,
+    Finalized,
+}
+
+impl Commitment {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Commitment::Processed => "processed",
+            Commitment::Confirmed => "confirmed",
+            Commitment::Finalized => "finalized",
+        }
+    }
+
+    /// Stored in the `commitment` column so rows can be filtered with `>=`.
+    pub fn rank(&self) -> u8 {
+        *self as u8
+    }
+}
+
+impl From<SlotStatus> for Commitment {
+    fn from(status: SlotStatus) -> Self {
+        match status {
+            SlotStatus::Processed => Commitment::Processed,
+            SlotStatus::Confirmed => Commitment::Confirmed,
+            SlotStatus::Rooted => Commitment::Finalized,
+            // Never raised; a dead slot's rows are deleted instead.
+            SlotStatus::Dead => Commitment::Processed,
+        }
+    }
+}
+
 pub type Account = serde_json::Value;
 pub type Transaction = serde_json::Value;
 
@@ -58,6 +102,7 @@ pub struct Storage {
     clickhouse: clickhouse::ClickhouseStorage,
     redis: redis::RedisStorage,
     bigtable: BigtableStorage,
+    journal: Arc<Mutex<WriteJournal>>,
 }
 
 impl Storage {
@@ -70,6 +115,7 @@ impl Storage {
                 &config.bigtable_app_profile_id,
             )
             .await?,
+            journal: Arc::new(Mutex::new(WriteJournal::default())),
         })
     }
 
@@ -77,23 +123,51 @@ impl Storage {
         &self,
         account: ParsedAccount,
         slot: u64,
+        commitment: Commitment,
     ) -> Result<(), StorageError> {
-        self.clickhouse.store_account(account.clone(), slot).await?;
+        self.journal
+            .lock()
+            .account(&account.pubkey().to_string(), slot);
+        self.clickhouse
+            .store_account(account.clone(), slot, commitment)
+            .await?;
         self.bigtable.store_account(account, slot).await?;
         Ok(())
     }
 
+    /// Bulk writes come from rooted startup snapshots, so they are not
+    /// journaled for rollback.
+    pub async fn store_accounts(
+        &self,
+        accounts: Vec<(ParsedAccount, u64)>,
+        commitment: Commitment,
+    ) -> Result<(), StorageError> {
+        if accounts.is_empty() {
+            return Ok(());
+        }
+        self.clickhouse
+            .store_accounts(accounts.clone(), commitment)
+            .await?;
+        self.bigtable.store_accounts(accounts).await?;
+        Ok(())
+    }
+
     pub async fn store_instruction(
         &self,
         instruction: ParsedInstruction,
+        position: &InstructionPosition,
         slot: u64,
         tx_signature: &str,
+        commitment: Commitment,
     ) -> Result<(), StorageError> {
+        self.journal
+            .lock()
+            .instruction(tx_signature, slot, &position.path());
         self.clickhouse
-            .store_instruction(instruction.clone(), slot, tx_signature)
+            .store_instruction(instruction.clone(), position, slot, tx_signature, commitment)
             .await?;
         self.bigtable
-            .store_instruction(instruction, slot, tx_signature)
+            .store_instruction(instruction, position, slot, tx_signature)
             .await?;
         Ok(())
     }
@@ -103,15 +177,40 @@ impl Storage {
         event: ParsedEvent,
         slot: u64,
         tx_signature: &str,
+        commitment: Commitment,
     ) -> Result<(), StorageError> {
+        self.journal.lock().event(tx_signature, slot);
         self.clickhouse
-            .store_event(event.clone(), slot, tx_signature)
+            .store_event(event.clone(), slot, tx_signature, commitment)
             .await?;
         self.bigtable.store_event(event, slot, tx_signature).await?;
         Ok(())
     }
 
-    pub async fn get_account(&self, pubkey: &str) -> Result<Option<Account>, StorageError> {
+    /// Fees are only kept in ClickHouse, where fee analytics run. Rows are
+    /// removed with their slot on rollback.
+    pub async fn store_transaction_fee(
+        &self,
+        fee: &TransactionFee,
+        slot: u64,
+        tx_signature: &str,
+        commitment: Commitment,
+    ) -> Result<(), StorageError> {
+        self.clickhouse
+            .store_transaction_fee(fee, slot, tx_signature, commitment)
+            .await
+    }
+
+    /// Reads below `Processed` go through the cache; stricter reads are
+    /// answered from ClickHouse, the only store that tracks commitment.
+    pub async fn get_account(
+        &self,
+        pubkey: &str,
+        commitment: Commitment,
+    ) -> Result<Option<Account>, StorageError> {
+        if commitment > Commitment::Processed {
+            return self.clickhouse.get_account(pubkey, commitment).await;
+        }
         if let Some(account) = self.redis.get_account(pubkey).await? {
             return Ok(Some(account));
         }
@@ -125,7 +224,11 @@ impl Storage {
     pub async fn get_transaction(
         &self,
         signature: &str,
+        commitment: Commitment,
     ) -> Result<Option<Transaction>, StorageError> {
+        if commitment > Commitment::Processed {
+            return self.clickhouse.get_transaction(signature, commitment).await;
+        }
         if let Some(transaction) = self.redis.get_transaction(signature).await? {
             return Ok(Some(transaction));
         }
@@ -140,9 +243,88 @@ impl Storage {
         &self,
         pubkey: &str,
         limit: i32,
+        commitment: Commitment,
     ) -> Result<Vec<Transaction>, StorageError> {
+        if commitment > Commitment::Processed {
+            return self
+                .clickhouse
+                .get_transactions_by_account(pubkey, limit, commitment)
+                .await;
+        }
         self.bigtable
             .get_transactions_by_account(pubkey, limit)
             .await
     }
+
+    /// Every instruction of a transaction, top-level ones at the root and
+    /// inner instructions under their callers.
+    pub async fn get_instruction_tree(
+        &self,
+        signature: &str,
+        commitment: Commitment,
+    ) -> Result<Vec<InstructionNode>, StorageError> {
+        let instructions = self
+            .clickhouse
+            .get_instructions(signature, commitment)
+            .await?;
+        Ok(call_tree::assemble(instructions))
+    }
+
+    pub async fn record_skipped_slot(&self, slot: u64) -> Result<(), StorageError> {
+        self.clickhouse.record_skipped_slot(slot).await
+    }
+
+    pub async fn store_block_metadata(&self, block: &BlockSnapshot) -> Result<(), StorageError> {
+        self.clickhouse.store_block_metadata(block).await
+    }
+
+    pub async fn store_slot_status(
+        &self,
+        slot: u64,
+        parent: Option<u64>,
+        status: SlotStatus,
+    ) -> Result<(), StorageError> {
+        self.clickhouse
+            .store_slot_status(slot, parent, status.as_str())
+            .await
+    }
+
+    /// Raises rows in `slots` to `commitment`. Rows already at or above it
+    /// are unaffected.
+    pub async fn update_commitment(
+        &self,
+        slots: &[u64],
+        commitment: Commitment,
+    ) -> Result<(), StorageError> {
+        self.clickhouse.update_commitment(slots, commitment).await
+    }
+
+    /// Undoes every write made in `slot`, which was abandoned by the cluster:
+    /// ClickHouse rows are deleted and the slot is recorded as dead, and the
+    /// journaled Bigtable rows and Redis cache entries are removed.
+    pub async fn rollback_slot(&self, slot: u64) -> Result<(), StorageError> {
+        self.clickhouse.delete_slot(slot).await?;
+        let writes = self.journal.lock().take(slot);
+        if let Some(writes) = writes {
+            for (table, row_keys) in &writes.rows {
+                self.bigtable.delete_rows(table, row_keys).await?;
+            }
+            self.redis.delete(&writes.cache_keys).await?;
+        }
+        Ok(())
+    }
+
+    /// Slots at or below `root` can no longer be rolled back.
+    pub fn forget_through(&self, root: u64) {
+        self.journal.lock().forget_through(root);
+    }
+
+    /// Last slot fully processed by the ingestion source `name`.
+    pub async fn get_checkpoint(&self, name: &str) -> Result<Option<u64>, StorageError> {
+        self.redis.get_checkpoint(name).await
+    }
+
+    pub async fn set_checkpoint(&self, name: &str, slot: u64) -> Result<(), StorageError> {
+        self.redis.set_checkpoint(name, slot).await
+    }
 }
@@ -51,4 +51,25 @@ impl RedisStorage {
         .await?;
         Ok(())
     }
+
+    pub async fn delete(&self, keys: &[String]) -> Result<(), StorageError> {
+        if keys.is_empty() {
+            return Ok(());
+        }
+        let mut conn = self.client.get_async_connection().await?;
+        conn.del(keys).await?;
+        Ok(())
+    }
+
+    pub async fn get_checkpoint(&self, name: &str) -> Result<Option<u64>, StorageError> {
+        let mut conn = self.client.get_async_connection().await?;
+        let slot: Option<u64> = conn.get(format!("checkpoint:{}", name)).await?;
+        Ok(slot)
+    }
+
+    pub async fn set_checkpoint(&self, name: &str, slot: u64) -> Result<(), StorageError> {
+        let mut conn = self.client.get_async_connection().await?;
+        conn.set(format!("checkpoint:{}", name), slot).await?;
+        Ok(())
+    }
 }
@@ -59,7 +59,7 @@ pub fn is_system_program(program_id: &Pubkey) -> bool {
 }
 
 pub fn is_spl_token_program(program_id: &Pubkey) -> bool {
-    program_id == &spl_token::id()
+    program_id == &crate::processing::programs::token::TOKEN_PROGRAM_ID
 }
 
 pub fn extract_program_id(instruction_data: &[u8]) -> Option<Pubkey> {